@@ -31,13 +31,8 @@ impl XmlComponentTrait for ImgComponent {
             .unwrap_or_default();
         let data_format = RawImageFormat::RGB8;
 
-        let image = RawImage {
-            width: image_info.width,
-            height: image_info.height,
-            data_format,
-            pixels: RawImageData::empty(data_format),
-            tag: im_info,
-        };
+        let mut image = RawImage::empty(image_info.width, image_info.height, data_format);
+        image.tag = im_info;
 
         let im = Dom::image(image.to_internal()).style(CssApiWrapper::empty());
 
@@ -66,7 +61,7 @@ fn main() -> Result<(), String> {
 
         let mut doc = PdfDocument::new("HTML rendering demo");
         let pages = doc.html2pages(h, config)?;
-        let doc = doc.with_pages(pages).save(&PdfSaveOptions::default());
+        let doc = doc.with_pages(pages).save(&PdfSaveOptions::default())?;
         std::fs::write(format!("html{i}.pdf"), doc).unwrap();
     }
 
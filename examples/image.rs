@@ -14,6 +14,9 @@ fn main() {
     }];
 
     let page1 = PdfPage::new(Mm(210.0), Mm(297.0), page1_contents);
-    let pdf_bytes: Vec<u8> = doc.with_pages(vec![page1]).save(&PdfSaveOptions::default());
+    let pdf_bytes: Vec<u8> = doc
+        .with_pages(vec![page1])
+        .save(&PdfSaveOptions::default())
+        .unwrap();
     let _ = std::fs::write("image.pdf", pdf_bytes);
 }
@@ -171,6 +171,9 @@ fn main() {
         // PdfPage::new(Mm(400.0), Mm(400.0), ops)
     ];
 
-    let bytes = doc.with_pages(pages).save(&PdfSaveOptions::default());
+    let bytes = doc
+        .with_pages(pages)
+        .save(&PdfSaveOptions::default())
+        .unwrap();
     std::fs::write("./simple.pdf", bytes).unwrap();
 }
@@ -0,0 +1,103 @@
+//! Bidirectional text reordering (Unicode Bidirectional Algorithm, UAX #9) with the
+//! `unicode-bidi` crate: splits a line of text into runs of the same direction
+
+use std::ops::Range;
+use unicode_bidi::{BidiInfo, Level};
+
+/// Returns the mirrored character (i.e. `)` for `(`) that has to be displayed
+/// for characters at right-to-left levels
+pub(crate) fn get_mirrored_char(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        '≤' => '≥',
+        '≥' => '≤',
+        c => c,
+    }
+}
+
+/// Run of text at the same embedding level, odd levels are right-to-left
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BidiRun {
+    /// Byte range of the run in the text
+    pub range: Range<usize>,
+    pub level: u8,
+}
+
+impl BidiRun {
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+/// Splits one line of text into runs of the same embedding level, in visual order.
+/// `base_level` is the paragraph level, detected from the first strong character
+/// if `None`. Paragraph separators in the text start a new paragraph.
+pub(crate) fn get_visual_runs(text: &str, base_level: Option<u8>) -> Vec<BidiRun> {
+    let base_level = base_level.and_then(|l| Level::new(l).ok());
+    let info = BidiInfo::new(text, base_level);
+
+    let mut runs = Vec::new();
+    for paragraph in info.paragraphs.iter() {
+        let (levels, level_runs) = info.visual_runs(paragraph, paragraph.range.clone());
+        runs.extend(level_runs.into_iter().map(|range| BidiRun {
+            level: levels[range.start].number(),
+            range,
+        }));
+    }
+    runs
+}
+
+#[test]
+fn test_bidi_runs() {
+    let get_parts = |text: &str, runs: &[BidiRun]| {
+        runs.iter()
+            .map(|r| text[r.range.clone()].to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let text = "abc אבג def";
+    let runs = get_visual_runs(text, None);
+    assert_eq!(get_parts(text, &runs), vec!["abc ", "אבג", " def"]);
+    assert_eq!(runs[1].level, 1);
+
+    // numbers keep their left-to-right order inside right-to-left text
+    let text = "אבג 123.5 דה";
+    let runs = get_visual_runs(text, None);
+    assert_eq!(get_parts(text, &runs), vec![" דה", "123.5", "אבג "]);
+    assert_eq!(runs[1].level, 2);
+
+    // Arabic digits after Arabic letters, trailing whitespace at paragraph level
+    let text = "مرحبا 12 ";
+    let runs = get_visual_runs(text, None);
+    assert_eq!(get_parts(text, &runs), vec![" ", "12", "مرحبا "]);
+    assert_eq!(runs.iter().map(|r| r.level).collect::<Vec<_>>(), [1, 2, 1]);
+
+    // the right-to-left mark is a strong right-to-left character
+    let text = "\u{200F}abc";
+    let runs = get_visual_runs(text, None);
+    assert_eq!(get_parts(text, &runs), vec!["abc", "\u{200F}"]);
+    assert_eq!(runs[0].level, 2);
+
+    // right-to-left isolate in left-to-right text
+    let text = "abc \u{2067}אבג 12\u{2069} def";
+    let runs = get_visual_runs(text, None);
+    assert_eq!(
+        get_parts(text, &runs),
+        vec!["abc \u{2067}", "12", "אבג ", "\u{2069} def"]
+    );
+    assert_eq!(
+        runs.iter().map(|r| r.level).collect::<Vec<_>>(),
+        [0, 2, 1, 0]
+    );
+}
@@ -0,0 +1,543 @@
+//! Boolean operations (union, intersection, difference, xor) on polygons
+
+use crate::{
+    graphics::{PaintMode, Point, Polygon, WindingOrder},
+    units::Pt,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Default maximum distance between a bezier curve and its flattened line segments
+pub const DEFAULT_FLATTEN_TOLERANCE: Pt = Pt(0.05);
+
+/// Grid size to which intersection points are snapped, so that the same
+/// point computed from different edges ends up as the same vertex
+const SNAP_GRID: f64 = 1e-6;
+
+/// Boolean operation on two polygons
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BooleanOp {
+    /// Area covered by either polygon
+    Union,
+    /// Area covered by both polygons
+    Intersection,
+    /// Area covered by the first, but not by the second polygon
+    Difference,
+    /// Area covered by exactly one of the polygons
+    Xor,
+}
+
+impl BooleanOp {
+    fn apply(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            BooleanOp::Union => in_a || in_b,
+            BooleanOp::Intersection => in_a && in_b,
+            BooleanOp::Difference => in_a && !in_b,
+            BooleanOp::Xor => in_a != in_b,
+        }
+    }
+}
+
+impl Polygon {
+    /// Returns the area covered by either polygon
+    pub fn union(&self, other: &Polygon) -> Polygon {
+        self.boolean_op(other, BooleanOp::Union, DEFAULT_FLATTEN_TOLERANCE)
+    }
+
+    /// Returns the area covered by both polygons
+    pub fn intersection(&self, other: &Polygon) -> Polygon {
+        self.boolean_op(other, BooleanOp::Intersection, DEFAULT_FLATTEN_TOLERANCE)
+    }
+
+    /// Returns the area covered by `self`, but not by `other` (i.e. for knockouts)
+    pub fn difference(&self, other: &Polygon) -> Polygon {
+        self.boolean_op(other, BooleanOp::Difference, DEFAULT_FLATTEN_TOLERANCE)
+    }
+
+    /// Returns the area covered by exactly one of the polygons
+    pub fn xor(&self, other: &Polygon) -> Polygon {
+        self.boolean_op(other, BooleanOp::Xor, DEFAULT_FLATTEN_TOLERANCE)
+    }
+
+    /// Resolves self-intersections and overlapping rings according to the winding order
+    pub fn simplify(&self) -> Polygon {
+        self.boolean_op(
+            &Polygon::default(),
+            BooleanOp::Union,
+            DEFAULT_FLATTEN_TOLERANCE,
+        )
+    }
+
+    /// Computes a boolean operation between two polygons. The insides of both polygons
+    /// are determined by their `winding_order`, bezier curves are flattened into line
+    /// segments with the given `tolerance`.
+    ///
+    /// The result only consists of straight line segments: outer rings are
+    /// counter-clockwise, holes are clockwise, so the result can be painted with
+    /// either winding order. The paint mode of `self` is kept.
+    pub fn boolean_op(&self, other: &Polygon, op: BooleanOp, tolerance: Pt) -> Polygon {
+        let a = flatten_rings(&self.rings, tolerance.0 as f64);
+        let b = flatten_rings(&other.rings, tolerance.0 as f64);
+
+        let mut graph = EdgeGraph::default();
+        let edges = ring_edges(&a).chain(ring_edges(&b)).collect::<Vec<_>>();
+        for (p, q) in split_edges(&edges) {
+            graph.add_edge(p, q);
+        }
+
+        // keep the edges which separate the inside from the outside of the result,
+        // oriented so that the inside of the result is on the left
+        let mut directed = Vec::new();
+        for &(v1, v2) in graph.edges.iter() {
+            let (p, q) = (graph.vertices[v1], graph.vertices[v2]);
+            let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+            let len = (dx * dx + dy * dy).sqrt();
+            let offset = (len * 1e-3).min(1e-4);
+            let (nx, ny) = (-dy / len * offset, dx / len * offset);
+            let mid = ((p.0 + q.0) / 2.0, (p.1 + q.1) / 2.0);
+
+            let is_inside = |pt: (f64, f64)| {
+                op.apply(
+                    is_inside(&a, self.winding_order, pt),
+                    is_inside(&b, other.winding_order, pt),
+                )
+            };
+            let left = is_inside((mid.0 + nx, mid.1 + ny));
+            let right = is_inside((mid.0 - nx, mid.1 - ny));
+
+            match (left, right) {
+                (true, false) => directed.push((v1, v2)),
+                (false, true) => directed.push((v2, v1)),
+                _ => {}
+            }
+        }
+
+        let rings = graph
+            .chain_rings(&directed)
+            .into_iter()
+            .map(|ring| {
+                remove_collinear(&ring)
+                    .into_iter()
+                    .map(|(x, y)| {
+                        let point = Point {
+                            x: Pt(x as f32),
+                            y: Pt(y as f32),
+                        };
+                        (point, false)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|ring| ring.len() > 2)
+            .collect();
+
+        Polygon {
+            rings,
+            mode: self.mode,
+            winding_order: WindingOrder::NonZero,
+        }
+    }
+
+    /// Returns the polygon with all bezier curves converted to line segments
+    pub fn flatten(&self, tolerance: Pt) -> Polygon {
+        Polygon {
+            rings: flatten_rings(&self.rings, tolerance.0 as f64)
+                .into_iter()
+                .map(|ring| {
+                    ring.into_iter()
+                        .map(|(x, y)| {
+                            let point = Point {
+                                x: Pt(x as f32),
+                                y: Pt(y as f32),
+                            };
+                            (point, false)
+                        })
+                        .collect()
+                })
+                .collect(),
+            mode: self.mode,
+            winding_order: self.winding_order,
+        }
+    }
+}
+
+/// Converts the rings into closed lists of points, flattening bezier curves
+/// the same way as they are interpreted when the polygon is drawn
+pub(crate) fn flatten_rings(rings: &[Vec<(Point, bool)>], tolerance: f64) -> Vec<Vec<(f64, f64)>> {
+    let tolerance = tolerance.max(1e-4);
+    let to_f64 = |p: &Point| (p.x.0 as f64, p.y.0 as f64);
+
+    rings
+        .iter()
+        .filter(|ring| !ring.is_empty())
+        .map(|ring| {
+            let mut points = vec![to_f64(&ring[0].0)];
+            let mut current = 1;
+            while current < ring.len() {
+                let p1 = &ring[current - 1];
+                let p2 = &ring[current];
+                if p1.1 && p2.1 && current + 2 < ring.len() {
+                    let curve = [
+                        to_f64(&p1.0),
+                        to_f64(&p2.0),
+                        to_f64(&ring[current + 1].0),
+                        to_f64(&ring[current + 2].0),
+                    ];
+                    flatten_cubic(curve, tolerance, &mut points);
+                    current += 3;
+                } else {
+                    points.push(to_f64(&p2.0));
+                    current += 1;
+                }
+            }
+            points
+        })
+        .collect()
+}
+
+/// Appends the points of a flattened cubic bezier curve (without the start point)
+fn flatten_cubic(curve: [(f64, f64); 4], tolerance: f64, points: &mut Vec<(f64, f64)>) {
+    let [p0, p1, p2, p3] = curve;
+
+    // the distance between a cubic curve and n line segments is at most
+    // 3/4 * max|p[i] - 2 * p[i+1] + p[i+2]| / n^2
+    let dd = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+        let x = a.0 - 2.0 * b.0 + c.0;
+        let y = a.1 - 2.0 * b.1 + c.1;
+        (x * x + y * y).sqrt()
+    };
+    let dd = dd(p0, p1, p2).max(dd(p1, p2, p3));
+    let n = ((0.75 * dd / tolerance).sqrt().ceil() as usize).clamp(1, 1000);
+
+    for i in 1..=n {
+        let t = i as f64 / n as f64;
+        let mt = 1.0 - t;
+        let a = mt * mt * mt;
+        let b = 3.0 * mt * mt * t;
+        let c = 3.0 * mt * t * t;
+        let d = t * t * t;
+        points.push((
+            a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+            a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+        ));
+    }
+}
+
+/// Iterates over all edges of the (implicitly closed) rings
+fn ring_edges(rings: &[Vec<(f64, f64)>]) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    rings.iter().flat_map(|ring| {
+        (0..ring.len())
+            .map(move |i| (ring[i], ring[(i + 1) % ring.len()]))
+            .filter(|(p, q)| p != q)
+    })
+}
+
+/// Splits the edges at all points where they intersect or touch other edges
+fn split_edges(edges: &[((f64, f64), (f64, f64))]) -> Vec<((f64, f64), (f64, f64))> {
+    let mut splits = edges
+        .iter()
+        .map(|(p, q)| vec![(0.0, *p), (1.0, *q)])
+        .collect::<Vec<_>>();
+
+    // sweep over the edges sorted by their minimum x coordinate
+    let mut order = (0..edges.len()).collect::<Vec<_>>();
+    let min_x = |i: usize| edges[i].0 .0.min(edges[i].1 .0);
+    let max_x = |i: usize| edges[i].0 .0.max(edges[i].1 .0);
+    order.sort_by(|a, b| min_x(*a).total_cmp(&min_x(*b)));
+
+    for (pos, &i) in order.iter().enumerate() {
+        for &j in order[pos + 1..].iter() {
+            if min_x(j) > max_x(i) + SNAP_GRID {
+                break;
+            }
+            for (on_i, t, point) in intersect(edges[i], edges[j]) {
+                let edge = if on_i { i } else { j };
+                splits[edge].push((t, point));
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for mut points in splits {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for w in points.windows(2) {
+            result.push((w[0].1, w[1].1));
+        }
+    }
+    result
+}
+
+/// Returns the split points of two edges: `(on_first_edge, t, point)`
+fn intersect(
+    e1: ((f64, f64), (f64, f64)),
+    e2: ((f64, f64), (f64, f64)),
+) -> Vec<(bool, f64, (f64, f64))> {
+    let (p, p2) = e1;
+    let (q, q2) = e2;
+    let r = (p2.0 - p.0, p2.1 - p.1);
+    let s = (q2.0 - q.0, q2.1 - q.1);
+    let cross = |a: (f64, f64), b: (f64, f64)| a.0 * b.1 - a.1 * b.0;
+    let dot = |a: (f64, f64), b: (f64, f64)| a.0 * b.0 + a.1 * b.1;
+    let r_len2 = dot(r, r);
+    let s_len2 = dot(s, s);
+    let qp = (q.0 - p.0, q.1 - p.1);
+    let eps = 1e-9;
+
+    let mut result = Vec::new();
+    let denom = cross(r, s);
+
+    if denom.abs() > eps * (r_len2 * s_len2).sqrt() {
+        let t = cross(qp, s) / denom;
+        let u = cross(qp, r) / denom;
+        let t_eps = SNAP_GRID / r_len2.sqrt();
+        let u_eps = SNAP_GRID / s_len2.sqrt();
+        if t < -t_eps || t > 1.0 + t_eps || u < -u_eps || u > 1.0 + u_eps {
+            return result;
+        }
+
+        // use the exact end point if the edges only touch
+        let point = if t.abs() <= t_eps {
+            p
+        } else if (t - 1.0).abs() <= t_eps {
+            p2
+        } else if u.abs() <= u_eps {
+            q
+        } else if (u - 1.0).abs() <= u_eps {
+            q2
+        } else {
+            (p.0 + t * r.0, p.1 + t * r.1)
+        };
+
+        result.push((true, t.clamp(0.0, 1.0), point));
+        result.push((false, u.clamp(0.0, 1.0), point));
+    } else if cross(qp, r).abs() <= SNAP_GRID * r_len2.sqrt() {
+        // collinear edges: split each edge at the end points of the other edge
+        for end in [q, q2] {
+            let t = dot((end.0 - p.0, end.1 - p.1), r) / r_len2;
+            if t > 0.0 && t < 1.0 {
+                result.push((true, t, end));
+            }
+        }
+        for end in [p, p2] {
+            let u = dot((end.0 - q.0, end.1 - q.1), s) / s_len2;
+            if u > 0.0 && u < 1.0 {
+                result.push((false, u, end));
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns whether the point is inside of the rings, according to the winding order
+fn is_inside(rings: &[Vec<(f64, f64)>], winding_order: WindingOrder, pt: (f64, f64)) -> bool {
+    let mut winding = 0;
+    for (p, q) in ring_edges(rings) {
+        let side = (q.0 - p.0) * (pt.1 - p.1) - (pt.0 - p.0) * (q.1 - p.1);
+        if p.1 <= pt.1 {
+            if q.1 > pt.1 && side > 0.0 {
+                winding += 1;
+            }
+        } else if q.1 <= pt.1 && side < 0.0 {
+            winding -= 1;
+        }
+    }
+
+    match winding_order {
+        WindingOrder::NonZero => winding != 0,
+        WindingOrder::EvenOdd => winding % 2 != 0,
+    }
+}
+
+/// Removes points which lie on the straight line between their neighbours
+fn remove_collinear(ring: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let len = ring.len();
+    (0..len)
+        .filter(|&i| {
+            let prev = ring[(i + len - 1) % len];
+            let cur = ring[i];
+            let next = ring[(i + 1) % len];
+            let a = (cur.0 - prev.0, cur.1 - prev.1);
+            let b = (next.0 - cur.0, next.1 - cur.1);
+            let cross = a.0 * b.1 - a.1 * b.0;
+            let dot = a.0 * b.0 + a.1 * b.1;
+            let scale = (a.0 * a.0 + a.1 * a.1).sqrt() * (b.0 * b.0 + b.1 * b.1).sqrt();
+            cross.abs() > 1e-9 * scale || dot < 0.0
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Planar graph of the split edges, with vertices snapped to a grid
+#[derive(Default)]
+struct EdgeGraph {
+    vertices: Vec<(f64, f64)>,
+    vertex_ids: HashMap<(i64, i64), usize>,
+    /// Undirected edges `(smaller vertex id, larger vertex id)`, without duplicates
+    edges: Vec<(usize, usize)>,
+    edge_ids: HashMap<(usize, usize), usize>,
+}
+
+impl EdgeGraph {
+    fn vertex(&mut self, p: (f64, f64)) -> usize {
+        let key = (
+            (p.0 / SNAP_GRID).round() as i64,
+            (p.1 / SNAP_GRID).round() as i64,
+        );
+        let next_id = self.vertices.len();
+        let id = *self.vertex_ids.entry(key).or_insert(next_id);
+        if id == next_id {
+            self.vertices.push(p);
+        }
+        id
+    }
+
+    fn add_edge(&mut self, p: (f64, f64), q: (f64, f64)) {
+        let (v1, v2) = (self.vertex(p), self.vertex(q));
+        if v1 == v2 {
+            return;
+        }
+        let key = (v1.min(v2), v1.max(v2));
+        let next_id = self.edges.len();
+        if *self.edge_ids.entry(key).or_insert(next_id) == next_id {
+            self.edges.push(key);
+        }
+    }
+
+    /// Connects the directed edges into closed rings. At vertices with several
+    /// outgoing edges, the edge with the sharpest left turn is taken, which keeps
+    /// rings that only touch each other at a vertex separate.
+    fn chain_rings(&self, directed: &[(usize, usize)]) -> Vec<Vec<(f64, f64)>> {
+        let mut outgoing = BTreeMap::<usize, Vec<usize>>::new();
+        for (i, (from, _)) in directed.iter().enumerate() {
+            outgoing.entry(*from).or_default().push(i);
+        }
+
+        let angle = |from: usize, to: usize| {
+            let (p, q) = (self.vertices[from], self.vertices[to]);
+            (q.1 - p.1).atan2(q.0 - p.0)
+        };
+
+        let mut used = vec![false; directed.len()];
+        let mut rings = Vec::new();
+
+        for start in 0..directed.len() {
+            if used[start] {
+                continue;
+            }
+
+            used[start] = true;
+            let (start_vertex, mut current_vertex) = directed[start];
+            let mut previous_vertex = start_vertex;
+            let mut ring = vec![self.vertices[start_vertex]];
+
+            while current_vertex != start_vertex {
+                ring.push(self.vertices[current_vertex]);
+
+                // angle of the reversed incoming edge, the next edge is the
+                // first one found when rotating clockwise from there
+                let back = angle(current_vertex, previous_vertex);
+                let next = outgoing.get(&current_vertex).and_then(|edges| {
+                    edges.iter().copied().filter(|e| !used[*e]).min_by(|a, b| {
+                        let cw = |e: usize| {
+                            let mut a = back - angle(current_vertex, directed[e].1);
+                            while a <= 1e-12 {
+                                a += 2.0 * std::f64::consts::PI;
+                            }
+                            a
+                        };
+                        cw(*a).total_cmp(&cw(*b))
+                    })
+                });
+
+                let Some(next) = next else {
+                    // open chain, can only happen with degenerate input
+                    ring.clear();
+                    break;
+                };
+
+                used[next] = true;
+                previous_vertex = current_vertex;
+                current_vertex = directed[next].1;
+            }
+
+            if ring.len() > 2 {
+                rings.push(ring);
+            }
+        }
+
+        rings
+    }
+}
+
+#[cfg(test)]
+impl Polygon {
+    /// Sum of the signed areas of the rings (shoelace formula): counter-clockwise
+    /// rings count positive, clockwise rings (holes) negative
+    pub(crate) fn signed_area(&self) -> f32 {
+        self.rings
+            .iter()
+            .map(|ring| {
+                (0..ring.len())
+                    .map(|i| {
+                        let (p, q) = (ring[i].0, ring[(i + 1) % ring.len()].0);
+                        p.x.0 * q.y.0 - q.x.0 * p.y.0
+                    })
+                    .sum::<f32>()
+                    / 2.0
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+fn square(x: f32, y: f32, size: f32) -> Polygon {
+    let pt = |x: f32, y: f32| (Point { x: Pt(x), y: Pt(y) }, false);
+    Polygon {
+        rings: vec![vec![
+            pt(x, y),
+            pt(x + size, y),
+            pt(x + size, y + size),
+            pt(x, y + size),
+        ]],
+        mode: PaintMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    }
+}
+
+#[test]
+fn test_boolean_ops_squares() {
+    let a = square(0.0, 0.0, 10.0);
+    let b = square(5.0, 5.0, 10.0);
+
+    let union = a.union(&b);
+    assert_eq!(union.rings.len(), 1);
+    assert_eq!(union.rings[0].len(), 8);
+    assert!((union.signed_area() - 175.0).abs() < 0.01);
+
+    let intersection = a.intersection(&b);
+    assert_eq!(intersection.rings.len(), 1);
+    assert!((intersection.signed_area() - 25.0).abs() < 0.01);
+
+    assert!((a.difference(&b).signed_area() - 75.0).abs() < 0.01);
+    assert!((a.xor(&b).signed_area() - 150.0).abs() < 0.01);
+
+    // adjacent squares are merged into one rectangle
+    let merged = a.union(&square(10.0, 0.0, 10.0));
+    assert_eq!(merged.rings.len(), 1);
+    assert_eq!(merged.rings[0].len(), 4);
+
+    // punching a hole: the hole is a clockwise ring
+    let knockout = square(0.0, 0.0, 30.0).difference(&square(10.0, 10.0, 10.0));
+    assert_eq!(knockout.rings.len(), 2);
+    assert!((knockout.signed_area() - 800.0).abs() < 0.01);
+}
+
+#[test]
+fn test_boolean_ops_winding_order() {
+    // two overlapping rings in one polygon: the overlap is a hole with even-odd
+    let mut a = square(0.0, 0.0, 10.0);
+    a.rings.extend(square(5.0, 0.0, 10.0).rings);
+    assert!((a.simplify().signed_area() - 150.0).abs() < 0.01);
+
+    a.winding_order = WindingOrder::EvenOdd;
+    assert!((a.simplify().signed_area() - 100.0).abs() < 0.01);
+}
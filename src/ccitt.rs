@@ -0,0 +1,661 @@
+//! CCITT Group 4 (T.6) encoder for bilevel images (`/CCITTFaxDecode` with `/K -1`),
+//! plus a Modified Huffman, Group 3 (T.4) and Group 4 decoder for fax TIFF files
+//!
+//! The input is a 1-bit image as in `RawImageFormat::R1`: rows start at a byte
+//! boundary, most significant bit first, 0 = black and 1 = white (which is the
+//! `/BlackIs1 false` default of the filter).
+
+/// Code (length in bits, bits) for the pass mode
+const PASS: (u8, u16) = (4, 0b0001);
+/// Code for the horizontal mode, followed by two run lengths
+const HORIZONTAL: (u8, u16) = (3, 0b001);
+/// Codes for the vertical modes, indexed by `a1 - b1 + 3`
+const VERTICAL: [(u8, u16); 7] = [
+    (7, 0b0000010),
+    (6, 0b000010),
+    (3, 0b010),
+    (1, 0b1),
+    (3, 0b011),
+    (6, 0b000011),
+    (7, 0b0000011),
+];
+/// End of line, written twice as the end of block (EOFB)
+const EOL: (u8, u16) = (12, 0b000000000001);
+
+/// Terminating codes for white runs of 0 - 63 pixels
+const WHITE_TERMINATING: [(u8, u16); 64] = [
+    (8, 0b00110101),
+    (6, 0b000111),
+    (4, 0b0111),
+    (4, 0b1000),
+    (4, 0b1011),
+    (4, 0b1100),
+    (4, 0b1110),
+    (4, 0b1111),
+    (5, 0b10011),
+    (5, 0b10100),
+    (5, 0b00111),
+    (5, 0b01000),
+    (6, 0b001000),
+    (6, 0b000011),
+    (6, 0b110100),
+    (6, 0b110101),
+    (6, 0b101010),
+    (6, 0b101011),
+    (7, 0b0100111),
+    (7, 0b0001100),
+    (7, 0b0001000),
+    (7, 0b0010111),
+    (7, 0b0000011),
+    (7, 0b0000100),
+    (7, 0b0101000),
+    (7, 0b0101011),
+    (7, 0b0010011),
+    (7, 0b0100100),
+    (7, 0b0011000),
+    (8, 0b00000010),
+    (8, 0b00000011),
+    (8, 0b00011010),
+    (8, 0b00011011),
+    (8, 0b00010010),
+    (8, 0b00010011),
+    (8, 0b00010100),
+    (8, 0b00010101),
+    (8, 0b00010110),
+    (8, 0b00010111),
+    (8, 0b00101000),
+    (8, 0b00101001),
+    (8, 0b00101010),
+    (8, 0b00101011),
+    (8, 0b00101100),
+    (8, 0b00101101),
+    (8, 0b00000100),
+    (8, 0b00000101),
+    (8, 0b00001010),
+    (8, 0b00001011),
+    (8, 0b01010010),
+    (8, 0b01010011),
+    (8, 0b01010100),
+    (8, 0b01010101),
+    (8, 0b00100100),
+    (8, 0b00100101),
+    (8, 0b01011000),
+    (8, 0b01011001),
+    (8, 0b01011010),
+    (8, 0b01011011),
+    (8, 0b01001010),
+    (8, 0b01001011),
+    (8, 0b00110010),
+    (8, 0b00110011),
+    (8, 0b00110100),
+];
+
+/// Make-up codes for white runs of 64 - 1728 pixels (multiples of 64)
+const WHITE_MAKEUP: [(u8, u16); 27] = [
+    (5, 0b11011),
+    (5, 0b10010),
+    (6, 0b010111),
+    (7, 0b0110111),
+    (8, 0b00110110),
+    (8, 0b00110111),
+    (8, 0b01100100),
+    (8, 0b01100101),
+    (8, 0b01101000),
+    (8, 0b01100111),
+    (9, 0b011001100),
+    (9, 0b011001101),
+    (9, 0b011010010),
+    (9, 0b011010011),
+    (9, 0b011010100),
+    (9, 0b011010101),
+    (9, 0b011010110),
+    (9, 0b011010111),
+    (9, 0b011011000),
+    (9, 0b011011001),
+    (9, 0b011011010),
+    (9, 0b011011011),
+    (9, 0b010011000),
+    (9, 0b010011001),
+    (9, 0b010011010),
+    (6, 0b011000),
+    (9, 0b010011011),
+];
+
+/// Terminating codes for black runs of 0 - 63 pixels
+const BLACK_TERMINATING: [(u8, u16); 64] = [
+    (10, 0b0000110111),
+    (3, 0b010),
+    (2, 0b11),
+    (2, 0b10),
+    (3, 0b011),
+    (4, 0b0011),
+    (4, 0b0010),
+    (5, 0b00011),
+    (6, 0b000101),
+    (6, 0b000100),
+    (7, 0b0000100),
+    (7, 0b0000101),
+    (7, 0b0000111),
+    (8, 0b00000100),
+    (8, 0b00000111),
+    (9, 0b000011000),
+    (10, 0b0000010111),
+    (10, 0b0000011000),
+    (10, 0b0000001000),
+    (11, 0b00001100111),
+    (11, 0b00001101000),
+    (11, 0b00001101100),
+    (11, 0b00000110111),
+    (11, 0b00000101000),
+    (11, 0b00000010111),
+    (11, 0b00000011000),
+    (12, 0b000011001010),
+    (12, 0b000011001011),
+    (12, 0b000011001100),
+    (12, 0b000011001101),
+    (12, 0b000001101000),
+    (12, 0b000001101001),
+    (12, 0b000001101010),
+    (12, 0b000001101011),
+    (12, 0b000011010010),
+    (12, 0b000011010011),
+    (12, 0b000011010100),
+    (12, 0b000011010101),
+    (12, 0b000011010110),
+    (12, 0b000011010111),
+    (12, 0b000001101100),
+    (12, 0b000001101101),
+    (12, 0b000011011010),
+    (12, 0b000011011011),
+    (12, 0b000001010100),
+    (12, 0b000001010101),
+    (12, 0b000001010110),
+    (12, 0b000001010111),
+    (12, 0b000001100100),
+    (12, 0b000001100101),
+    (12, 0b000001010010),
+    (12, 0b000001010011),
+    (12, 0b000000100100),
+    (12, 0b000000110111),
+    (12, 0b000000111000),
+    (12, 0b000000100111),
+    (12, 0b000000101000),
+    (12, 0b000001011000),
+    (12, 0b000001011001),
+    (12, 0b000000101011),
+    (12, 0b000000101100),
+    (12, 0b000001011010),
+    (12, 0b000001100110),
+    (12, 0b000001100111),
+];
+
+/// Make-up codes for black runs of 64 - 1728 pixels (multiples of 64)
+const BLACK_MAKEUP: [(u8, u16); 27] = [
+    (10, 0b0000001111),
+    (12, 0b000011001000),
+    (12, 0b000011001001),
+    (12, 0b000001011011),
+    (12, 0b000000110011),
+    (12, 0b000000110100),
+    (12, 0b000000110101),
+    (13, 0b0000001101100),
+    (13, 0b0000001101101),
+    (13, 0b0000001001010),
+    (13, 0b0000001001011),
+    (13, 0b0000001001100),
+    (13, 0b0000001001101),
+    (13, 0b0000001110010),
+    (13, 0b0000001110011),
+    (13, 0b0000001110100),
+    (13, 0b0000001110101),
+    (13, 0b0000001110110),
+    (13, 0b0000001110111),
+    (13, 0b0000001010010),
+    (13, 0b0000001010011),
+    (13, 0b0000001010100),
+    (13, 0b0000001010101),
+    (13, 0b0000001011010),
+    (13, 0b0000001011011),
+    (13, 0b0000001100100),
+    (13, 0b0000001100101),
+];
+
+/// Make-up codes for runs of 1792 - 2560 pixels (multiples of 64), same for both colors
+const EXTENDED_MAKEUP: [(u8, u16); 13] = [
+    (11, 0b00000001000),
+    (11, 0b00000001100),
+    (11, 0b00000001101),
+    (12, 0b000000010010),
+    (12, 0b000000010011),
+    (12, 0b000000010100),
+    (12, 0b000000010101),
+    (12, 0b000000010110),
+    (12, 0b000000010111),
+    (12, 0b000000011100),
+    (12, 0b000000011101),
+    (12, 0b000000011110),
+    (12, 0b000000011111),
+];
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, (len, code): (u8, u16)) {
+        for i in (0..len).rev() {
+            self.current = (self.current << 1) | ((code >> i) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.bytes.push(self.current << (8 - self.used));
+        }
+        self.bytes
+    }
+}
+
+/// Encodes a 1-bit image with CCITT Group 4 compression, terminated by an EOFB
+pub(crate) fn encode_g4(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8).max(1);
+    let mut writer = BitWriter::default();
+
+    // the line above the first line is all white
+    let mut reference = vec![width, width];
+    for row in pixels.chunks(row_bytes).take(height) {
+        let coding = changing_elements(row, width);
+        encode_2d_row(&mut writer, &coding, &reference, width);
+        reference = coding;
+    }
+
+    writer.write(EOL);
+    writer.write(EOL);
+    writer.finish()
+}
+
+// Writes the 2D (T.6) codes of a row with the changing elements `coding`
+fn encode_2d_row(writer: &mut BitWriter, coding: &[usize], reference: &[usize], width: usize) {
+    let mut a0 = -1_isize;
+    let mut white = true;
+
+    while a0 < width as isize {
+        let a1 = next_change(coding, a0);
+        let (b1, b2) = reference_changes(reference, a0, white);
+
+        if b2 < a1 {
+            writer.write(PASS);
+            a0 = b2 as isize;
+        } else if a1.abs_diff(b1) <= 3 {
+            writer.write(VERTICAL[(a1 as isize - b1 as isize + 3) as usize]);
+            a0 = a1 as isize;
+            white = !white;
+        } else {
+            let a2 = next_change(coding, a1 as isize);
+            writer.write(HORIZONTAL);
+            write_run(writer, a1 - a0.max(0) as usize, white);
+            write_run(writer, a2 - a1, !white);
+            a0 = a2 as isize;
+        }
+    }
+}
+
+fn write_run(writer: &mut BitWriter, mut run: usize, white: bool) {
+    let (terminating, makeup) = if white {
+        (&WHITE_TERMINATING, &WHITE_MAKEUP)
+    } else {
+        (&BLACK_TERMINATING, &BLACK_MAKEUP)
+    };
+
+    while run >= 2624 {
+        writer.write(EXTENDED_MAKEUP[12]);
+        run -= 2560;
+    }
+
+    if run >= 64 {
+        let m = run / 64;
+        writer.write(if m >= 28 {
+            EXTENDED_MAKEUP[m - 28]
+        } else {
+            makeup[m - 1]
+        });
+        run %= 64;
+    }
+
+    writer.write(terminating[run]);
+}
+
+// Positions of the pixels whose color differs from the previous pixel (the pixel
+// before the line is white), followed by two `width` entries as end markers.
+// Even entries start a black run, odd entries start a white run.
+fn changing_elements(row: &[u8], width: usize) -> Vec<usize> {
+    let mut changes = Vec::new();
+    let mut previous = 1;
+    for i in 0..width {
+        let pixel = row.get(i / 8).map_or(1, |b| (b >> (7 - i % 8)) & 1);
+        if pixel != previous {
+            changes.push(i);
+            previous = pixel;
+        }
+    }
+    changes.push(width);
+    changes.push(width);
+    changes
+}
+
+// First changing element right of a0
+fn next_change(changes: &[usize], a0: isize) -> usize {
+    changes
+        .iter()
+        .copied()
+        .find(|c| *c as isize > a0)
+        .unwrap_or(changes[changes.len() - 1])
+}
+
+// b1 (first changing element on the reference line right of a0 and of the
+// opposite color of a0) and b2 (next changing element after b1)
+fn reference_changes(reference: &[usize], a0: isize, white: bool) -> (usize, usize) {
+    let last = reference.len() - 1;
+    let mut i = reference
+        .iter()
+        .position(|c| *c as isize > a0)
+        .unwrap_or(last);
+    // a black run starts at even positions, so b1 must be even if a0 is white
+    if (i % 2 == 0) != white {
+        i += 1;
+    }
+    let b1 = reference[i.min(last)];
+    let b2 = reference[(i + 1).min(last)];
+    (b1, b2)
+}
+
+/// CCITT compression of a fax TIFF strip (TIFF `Compression` 2, 3 or 4)
+#[cfg(any(test, feature = "tiff"))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FaxCompression {
+    /// Modified Huffman (2): 1D coded rows starting at a byte boundary, without EOLs
+    Huffman,
+    /// Group 3 / T.4 (3): rows start with an EOL, followed by a 1D / 2D tag bit
+    /// if `two_dimensional` is set (bit 0 of the `T4Options` tag)
+    Group3 { two_dimensional: bool },
+    /// Group 4 / T.6 (4): 2D coded rows
+    Group4,
+}
+
+#[cfg(any(test, feature = "tiff"))]
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+#[cfg(any(test, feature = "tiff"))]
+impl BitReader<'_> {
+    fn bit_at(&self, bit: usize) -> Option<u8> {
+        let byte = *self.data.get(bit / 8)?;
+        Some((byte >> (7 - bit % 8)) & 1)
+    }
+
+    fn read_bit(&mut self) -> Option<u8> {
+        let bit = self.bit_at(self.bit)?;
+        self.bit += 1;
+        Some(bit)
+    }
+
+    // Skips an EOL (at least 11 zero bits followed by a one bit), the zero bits
+    // include the fill bits that align EOLs to a byte boundary
+    fn skip_eol(&mut self) {
+        let mut bit = self.bit;
+        while self.bit_at(bit) == Some(0) {
+            bit += 1;
+        }
+        if bit - self.bit >= 11 && self.bit_at(bit) == Some(1) {
+            self.bit = bit + 1;
+        }
+    }
+
+    // Reads the next code, returns the index of the table and of the code in the table
+    fn read_code(&mut self, tables: &[&[(u8, u16)]]) -> Option<(usize, usize)> {
+        let (mut len, mut code) = (0_u8, 0_u16);
+        while len < 13 {
+            code = (code << 1) | self.read_bit()? as u16;
+            len += 1;
+            for (table_index, table) in tables.iter().enumerate() {
+                if let Some(i) = table.iter().position(|c| *c == (len, code)) {
+                    return Some((table_index, i));
+                }
+            }
+        }
+        None
+    }
+
+    fn read_run(&mut self, white: bool) -> Option<usize> {
+        let tables: [&[(u8, u16)]; 3] = if white {
+            [&WHITE_TERMINATING, &WHITE_MAKEUP, &EXTENDED_MAKEUP]
+        } else {
+            [&BLACK_TERMINATING, &BLACK_MAKEUP, &EXTENDED_MAKEUP]
+        };
+        let mut run = 0;
+        loop {
+            match self.read_code(&tables)? {
+                (0, i) => return Some(run + i),
+                (1, i) => run += (i + 1) * 64,
+                (_, i) => run += 1792 + i * 64,
+            }
+        }
+    }
+
+    // Reads the changing elements of a 1D (run length) coded row
+    fn read_1d_row(&mut self, width: usize) -> Option<Vec<usize>> {
+        let mut changes = Vec::new();
+        let mut a0 = 0;
+        let mut white = true;
+        while a0 < width {
+            a0 += self.read_run(white)?;
+            changes.push(a0);
+            white = !white;
+        }
+        Some(changes)
+    }
+
+    // Reads the changing elements of a 2D coded row
+    fn read_2d_row(&mut self, reference: &[usize], width: usize) -> Option<Vec<usize>> {
+        let modes: [&[(u8, u16)]; 3] = [&[PASS], &[HORIZONTAL], &VERTICAL];
+        let mut changes = Vec::new();
+        let mut a0 = -1_isize;
+        let mut white = true;
+        while a0 < width as isize {
+            let (b1, b2) = reference_changes(reference, a0, white);
+            match self.read_code(&modes)? {
+                (0, _) => a0 = b2 as isize,
+                (1, _) => {
+                    let a1 = a0.max(0) as usize + self.read_run(white)?;
+                    let a2 = a1 + self.read_run(!white)?;
+                    changes.extend([a1, a2]);
+                    a0 = a2 as isize;
+                }
+                (_, i) => {
+                    let a1 = usize::try_from(b1 as isize + i as isize - 3).ok()?;
+                    changes.push(a1);
+                    a0 = a1 as isize;
+                    white = !white;
+                }
+            }
+        }
+        Some(changes)
+    }
+}
+
+/// Decodes CCITT fax data (i.e. a strip of a fax TIFF) to packed 1-bit pixels
+/// (0 = black), each row starts at a byte boundary
+#[cfg(any(test, feature = "tiff"))]
+pub(crate) fn decode_fax(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    compression: FaxCompression,
+) -> Option<Vec<u8>> {
+    let mut reader = BitReader { data, bit: 0 };
+
+    let row_bytes = width.div_ceil(8);
+    let mut pixels = Vec::with_capacity(row_bytes * height);
+    let mut reference = vec![width, width];
+    for _ in 0..height {
+        let two_dimensional = match compression {
+            FaxCompression::Huffman => {
+                reader.bit = reader.bit.next_multiple_of(8);
+                false
+            }
+            FaxCompression::Group3 { two_dimensional } => {
+                reader.skip_eol();
+                // the tag bit is 1 for a 1D coded row
+                two_dimensional && reader.read_bit()? == 0
+            }
+            FaxCompression::Group4 => true,
+        };
+        let changes = if two_dimensional {
+            reader.read_2d_row(&reference, width)?
+        } else {
+            reader.read_1d_row(width)?
+        };
+
+        let mut row = vec![0_u8; row_bytes];
+        let mut pixel_white = true;
+        let mut changes = changes.into_iter().peekable();
+        for i in 0..width {
+            while changes.next_if(|c| *c == i).is_some() {
+                pixel_white = !pixel_white;
+            }
+            if pixel_white {
+                row[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        reference = changing_elements(&row, width);
+        pixels.extend(row);
+    }
+
+    Some(pixels)
+}
+
+#[test]
+fn test_g4_roundtrip() {
+    let (width, height) = (75_usize, 40);
+    let row_bytes = width.div_ceil(8);
+    let mut pixels = vec![0_u8; row_bytes * height];
+    for y in 0..height {
+        for x in 0..width {
+            // a filled circle, a diagonal line and a long black bar
+            let (dx, dy) = (x as isize - 30, y as isize - 20);
+            let black = dx * dx + dy * dy < 150 || x == y + 20 || (y == 35 && x > 2);
+            if !black {
+                pixels[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+
+    let encoded = encode_g4(&pixels, width, height);
+    assert!(encoded.len() < pixels.len());
+    assert_eq!(
+        decode_fax(&encoded, width, height, FaxCompression::Group4),
+        Some(pixels)
+    );
+
+    // run lengths that need make-up codes
+    let width = 3000_usize;
+    let mut row = vec![0xFF_u8; width / 8];
+    row[100..300].fill(0);
+    let encoded = encode_g4(&row, width, 1);
+    assert_eq!(
+        decode_fax(&encoded, width, 1, FaxCompression::Group4),
+        Some(row)
+    );
+}
+
+#[test]
+fn test_g3_decode() {
+    let (width, height) = (75_usize, 12);
+    let row_bytes = width.div_ceil(8);
+    let mut pixels = vec![0_u8; row_bytes * height];
+    for y in 0..height {
+        // a diagonal black bar and a black row
+        for x in (0..width).filter(|x| y != 5 && !(y * 3..y * 3 + 20).contains(x)) {
+            pixels[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    let rows: Vec<Vec<usize>> = pixels
+        .chunks(row_bytes)
+        .map(|row| changing_elements(row, width))
+        .collect();
+
+    let write_1d_row = |writer: &mut BitWriter, coding: &[usize]| {
+        let (mut a0, mut white) = (0, true);
+        for c in coding {
+            write_run(writer, c - a0, white);
+            (a0, white) = (*c, !white);
+            if *c == width {
+                break;
+            }
+        }
+    };
+
+    // Modified Huffman: byte aligned rows
+    let mut writer = BitWriter::default();
+    for coding in &rows {
+        write_1d_row(&mut writer, coding);
+        while writer.used != 0 {
+            writer.write((1, 0));
+        }
+    }
+    let encoded = writer.finish();
+    let decoded = decode_fax(&encoded, width, height, FaxCompression::Huffman);
+    assert_eq!(decoded.as_ref(), Some(&pixels));
+
+    // Group 3, 1D: EOLs with fill bits, so that they end at a byte boundary
+    let mut writer = BitWriter::default();
+    for coding in &rows {
+        while (writer.used + 12) % 8 != 0 {
+            writer.write((1, 0));
+        }
+        writer.write(EOL);
+        write_1d_row(&mut writer, coding);
+    }
+    let encoded = writer.finish();
+    let compression = FaxCompression::Group3 {
+        two_dimensional: false,
+    };
+    assert_eq!(
+        decode_fax(&encoded, width, height, compression).as_ref(),
+        Some(&pixels)
+    );
+
+    // Group 3, 2D: every fourth row is 1D coded
+    let mut writer = BitWriter::default();
+    let mut reference = vec![width, width];
+    for (y, coding) in rows.iter().enumerate() {
+        writer.write(EOL);
+        if y % 4 == 0 {
+            writer.write((1, 1));
+            write_1d_row(&mut writer, coding);
+        } else {
+            writer.write((1, 0));
+            encode_2d_row(&mut writer, coding, &reference, width);
+        }
+        reference = coding.clone();
+    }
+    let encoded = writer.finish();
+    let compression = FaxCompression::Group3 {
+        two_dimensional: true,
+    };
+    assert_eq!(
+        decode_fax(&encoded, width, height, compression),
+        Some(pixels)
+    );
+}
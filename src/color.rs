@@ -1,4 +1,4 @@
-use crate::IccProfileId;
+use crate::{IccProfileId, PatternId, ShadingId};
 
 /// Color space (enum for marking the number of bits a color has)
 #[derive(Debug, Copy, PartialEq, Clone)]
@@ -62,8 +62,58 @@ pub enum Color {
     Cmyk(Cmyk),
     Greyscale(Greyscale),
     SpotColor(SpotColor),
+    /// Device-independent CIE L*a*b* color, painted in a `/Lab` color space
+    Lab {
+        /// Lightness, 0.0 - 100.0
+        l: f32,
+        a: f32,
+        b: f32,
+        /// CIE 1931 XYZ tristimulus value of the diffuse white point (Y must be 1.0)
+        white_point: [f32; 3],
+        /// Valid ranges of the a* and b* components: `[a_min, a_max, b_min, b_max]`
+        range: [f32; 4],
+    },
+    /// Calibrated RGB color, painted in a `/CalRGB` color space
+    CalRgb {
+        r: f32,
+        g: f32,
+        b: f32,
+        /// CIE 1931 XYZ tristimulus value of the diffuse white point (Y must be 1.0)
+        white_point: [f32; 3],
+        /// CIE 1931 XYZ tristimulus value of the diffuse black point
+        black_point: [f32; 3],
+        /// Gamma of the R, G and B components
+        gamma: [f32; 3],
+        /// Linear interpretation of the decoded components (XYZ of the R, G and B primaries)
+        matrix: [f32; 9],
+    },
+    /// Calibrated greyscale color, painted in a `/CalGray` color space
+    CalGray {
+        gray: f32,
+        /// CIE 1931 XYZ tristimulus value of the diffuse white point (Y must be 1.0)
+        white_point: [f32; 3],
+        /// CIE 1931 XYZ tristimulus value of the diffuse black point
+        black_point: [f32; 3],
+        gamma: f32,
+    },
+    /// Shading pattern, paints the area with a gradient registered via `PdfDocument::add_shading`
+    Shading(ShadingId),
+    /// Colored tiling pattern, registered via `PdfDocument::add_pattern`
+    Pattern(PatternId),
+    /// Uncolored tiling pattern, painted in the given (non-pattern) color.
+    /// Use `Color::uncolored_pattern` to check the color, pattern colors
+    /// can't be nested and are painted black.
+    UncoloredPattern {
+        pattern: PatternId,
+        color: Box<Color>,
+    },
 }
 
+/// CIE 1931 XYZ tristimulus value of the D50 white point (ICC profile connection space)
+pub const D50_WHITE_POINT: [f32; 3] = [0.9642, 1.0, 0.8249];
+/// CIE 1931 XYZ tristimulus value of the D65 white point (sRGB)
+pub const D65_WHITE_POINT: [f32; 3] = [0.9505, 1.0, 1.089];
+
 impl Color {
     /// Consumes the color and converts into into a vector of numbers
     pub fn into_vec(&self) -> Vec<f32> {
@@ -80,6 +130,94 @@ impl Color {
             Color::SpotColor(spot) => {
                 vec![spot.c, spot.m, spot.y, spot.k]
             }
+            Color::Lab { l, a, b, .. } => {
+                vec![*l, *a, *b]
+            }
+            Color::CalRgb { r, g, b, .. } => {
+                vec![*r, *g, *b]
+            }
+            Color::CalGray { gray, .. } => {
+                vec![*gray]
+            }
+            Color::Shading(_) | Color::Pattern(_) => Vec::new(),
+            Color::UncoloredPattern { color, .. } => color.into_vec(),
+        }
+    }
+
+    /// Creates a L*a*b* color relative to the D50 white point, with the
+    /// a* and b* components ranging from -100.0 to 100.0
+    pub fn lab(l: f32, a: f32, b: f32) -> Self {
+        Color::Lab {
+            l,
+            a,
+            b,
+            white_point: D50_WHITE_POINT,
+            range: [-100.0, 100.0, -100.0, 100.0],
+        }
+    }
+
+    /// Creates an uncolored pattern color, returns an error if `color` is itself
+    /// a shading or pattern color
+    pub fn uncolored_pattern(pattern: PatternId, color: Color) -> Result<Self, String> {
+        match color {
+            Color::Shading(_) | Color::Pattern(_) | Color::UncoloredPattern { .. } => Err(format!(
+                "uncolored pattern {} can't be painted with a pattern color",
+                pattern.0
+            )),
+            color => Ok(Color::UncoloredPattern {
+                pattern,
+                color: Box::new(color),
+            }),
+        }
+    }
+
+    /// Returns the color an uncolored pattern is painted with, nested pattern
+    /// colors (see `Color::uncolored_pattern`) are replaced with black
+    pub(crate) fn get_uncolored_pattern_color(&self) -> Option<Color> {
+        match self {
+            Color::UncoloredPattern { color, .. } => match color.as_ref() {
+                Color::Shading(_) | Color::Pattern(_) | Color::UncoloredPattern { .. } => {
+                    Some(Color::Greyscale(Greyscale::new(0.0, None)))
+                }
+                c => Some(c.clone()),
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the CIE-based color space of the color, `None` for device colors
+    /// (which are set with the `rg` / `k` / `g` operators instead)
+    pub fn get_cie_color_space(&self) -> Option<CieColorSpace> {
+        match self {
+            Color::Lab {
+                white_point, range, ..
+            } => Some(CieColorSpace::Lab {
+                white_point: *white_point,
+                range: *range,
+            }),
+            Color::CalRgb {
+                white_point,
+                black_point,
+                gamma,
+                matrix,
+                ..
+            } => Some(CieColorSpace::CalRgb {
+                white_point: *white_point,
+                black_point: *black_point,
+                gamma: *gamma,
+                matrix: *matrix,
+            }),
+            Color::CalGray {
+                white_point,
+                black_point,
+                gamma,
+                ..
+            } => Some(CieColorSpace::CalGray {
+                white_point: *white_point,
+                black_point: *black_point,
+                gamma: *gamma,
+            }),
+            _ => None,
         }
     }
 
@@ -90,7 +228,150 @@ impl Color {
             Color::Cmyk(ref cmyk) => Some(&cmyk.icc_profile),
             Color::Greyscale(ref gs) => Some(&gs.icc_profile),
             Color::SpotColor(_) => None,
+            Color::Lab { .. } | Color::CalRgb { .. } | Color::CalGray { .. } => None,
+            Color::Shading(_) | Color::Pattern(_) | Color::UncoloredPattern { .. } => None,
+        }
+    }
+
+    /// Returns the name of the device color space of the color
+    /// (`None` for CIE-based colors and patterns)
+    pub fn get_device_color_space(&self) -> Option<&'static str> {
+        match self {
+            Color::Rgb(_) => Some("DeviceRGB"),
+            Color::Cmyk(_) | Color::SpotColor(_) => Some("DeviceCMYK"),
+            Color::Greyscale(_) => Some("DeviceGray"),
+            _ => None,
+        }
+    }
+
+    /// Returns the name and definition of the color space resource that has to be
+    /// selected with `cs` / `CS` before using the color (`None` for device colors
+    /// and colored patterns, which don't need a `/ColorSpace` resource)
+    pub(crate) fn get_color_space_resource(&self) -> Option<(String, lopdf::Object)> {
+        use lopdf::Object::{Array, Name};
+
+        match self {
+            Color::UncoloredPattern { .. } => {
+                let color = self.get_uncolored_pattern_color()?;
+                let (base_id, base) = match color.get_cie_color_space() {
+                    Some(cs) => (cs.get_id(), cs.to_object()),
+                    None => {
+                        let name = color.get_device_color_space()?;
+                        (name.to_string(), Name(name.into()))
+                    }
+                };
+                Some((
+                    format!("Pattern{base_id}"),
+                    Array(vec![Name("Pattern".into()), base]),
+                ))
+            }
+            _ => self
+                .get_cie_color_space()
+                .map(|cs| (cs.get_id(), cs.to_object())),
+        }
+    }
+}
+
+/// Parameters of a CIE-based color space, written as an entry of the
+/// `/ColorSpace` resource dictionary
+#[derive(Debug, Clone, PartialEq)]
+pub enum CieColorSpace {
+    Lab {
+        white_point: [f32; 3],
+        range: [f32; 4],
+    },
+    CalRgb {
+        white_point: [f32; 3],
+        black_point: [f32; 3],
+        gamma: [f32; 3],
+        matrix: [f32; 9],
+    },
+    CalGray {
+        white_point: [f32; 3],
+        black_point: [f32; 3],
+        gamma: f32,
+    },
+}
+
+impl CieColorSpace {
+    /// Returns the resource name of the color space, colors with identical
+    /// color space parameters share the same name
+    pub fn get_id(&self) -> String {
+        use std::hash::{Hash, Hasher};
+
+        let (prefix, params) = match self {
+            CieColorSpace::Lab { white_point, range } => {
+                ("Lab", [&white_point[..], &range[..]].concat())
+            }
+            CieColorSpace::CalRgb {
+                white_point,
+                black_point,
+                gamma,
+                matrix,
+            } => (
+                "CalRGB",
+                [&white_point[..], &black_point[..], &gamma[..], &matrix[..]].concat(),
+            ),
+            CieColorSpace::CalGray {
+                white_point,
+                black_point,
+                gamma,
+            } => (
+                "CalGray",
+                [&white_point[..], &black_point[..], &[*gamma][..]].concat(),
+            ),
+        };
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for p in params {
+            p.to_bits().hash(&mut hasher);
         }
+        format!("{prefix}{:016x}", hasher.finish())
+    }
+
+    /// Returns the color space array, i.e. `[/Lab << /WhitePoint [...] /Range [...] >>]`
+    pub(crate) fn to_object(&self) -> lopdf::Object {
+        use lopdf::Object::{Array, Dictionary, Name, Real};
+
+        let real_array = |v: &[f32]| Array(v.iter().copied().map(Real).collect());
+
+        let (name, dict) = match self {
+            CieColorSpace::Lab { white_point, range } => (
+                "Lab",
+                lopdf::Dictionary::from_iter(vec![
+                    ("WhitePoint", real_array(white_point)),
+                    ("Range", real_array(range)),
+                ]),
+            ),
+            CieColorSpace::CalRgb {
+                white_point,
+                black_point,
+                gamma,
+                matrix,
+            } => (
+                "CalRGB",
+                lopdf::Dictionary::from_iter(vec![
+                    ("WhitePoint", real_array(white_point)),
+                    ("BlackPoint", real_array(black_point)),
+                    ("Gamma", real_array(gamma)),
+                    ("Matrix", real_array(matrix)),
+                ]),
+            ),
+            CieColorSpace::CalGray {
+                white_point,
+                black_point,
+                gamma,
+            } => (
+                "CalGray",
+                lopdf::Dictionary::from_iter(vec![
+                    ("WhitePoint", real_array(white_point)),
+                    ("BlackPoint", real_array(black_point)),
+                    ("Gamma", Real(*gamma)),
+                ]),
+            ),
+        };
+
+        Array(vec![Name(name.into()), Dictionary(dict)])
     }
 }
 
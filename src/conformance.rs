@@ -100,6 +100,10 @@ pub struct CustomPdfConformance {
     ///
     /// Default: __true__
     pub allows_jpeg_content: bool,
+    /// Does this standard allow embedding JPEG 2000 files?
+    ///
+    /// Default: __true__
+    pub allows_jpx_content: bool,
     /// Does this standard require XMP metadata to be set?
     ///
     /// Default: __true__
@@ -129,6 +133,7 @@ impl Default for CustomPdfConformance {
             allows_audio_content: false,
             allows_embedded_javascript: false,
             allows_jpeg_content: true,
+            allows_jpx_content: true,
             requires_xmp_metadata: false,
             allows_default_fonts: false,
             requires_icc_profile: false,
@@ -216,6 +221,21 @@ impl PdfConformance {
         }
     }
 
+    /// Does this conformance level allow JPEG 2000 images (`/JPXDecode`, PDF 1.5),
+    /// PDF/A-1 and the PDF/X-1a and PDF/X-3 standards are based on older PDF versions
+    pub fn is_jpx_content_allowed(&self) -> bool {
+        match *self {
+            PdfConformance::A1B_2005_PDF_1_4
+            | PdfConformance::A1A_2005_PDF_1_4
+            | PdfConformance::X1A_2001_PDF_1_3
+            | PdfConformance::X3_2002_PDF_1_3
+            | PdfConformance::X1A_2003_PDF_1_4
+            | PdfConformance::X3_2003_PDF_1_4 => false,
+            PdfConformance::Custom(ref c) => c.allows_jpx_content,
+            _ => true,
+        }
+    }
+
     /// Detects if the PDF must have XMP metadata
     /// if it has to conform to the given PDF Standard
     pub fn must_have_xmp_metadata(&self) -> bool {
@@ -1,4 +1,4 @@
-use crate::{FontId, Op, PdfPage};
+use crate::{FontId, Op};
 use allsorts::binary::read::ReadArray;
 use allsorts::tables::loca::LocaOffsets;
 use allsorts::tables::IndexToLocFormat;
@@ -176,6 +176,7 @@ pub struct ParsedFont {
     pub num_glyphs: u16,
     pub hhea_table: HheaTable,
     pub hmtx_data: Vec<u8>,
+    pub vhea_table: Option<HheaTable>,
     pub vmtx_data: Vec<u8>,
     pub maxp_table: MaxpTable,
     pub gsub_cache: Option<LayoutCache<GSUB>>,
@@ -242,45 +243,51 @@ impl SubsetFont {
 
 impl ParsedFont {
     /// Returns the glyph IDs used in the PDF file
-    pub(crate) fn get_used_glyph_ids(
-        &self,
-        font_id: &FontId,
-        pages: &[PdfPage],
-    ) -> BTreeMap<u16, char> {
+    pub(crate) fn get_used_glyph_ids(&self, font_id: &FontId, ops: &[&Op]) -> BTreeMap<u16, char> {
         enum CharsOrCodepoint {
             Chars(String),
             Cp(Vec<(u16, char)>),
         }
 
-        let chars_or_codepoints = pages
+        let chars_or_codepoints = ops
             .iter()
-            .flat_map(|p| {
-                p.ops.iter().filter_map(|s| match s {
-                    Op::WriteText { font, text, .. } => {
-                        if font_id == font {
-                            Some(CharsOrCodepoint::Chars(text.clone()))
-                        } else {
-                            None
-                        }
+            .filter_map(|s| match s {
+                Op::WriteText { font, text, .. } => {
+                    if font_id == font {
+                        Some(CharsOrCodepoint::Chars(text.clone()))
+                    } else {
+                        None
                     }
-                    Op::WriteCodepoints { font, cp, .. } => {
-                        if font_id == font {
-                            Some(CharsOrCodepoint::Cp(cp.clone()))
-                        } else {
-                            None
-                        }
+                }
+                Op::WriteCodepoints { font, cp, .. } => {
+                    if font_id == font {
+                        Some(CharsOrCodepoint::Cp(cp.clone()))
+                    } else {
+                        None
                     }
-                    Op::WriteCodepointsWithKerning { font, cpk, .. } => {
-                        if font_id == font {
-                            Some(CharsOrCodepoint::Cp(
-                                cpk.iter().map(|s| (s.1, s.2)).collect(),
-                            ))
-                        } else {
-                            None
-                        }
+                }
+                Op::WriteCodepointsWithKerning { font, cpk, .. } => {
+                    if font_id == font {
+                        Some(CharsOrCodepoint::Cp(
+                            cpk.iter().map(|s| (s.1, s.2)).collect(),
+                        ))
+                    } else {
+                        None
                     }
-                    _ => None,
-                })
+                }
+                Op::WriteShapedText { font, text, .. } => {
+                    if font_id == font {
+                        Some(CharsOrCodepoint::Cp(
+                            text.glyphs
+                                .iter()
+                                .map(|g| (g.glyph_id, g.text.chars().next().unwrap_or(' ')))
+                                .collect(),
+                        ))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
             })
             .collect::<Vec<_>>();
 
@@ -381,10 +388,13 @@ impl ParsedFont {
         })
     }
 
+    /// Generates the ToUnicode CMap, `clusters` overrides the text of glyphs that
+    /// represent more than one character (ligatures) or no character at all
     pub(crate) fn generate_cid_to_unicode_map(
         &self,
         font_id: &FontId,
         glyph_ids: &BTreeMap<u16, char>,
+        clusters: &BTreeMap<u16, String>,
     ) -> String {
         // current first bit of the glyph id (0x10 or 0x12) for example
         let mut cur_first_bit: u16 = 0_u16;
@@ -399,7 +409,13 @@ impl ParsedFont {
                 cur_first_bit = *glyph_id >> 8;
             }
 
-            current_cmap_block.push((*glyph_id, *unicode as u32));
+            let text = match clusters.get(glyph_id) {
+                Some(text) if text.is_empty() => continue,
+                Some(text) => text.clone(),
+                None => unicode.to_string(),
+            };
+
+            current_cmap_block.push((*glyph_id, text));
         }
 
         all_cmap_blocks.push(current_cmap_block);
@@ -416,28 +432,19 @@ impl ParsedFont {
         let mut current_high_gid = 0;
         let mut current_width_vec = Vec::new();
 
-        // scale the font width so that it sort-of fits into an 1000 unit square
-        let percentage_font_scaling = 1000.0 / (self.font_metrics.units_per_em as f32);
-
         for gid in glyph_ids.keys() {
-            let (width, _) = match self.get_glyph_size(*gid) {
-                Some(s) => s,
-                None => match self.get_space_width() {
-                    Some(w) => (w as i32, 0),
-                    None => (0, 0),
-                },
-            };
+            let width = self.get_pdf_glyph_width(*gid);
 
             if *gid == current_high_gid {
                 // subsequent GID
-                current_width_vec.push(Integer((width as f32 * percentage_font_scaling) as i64));
+                current_width_vec.push(Integer(width));
                 current_high_gid += 1;
             } else {
                 // non-subsequent GID
                 widths_list.push(Integer(current_low_gid as i64));
                 widths_list.push(Array(std::mem::take(&mut current_width_vec)));
 
-                current_width_vec.push(Integer((width as f32 * percentage_font_scaling) as i64));
+                current_width_vec.push(Integer(width));
                 current_low_gid = *gid;
                 current_high_gid = gid + 1;
             }
@@ -463,6 +470,66 @@ impl ParsedFont {
         */
     }
 
+    /// Returns the width of the glyph as written into the `W` array of the font
+    /// (scaled so that it sort-of fits into an 1000 unit square)
+    pub(crate) fn get_pdf_glyph_width(&self, glyph_id: u16) -> i64 {
+        let percentage_font_scaling = 1000.0 / (self.font_metrics.units_per_em as f32);
+        let width = match self.get_glyph_size(glyph_id) {
+            Some((w, _)) => w,
+            None => self.get_space_width().unwrap_or(0) as i32,
+        };
+        (width as f32 * percentage_font_scaling) as i64
+    }
+
+    /// Returns the vertical metrics of the used glyph IDs for the `W2` array of the font:
+    /// `gid [w1y vx vy ...]`, with the vertical advance `w1y` and the position vector
+    /// `vx vy` from the horizontal to the vertical origin (top center) of the glyph
+    pub(crate) fn get_normalized_vertical_widths(
+        &self,
+        glyph_ids: &BTreeMap<u16, char>,
+    ) -> Vec<lopdf::Object> {
+        let percentage_font_scaling = 1000.0 / (self.font_metrics.units_per_em as f32);
+        let scale = |v: f32| Integer((v * percentage_font_scaling) as i64);
+
+        let mut widths_list = Vec::new();
+        let mut current_low_gid = 0;
+        let mut current_high_gid = 0;
+        let mut current_width_vec = Vec::new();
+
+        for gid in glyph_ids.keys() {
+            let (advance, origin_y) = self.get_vertical_metrics(*gid);
+            let width = self.get_pdf_glyph_width(*gid);
+
+            if *gid != current_high_gid {
+                if !current_width_vec.is_empty() {
+                    widths_list.push(Integer(current_low_gid as i64));
+                    widths_list.push(Array(std::mem::take(&mut current_width_vec)));
+                }
+                current_low_gid = *gid;
+                current_high_gid = *gid;
+            }
+
+            current_width_vec.push(scale(-(advance as f32)));
+            current_width_vec.push(Integer(width / 2));
+            current_width_vec.push(scale(origin_y as f32));
+            current_high_gid = current_high_gid.wrapping_add(1);
+        }
+
+        if !current_width_vec.is_empty() {
+            widths_list.push(Integer(current_low_gid as i64));
+            widths_list.push(Array(current_width_vec));
+        }
+
+        widths_list
+    }
+
+    /// Returns the vertical advance of the glyph as written into the `W2` array of the font
+    pub(crate) fn get_pdf_glyph_vertical_advance(&self, glyph_id: u16) -> i64 {
+        let percentage_font_scaling = 1000.0 / (self.font_metrics.units_per_em as f32);
+        let (advance, _) = self.get_vertical_metrics(glyph_id);
+        (advance as f32 * percentage_font_scaling) as i64
+    }
+
     /// Returns the maximum height in UNSCALED units of the used glyph IDs
     pub(crate) fn get_max_height(&self, glyph_ids: &BTreeMap<u16, char>) -> i64 {
         let mut max_height = 0;
@@ -486,6 +553,40 @@ impl ParsedFont {
     }
 }
 
+#[test]
+fn test_normalized_vertical_widths() {
+    let font = ParsedFont::from_bytes(
+        include_bytes!("../examples/assets/fonts/RobotoMedium.ttf"),
+        0,
+    )
+    .unwrap();
+    // Roboto has no vertical metrics: the advance is the ascender (1900) minus the
+    // descender (-500), the vertical origin is at the ascender (2048 units per em)
+    let [a, b, d] = ['A', 'B', 'D'].map(|c| font.lookup_glyph_index(c as u32).unwrap());
+    assert_eq!((b, d), (a + 1, a + 3));
+    assert_eq!(font.get_vertical_metrics(a), (2400, 1900));
+    assert_eq!(font.get_pdf_glyph_vertical_advance(a), 1171);
+
+    let glyph_ids = BTreeMap::from([(a, 'A'), (b, 'B'), (d, 'D')]);
+    let half_width = |gid: u16| Integer(font.get_pdf_glyph_width(gid) / 2);
+    assert_eq!(
+        font.get_normalized_vertical_widths(&glyph_ids),
+        vec![
+            Integer(a as i64),
+            Array(vec![
+                Integer(-1171),
+                half_width(a),
+                Integer(927),
+                Integer(-1171),
+                half_width(b),
+                Integer(927),
+            ]),
+            Integer(d as i64),
+            Array(vec![Integer(-1171), half_width(d), Integer(927)]),
+        ]
+    );
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 #[repr(C, u8)]
 pub enum GlyphOutlineOperation {
@@ -635,12 +736,12 @@ impl ParsedFont {
         let second_font_file = second_scope.read::<FontData<'_>>().ok()?;
         let second_provider = second_font_file.table_provider(font_index).ok()?;
 
-        let font_data_impl = allsorts::font::Font::new(second_provider).ok()?;
+        let mut font_data_impl = allsorts::font::Font::new(second_provider).ok()?;
 
         // required for font layout: gsub_cache, gpos_cache and gdef_table
-        let gsub_cache = None; // font_data_impl.gsub_cache().ok().and_then(|s| s);
-        let gpos_cache = None; // font_data_impl.gpos_cache().ok().and_then(|s| s);
-        let opt_gdef_table = None; // font_data_impl.gdef_table().ok().and_then(|o| o);
+        let gsub_cache = font_data_impl.gsub_cache().ok().and_then(|s| s);
+        let gpos_cache = font_data_impl.gpos_cache().ok().and_then(|s| s);
+        let opt_gdef_table = font_data_impl.gdef_table().ok().and_then(|o| o);
         let num_glyphs = font_data_impl.num_glyphs();
 
         let cmap_subtable = ReadScope::new(font_data_impl.cmap_subtable_data());
@@ -683,6 +784,12 @@ impl ParsedFont {
                 num_h_metrics: 0,
             });
 
+        // the vhea table has the same layout as the hhea table
+        let vhea_table = provider
+            .table_data(tag::VHEA)
+            .ok()
+            .and_then(|vhea_data| ReadScope::new(&vhea_data?).read::<HheaTable>().ok());
+
         let font_metrics = FontMetrics::from_bytes(font_bytes, font_index);
 
         // not parsing glyph outlines can save lots of memory
@@ -720,6 +827,7 @@ impl ParsedFont {
             num_glyphs,
             hhea_table,
             hmtx_data,
+            vhea_table,
             vmtx_data,
             maxp_table,
             gsub_cache,
@@ -764,6 +872,45 @@ impl ParsedFont {
             .unwrap_or_default()
     }
 
+    /// Returns the vertical advance and the y coordinate of the vertical origin
+    /// (top center) of a glyph (unscaled units). Falls back to the ascender and
+    /// descender for fonts without vertical metrics.
+    pub fn get_vertical_metrics(&self, glyph_index: u16) -> (u16, i32) {
+        let ascender = self.font_metrics.ascender as i32;
+        let default_advance = (ascender - self.font_metrics.descender as i32).max(0) as u16;
+        let Some(vhea) = self.vhea_table.as_ref() else {
+            return (default_advance, ascender);
+        };
+
+        // vmtx: long metrics (advance height, top side bearing), followed by the
+        // top side bearings of the glyphs that use the last advance height
+        let read_u16 = |pos: usize| {
+            let bytes = self.vmtx_data.get(pos..pos + 2)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let num_metrics = vhea.num_h_metrics as usize;
+        let gid = glyph_index as usize;
+        let (advance, tsb) = if gid < num_metrics {
+            (read_u16(gid * 4), read_u16(gid * 4 + 2))
+        } else {
+            (
+                read_u16(num_metrics.saturating_sub(1) * 4),
+                read_u16(num_metrics * 4 + (gid - num_metrics) * 2),
+            )
+        };
+        let Some(advance) = advance else {
+            return (default_advance, ascender);
+        };
+
+        // the vertical origin is above the glyph by the top side bearing
+        let origin_y = match (tsb, self.glyph_records_decoded.get(&glyph_index)) {
+            (Some(tsb), Some(g)) => g.bounding_box.max_y as i32 + tsb as i16 as i32,
+            _ => ascender,
+        };
+
+        (advance, origin_y)
+    }
+
     // get the x and y size of a glyph (unscaled units)
     pub fn get_glyph_size(&self, glyph_index: u16) -> Option<(i32, i32)> {
         let g = self.glyph_records_decoded.get(&glyph_index)?;
@@ -781,8 +928,7 @@ impl ParsedFont {
 }
 
 type GlyphId = u16;
-type UnicodeCodePoint = u32;
-type CmapBlock = Vec<(GlyphId, UnicodeCodePoint)>;
+type CmapBlock = Vec<(GlyphId, String)>;
 
 /// Generates a CMAP (character map) from valid cmap blocks
 fn generate_cid_to_unicode_map(face_name: String, all_cmap_blocks: Vec<CmapBlock>) -> String {
@@ -793,8 +939,14 @@ fn generate_cid_to_unicode_map(face_name: String, all_cmap_blocks: Vec<CmapBlock
         .filter(|block| !block.is_empty() || block.len() < 100)
     {
         cid_to_unicode_map.push_str(format!("{} beginbfchar\r\n", cmap_block.len()).as_str());
-        for (glyph_id, unicode) in cmap_block {
-            cid_to_unicode_map.push_str(format!("<{glyph_id:04x}> <{unicode:04x}>\n").as_str());
+        for (glyph_id, text) in cmap_block {
+            // the destination string is UTF-16BE, so that ligatures and characters
+            // outside of the BMP can be mapped back to their text
+            let utf16 = text
+                .encode_utf16()
+                .map(|u| format!("{u:04x}"))
+                .collect::<String>();
+            cid_to_unicode_map.push_str(format!("<{glyph_id:04x}> <{utf16}>\n").as_str());
         }
         cid_to_unicode_map.push_str("endbfchar\r\n");
     }
@@ -0,0 +1,241 @@
+//! PDF function objects (used by shadings, transfer functions, black generation, etc.)
+
+use lopdf::Object::{Array, Dictionary, Integer, Real, Reference};
+
+/// Function mapping `m` input values to `n` output values,
+/// __see PDF Reference Page 166__ - Functions
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdfFunction {
+    /// Type 0: Uses a table of sample values, interpolated linearly
+    Sampled {
+        /// Input ranges, `[min_0, max_0, min_1, max_1, ...]`
+        domain: Vec<f32>,
+        /// Output ranges, `[min_0, max_0, min_1, max_1, ...]`
+        range: Vec<f32>,
+        /// Number of samples in each input dimension
+        size: Vec<u32>,
+        /// Number of bits per sample: 1, 2, 4, 8, 12, 16, 24 or 32
+        bits_per_sample: u8,
+        /// Packed sample values (big-endian, rows padded to the next byte)
+        samples: Vec<u8>,
+        /// Maps the inputs to the sample table indices (default: `[0, size_i - 1]`)
+        encode: Option<Vec<f32>>,
+        /// Maps the samples to the output range (default: same as `range`)
+        decode: Option<Vec<f32>>,
+    },
+    /// Type 2: Exponential interpolation `y = c0 + x^exponent * (c1 - c0)`
+    Exponential {
+        /// Input range, `[min, max]`
+        domain: [f32; 2],
+        /// Output at `x = 0.0` (default: `[0.0]`)
+        c0: Vec<f32>,
+        /// Output at `x = 1.0` (default: `[1.0]`)
+        c1: Vec<f32>,
+        /// Interpolation exponent (1.0 = linear)
+        exponent: f32,
+    },
+    /// Type 3: Combines several 1-input functions into one function,
+    /// each covering a subdomain (i.e. multi-stop gradients)
+    Stitching {
+        /// Input range, `[min, max]`
+        domain: [f32; 2],
+        /// Functions to stitch together, must have the same number of outputs
+        functions: Vec<PdfFunction>,
+        /// `functions.len() - 1` numbers in increasing order, dividing the domain into subdomains
+        bounds: Vec<f32>,
+        /// Maps each subdomain to the domain of its function, `2 * functions.len()` numbers
+        encode: Vec<f32>,
+    },
+    /// Type 4: PostScript calculator function, i.e. `{ 360 mul sin 2 div exch 360 mul sin 2 div add }`
+    PostScript {
+        /// Input ranges, `[min_0, max_0, min_1, max_1, ...]`
+        domain: Vec<f32>,
+        /// Output ranges, `[min_0, max_0, min_1, max_1, ...]`
+        range: Vec<f32>,
+        /// Code of the function (the enclosing braces are added if missing)
+        code: String,
+    },
+}
+
+impl PdfFunction {
+    /// Returns the function type (0, 2, 3 or 4)
+    pub fn get_type(&self) -> i64 {
+        match self {
+            PdfFunction::Sampled { .. } => 0,
+            PdfFunction::Exponential { .. } => 2,
+            PdfFunction::Stitching { .. } => 3,
+            PdfFunction::PostScript { .. } => 4,
+        }
+    }
+
+    /// Identity function `y = x` on the domain `[0.0, 1.0]`
+    pub fn identity() -> Self {
+        Self::linear(vec![0.0], vec![1.0])
+    }
+
+    /// Linear interpolation between `c0` and `c1` on the domain `[0.0, 1.0]`
+    pub fn linear(c0: Vec<f32>, c1: Vec<f32>) -> Self {
+        PdfFunction::Exponential {
+            domain: [0.0, 1.0],
+            c0,
+            c1,
+            exponent: 1.0,
+        }
+    }
+
+    /// Serializes the function. Type 2 and 3 functions are written as inline
+    /// dictionaries, type 0 and 4 functions are added to the document as streams.
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        let real_array = |v: &[f32]| Array(v.iter().copied().map(Real).collect());
+
+        match self {
+            PdfFunction::Sampled {
+                domain,
+                range,
+                size,
+                bits_per_sample,
+                samples,
+                encode,
+                decode,
+            } => {
+                let mut dict = lopdf::Dictionary::from_iter(vec![
+                    ("FunctionType", Integer(self.get_type())),
+                    ("Domain", real_array(domain)),
+                    ("Range", real_array(range)),
+                    (
+                        "Size",
+                        Array(size.iter().map(|s| Integer(*s as i64)).collect()),
+                    ),
+                    ("BitsPerSample", Integer(*bits_per_sample as i64)),
+                ]);
+                if let Some(encode) = encode {
+                    dict.set("Encode", real_array(encode));
+                }
+                if let Some(decode) = decode {
+                    dict.set("Decode", real_array(decode));
+                }
+                let mut stream = lopdf::Stream::new(dict, samples.clone()).with_compression(true);
+                let _ = stream.compress();
+                Reference(doc.add_object(stream))
+            }
+            PdfFunction::Exponential {
+                domain,
+                c0,
+                c1,
+                exponent,
+            } => Dictionary(lopdf::Dictionary::from_iter(vec![
+                ("FunctionType", Integer(self.get_type())),
+                ("Domain", real_array(domain)),
+                ("C0", real_array(c0)),
+                ("C1", real_array(c1)),
+                ("N", Real(*exponent)),
+            ])),
+            PdfFunction::Stitching {
+                domain,
+                functions,
+                bounds,
+                encode,
+            } => {
+                let functions = functions.iter().map(|f| f.to_object(doc)).collect();
+                Dictionary(lopdf::Dictionary::from_iter(vec![
+                    ("FunctionType", Integer(self.get_type())),
+                    ("Domain", real_array(domain)),
+                    ("Functions", Array(functions)),
+                    ("Bounds", real_array(bounds)),
+                    ("Encode", real_array(encode)),
+                ]))
+            }
+            PdfFunction::PostScript {
+                domain,
+                range,
+                code,
+            } => {
+                let code = code.trim();
+                let code = if code.starts_with('{') {
+                    code.to_string()
+                } else {
+                    format!("{{ {code} }}")
+                };
+                let dict = lopdf::Dictionary::from_iter(vec![
+                    ("FunctionType", Integer(self.get_type())),
+                    ("Domain", real_array(domain)),
+                    ("Range", real_array(range)),
+                ]);
+                let stream = lopdf::Stream::new(dict, code.into_bytes());
+                Reference(doc.add_object(stream))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_function_to_object() {
+    use lopdf::Object;
+
+    let mut doc = lopdf::Document::new();
+
+    // type 2 and 3 are written inline
+    let linear = PdfFunction::linear(vec![0.0, 0.0, 0.0], vec![1.0, 0.5, 0.0]);
+    let dict = match linear.to_object(&mut doc) {
+        Dictionary(d) => d,
+        o => panic!("expected dictionary, got {o:?}"),
+    };
+    assert_eq!(dict.get(b"FunctionType").unwrap(), &Integer(2));
+    assert_eq!(
+        dict.get(b"C1").unwrap(),
+        &Array(vec![Real(1.0), Real(0.5), Real(0.0)])
+    );
+    assert_eq!(dict.get(b"N").unwrap(), &Real(1.0));
+
+    let stitching = PdfFunction::Stitching {
+        domain: [0.0, 1.0],
+        functions: vec![linear.clone(), linear],
+        bounds: vec![0.5],
+        encode: vec![0.0, 1.0, 0.0, 1.0],
+    };
+    let dict = match stitching.to_object(&mut doc) {
+        Dictionary(d) => d,
+        o => panic!("expected dictionary, got {o:?}"),
+    };
+    assert_eq!(dict.get(b"FunctionType").unwrap(), &Integer(3));
+    assert_eq!(dict.get(b"Bounds").unwrap(), &Array(vec![Real(0.5)]));
+    match dict.get(b"Functions").unwrap() {
+        Array(a) => assert_eq!(a.len(), 2),
+        o => panic!("expected array, got {o:?}"),
+    }
+
+    // type 0 and 4 are streams
+    let sampled = PdfFunction::Sampled {
+        domain: vec![0.0, 1.0],
+        range: vec![0.0, 1.0],
+        size: vec![3],
+        bits_per_sample: 8,
+        samples: vec![0, 128, 255],
+        encode: None,
+        decode: Some(vec![1.0, 0.0]),
+    };
+    let stream = match sampled.to_object(&mut doc) {
+        Reference(id) => doc.get_object(id).unwrap().as_stream().unwrap().clone(),
+        o => panic!("expected reference, got {o:?}"),
+    };
+    assert_eq!(stream.dict.get(b"FunctionType").unwrap(), &Integer(0));
+    assert_eq!(stream.dict.get(b"Size").unwrap(), &Array(vec![Integer(3)]));
+    assert_eq!(
+        stream.dict.get(b"Decode").unwrap(),
+        &Array(vec![Real(1.0), Real(0.0)])
+    );
+    assert!(stream.dict.get(b"Encode").is_err());
+
+    let postscript = PdfFunction::PostScript {
+        domain: vec![0.0, 1.0],
+        range: vec![0.0, 1.0],
+        code: "1 exch sub".to_string(),
+    };
+    let stream = match postscript.to_object(&mut doc) {
+        Reference(id) => doc.get_object(id).unwrap().as_stream().unwrap().clone(),
+        o => panic!("expected reference, got {o:?}"),
+    };
+    assert_eq!(stream.dict.get(b"FunctionType").unwrap(), &Integer(4));
+    assert_eq!(stream.content, b"{ 1 exch sub }".to_vec());
+    assert!(matches!(stream.dict.get(b"Range"), Ok(Object::Array(_))));
+}
@@ -1,5 +1,5 @@
 use crate::units::{Mm, Pt};
-use crate::FontId;
+use crate::{Color, ColorSpace, FontId, Op, PdfFunction};
 use lopdf::Dictionary as LoDictionary;
 use std::collections::HashSet;
 
@@ -85,6 +85,14 @@ impl Rect {
         vec![(tl, false), (tr, false), (br, false), (bl, false)]
     }
 
+    /// Returns the rectangle as `[llx lly urx ury]`, as used for the `/BBox` of
+    /// forms and patterns
+    pub fn to_bbox_array(&self) -> Vec<lopdf::Object> {
+        let ll = self.lower_left();
+        let ur = self.upper_right();
+        vec![ll.x.0.into(), ll.y.0.into(), ur.x.0.into(), ur.y.0.into()]
+    }
+
     pub fn to_array(&self) -> Vec<lopdf::Object> {
         vec![
             (self.x.0.round() as i64).into(),
@@ -363,6 +371,17 @@ impl TextRenderingMode {
     }
 }
 
+/// Direction in which the glyphs of a text section advance
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WritingMode {
+    /// Glyphs advance from left to right (default)
+    #[default]
+    Horizontal,
+    /// Glyphs advance from top to bottom, using the vertical metrics of the font
+    /// (i.e. Japanese tategaki). The text cursor is the top center of the first glyph.
+    Vertical,
+}
+
 /// __See PDF Reference (Page 216)__ - Line cap (ending) style
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LineCapStyle {
@@ -589,7 +608,14 @@ pub struct ExtendedGraphicsState {
     pub(crate) text_knockout: bool,
 }
 
-pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
+/// Serializes the graphics state into an `ExtGState` dictionary. Functions and
+/// halftones that are streams (function types 0 and 4, halftone types 6, 10 and 16)
+/// are added to `doc` and referenced. Fails if a transfer function or halftone is
+/// invalid, see `ExtendedGraphicsState::validate`.
+pub fn extgstate_to_dict(
+    val: &ExtendedGraphicsState,
+    doc: &mut lopdf::Document,
+) -> Result<LoDictionary, String> {
     use lopdf::Object::*;
     use std::string::String;
 
@@ -637,11 +663,11 @@ pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
     }
 
     if val.changed_fields.contains(CURRENT_FILL_ALPHA) {
-        gs_operations.push(("CA".to_string(), Real(val.current_fill_alpha)));
+        gs_operations.push(("ca".to_string(), Real(val.current_fill_alpha)));
     }
 
     if val.changed_fields.contains(CURRENT_STROKE_ALPHA) {
-        gs_operations.push(("ca".to_string(), Real(val.current_stroke_alpha)));
+        gs_operations.push(("CA".to_string(), Real(val.current_stroke_alpha)));
     }
 
     if val.changed_fields.contains(BLEND_MODE) {
@@ -670,56 +696,56 @@ pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
         }
     }
 
-    // todo: transfer functions, halftone functions,
-    // black generation, undercolor removal
-    // these types cannot yet be converted into lopdf::Objects,
-    // need to implement Into<Object> for them
+    // functions and halftones of type 0 / 4 / 6 / 10 / 16 are streams,
+    // so they are added to the document and referenced here
+    val.validate()?;
 
     if val.changed_fields.contains(BLACK_GENERATION) {
         if let Some(ref black_generation) = val.black_generation {
-            // TODO
+            gs_operations.push(("BG".to_string(), black_generation.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(BLACK_GENERATION_EXTRA) {
         if let Some(ref black_generation_extra) = val.black_generation_extra {
-            // TODO
+            gs_operations.push(("BG2".to_string(), black_generation_extra.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(UNDERCOLOR_REMOVAL) {
         if let Some(ref under_color_removal) = val.under_color_removal {
-            // TODO
+            gs_operations.push(("UCR".to_string(), under_color_removal.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(UNDERCOLOR_REMOVAL_EXTRA) {
         if let Some(ref under_color_removal_extra) = val.under_color_removal_extra {
-            // TODO
+            gs_operations.push(("UCR2".to_string(), under_color_removal_extra.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(TRANSFER_FUNCTION) {
-        if let Some(ref transfer_function) = val.transfer_function {
-            // TODO
+        if let Some(transfer_function) = val.transfer_function.as_ref() {
+            gs_operations.push(("TR".to_string(), transfer_function.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(TRANSFER_FUNCTION_EXTRA) {
-        if let Some(ref transfer_extra_function) = val.transfer_extra_function {
-            // TODO
+        if let Some(transfer_extra_function) = val.transfer_extra_function.as_ref() {
+            gs_operations.push(("TR2".to_string(), transfer_extra_function.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(HALFTONE_DICTIONARY) {
-        if let Some(ref halftone_dictionary) = val.halftone_dictionary {
-            // TODO
+        if let Some(halftone_dictionary) = val.halftone_dictionary.as_ref() {
+            gs_operations.push(("HT".to_string(), halftone_dictionary.to_object(doc)));
         }
     }
 
     if val.changed_fields.contains(SOFT_MASK) {
-        if let Some(ref soft_mask) = val.soft_mask {
-        } else {
+        // NOTE: a set soft mask references the Form XObject of its mask group,
+        // so the "SM" entry is added when the document is serialized
+        if val.soft_mask.is_none() {
             gs_operations.push(("SM".to_string(), Name("None".as_bytes().to_vec())));
         }
     }
@@ -730,7 +756,7 @@ pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
         gs_operations.push(("Type".to_string(), "ExtGState".into()));
     }
 
-    LoDictionary::from_iter(gs_operations)
+    Ok(LoDictionary::from_iter(gs_operations))
 }
 
 #[derive(Debug, Clone, Default)]
@@ -968,6 +994,26 @@ impl ExtendedGraphicsStateBuilder {
     }
 }
 
+impl ExtendedGraphicsState {
+    /// Checks the transfer functions, the halftone and the soft mask, see
+    /// `TransferFunction::validate`, `HalftoneType::validate` and `SoftMask::validate`
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(transfer_function) = self.transfer_function.as_ref() {
+            transfer_function.validate()?;
+        }
+        if let Some(transfer_extra_function) = self.transfer_extra_function.as_ref() {
+            transfer_extra_function.validate()?;
+        }
+        if let Some(halftone) = self.halftone_dictionary.as_ref() {
+            halftone.validate()?;
+        }
+        if let Some(soft_mask) = self.soft_mask.as_ref() {
+            soft_mask.validate()?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for ExtendedGraphicsState {
     /// Creates a default ExtGState dictionary. Useful for resetting
     fn default() -> Self {
@@ -1025,7 +1071,8 @@ impl OverprintMode {
 }
 
 /// Black generation calculates the amount of black to be used when trying to
-/// reproduce a particular color.
+/// reproduce a particular color. Custom functions are set with
+/// `BlackGenerationExtraFunction::Function`.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum BlackGenerationFunction {
     /// Regular black generation function
@@ -1049,8 +1096,34 @@ pub enum BlackGenerationFunction {
     WithUnderColorRemoval,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum BlackGenerationExtraFunction {}
+impl BlackGenerationFunction {
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            BlackGenerationFunction::Default | BlackGenerationFunction::WithUnderColorRemoval => {
+                PdfFunction::identity().to_object(doc)
+            }
+        }
+    }
+}
+
+/// __(PDF 1.3)__ Same as `BlackGenerationFunction`, but can reset the black generation
+/// to the default of the output device
+#[derive(Debug, PartialEq, Clone)]
+pub enum BlackGenerationExtraFunction {
+    /// Default black generation of the output device
+    Default,
+    /// Custom function, mapping `min(cyan, magenta, yellow)` to the amount of black
+    Function(PdfFunction),
+}
+
+impl BlackGenerationExtraFunction {
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            BlackGenerationExtraFunction::Default => lopdf::Object::Name("Default".into()),
+            BlackGenerationExtraFunction::Function(f) => f.to_object(doc),
+        }
+    }
+}
 
 /// See `BlackGenerationFunction`, too. Undercolor removal reduces the amounts
 /// of the cyan, magenta, and yellow components to compensate for the amount of
@@ -1061,19 +1134,102 @@ pub enum BlackGenerationExtraFunction {}
 /// components. It can simply return its k operand unchanged, or it can return 0.0
 /// (so that no color is removed), some fraction of the black amount, or even a
 /// negative amount, thereby adding to the total amount of colorant.
+///
+/// Custom functions are set with `UnderColorRemovalExtraFunction::Function`.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum UnderColorRemovalFunction {
+    /// Removes the full amount of black (returns its k operand unchanged)
     Default,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum UnderColorRemovalExtraFunction {}
+impl UnderColorRemovalFunction {
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            UnderColorRemovalFunction::Default => PdfFunction::identity().to_object(doc),
+        }
+    }
+}
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum TransferFunction {}
+/// __(PDF 1.3)__ Same as `UnderColorRemovalFunction`, but can reset the undercolor
+/// removal to the default of the output device
+#[derive(Debug, PartialEq, Clone)]
+pub enum UnderColorRemovalExtraFunction {
+    /// Default undercolor removal of the output device
+    Default,
+    /// Custom function, mapping the amount of black to the amount to subtract from c, m and y
+    Function(PdfFunction),
+}
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum TransferExtraFunction {}
+impl UnderColorRemovalExtraFunction {
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            UnderColorRemovalExtraFunction::Default => lopdf::Object::Name("Default".into()),
+            UnderColorRemovalExtraFunction::Function(f) => f.to_object(doc),
+        }
+    }
+}
+
+/// Transfer functions adjust the color components to compensate for the
+/// characteristics of the output device (i.e. dot gain)
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransferFunction {
+    /// Leaves the color components unchanged
+    Identity,
+    /// Single function, applied to all color components
+    Function(PdfFunction),
+    /// One function per colorant (cyan, magenta, yellow and black / red, green, blue and gray),
+    /// expects four functions
+    PerComponent(Vec<PdfFunction>),
+}
+
+impl TransferFunction {
+    /// Checks that per-component transfer functions have exactly four functions
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TransferFunction::PerComponent(fs) if fs.len() != 4 => Err(format!(
+                "per-component transfer function needs 4 functions, got {}",
+                fs.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            TransferFunction::Identity => lopdf::Object::Name("Identity".into()),
+            TransferFunction::Function(f) => f.to_object(doc),
+            TransferFunction::PerComponent(fs) => {
+                lopdf::Object::Array(fs.iter().map(|f| f.to_object(doc)).collect())
+            }
+        }
+    }
+}
+
+/// __(PDF 1.3)__ Same as `TransferFunction`, but can reset the transfer
+/// function to the default of the output device
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransferExtraFunction {
+    /// Default transfer function of the output device
+    Default,
+    Transfer(TransferFunction),
+}
+
+impl TransferExtraFunction {
+    /// See `TransferFunction::validate`
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TransferExtraFunction::Default => Ok(()),
+            TransferExtraFunction::Transfer(t) => t.validate(),
+        }
+    }
+
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        match self {
+            TransferExtraFunction::Default => lopdf::Object::Name("Default".into()),
+            TransferExtraFunction::Transfer(t) => t.to_object(doc),
+        }
+    }
+}
 
 /// In PDF 1.2, the graphics state includes a current halftone parameter,
 /// which determines the halftoning process to be used by the painting operators.
@@ -1097,21 +1253,40 @@ pub enum TransferExtraFunction {}
 /// Deserialized into Integer: 1, 5, 6, 10 or 16
 #[derive(Debug, PartialEq, Clone)]
 pub enum HalftoneType {
-    /// 1: Defines a single halftone screen by a frequency, angle, and spot function
+    /// 1: Defines a single halftone screen by a frequency (in lines per inch),
+    /// angle (in degrees), and spot function
     Type1(f32, f32, SpotFunction),
     /// 5: Defines an arbitrary number of halftone screens, one for each colorant or
     /// color component (including both primary and spot colorants).
-    /// The keys in this dictionary are names of colorants; the values are halftone
-    /// dictionaries of other types, each defining the halftone screen for a single colorant.
-    Type5(Vec<HalftoneType>),
+    /// The keys are names of colorants (i.e. "Cyan", "Black" and the required "Default");
+    /// the values are halftones of other types, each defining the halftone screen for a
+    /// single colorant.
+    Type5(Vec<(String, HalftoneType)>),
     /// 6: Defines a single halftone screen by a threshold array containing 8-bit sample values.
-    Type6(Vec<u8>),
+    Type6 {
+        width: u32,
+        height: u32,
+        /// `width * height` threshold values
+        thresholds: Vec<u8>,
+    },
     /// 10: Defines a single halftone screen by a threshold array containing 8-bit sample values,
     /// representing a halftone cell that may have a nonzero screen angle.
-    Type10(Vec<u8>),
+    Type10 {
+        /// Side of the first square of the cell
+        x_square: u32,
+        /// Side of the second square of the cell
+        y_square: u32,
+        /// `x_square^2 + y_square^2` threshold values
+        thresholds: Vec<u8>,
+    },
     /// 16: __(PDF 1.3)__ Defines a single halftone screen by a threshold array containing 16-bit
     /// sample values, representing a halftone cell that may have a nonzero screen angle.
-    Type16(Vec<u16>),
+    Type16 {
+        width: u32,
+        height: u32,
+        /// `width * height` threshold values
+        thresholds: Vec<u16>,
+    },
 }
 
 impl HalftoneType {
@@ -1120,12 +1295,120 @@ impl HalftoneType {
         use self::HalftoneType::*;
         match *self {
             Type1(_, _, _) => 1,
-            Type5(_) => 5, /* this type does not actually exist, todo */
-            Type6(_) => 6,
-            Type10(_) => 10,
-            Type16(_) => 16,
+            Type5(_) => 5,
+            Type6 { .. } => 6,
+            Type10 { .. } => 10,
+            Type16 { .. } => 16,
         }
     }
+
+    /// Checks that type 5 halftones have a "Default" entry and don't nest other
+    /// type 5 halftones, and that threshold halftones (types 6, 10 and 16) have one
+    /// threshold value per cell
+    pub fn validate(&self) -> Result<(), String> {
+        let check_thresholds = |len: usize, expected: u64| {
+            if len as u64 == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "type {} halftone needs {expected} threshold values, got {len}",
+                    self.get_type()
+                ))
+            }
+        };
+
+        match self {
+            HalftoneType::Type1(..) => Ok(()),
+            HalftoneType::Type5(colorants) => {
+                if !colorants.iter().any(|(colorant, _)| colorant == "Default") {
+                    return Err("type 5 halftone has no \"Default\" entry".to_string());
+                }
+                if colorants
+                    .iter()
+                    .any(|(_, halftone)| matches!(halftone, HalftoneType::Type5(_)))
+                {
+                    return Err("type 5 halftones can't be nested".to_string());
+                }
+                colorants
+                    .iter()
+                    .try_for_each(|(_, halftone)| halftone.validate())
+            }
+            HalftoneType::Type6 {
+                width,
+                height,
+                thresholds,
+            } => check_thresholds(thresholds.len(), *width as u64 * *height as u64),
+            HalftoneType::Type10 {
+                x_square,
+                y_square,
+                thresholds,
+            } => check_thresholds(
+                thresholds.len(),
+                (*x_square as u64).pow(2) + (*y_square as u64).pow(2),
+            ),
+            HalftoneType::Type16 {
+                width,
+                height,
+                thresholds,
+            } => check_thresholds(thresholds.len(), *width as u64 * *height as u64),
+        }
+    }
+
+    /// Serializes the halftone, types 6, 10 and 16 are added to the document as streams
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        use lopdf::Object::*;
+
+        let mut dict = LoDictionary::from_iter(vec![
+            ("Type", Name("Halftone".into())),
+            ("HalftoneType", Integer(self.get_type())),
+        ]);
+
+        let data = match self {
+            HalftoneType::Type1(frequency, angle, spot_function) => {
+                dict.set("Frequency", Real(*frequency));
+                dict.set("Angle", Real(*angle));
+                dict.set("SpotFunction", Name(spot_function.get_id().into()));
+                return Dictionary(dict);
+            }
+            HalftoneType::Type5(colorants) => {
+                for (colorant, halftone) in colorants.iter() {
+                    dict.set(colorant.clone(), halftone.to_object(doc));
+                }
+                return Dictionary(dict);
+            }
+            HalftoneType::Type6 {
+                width,
+                height,
+                thresholds,
+            } => {
+                dict.set("Width", Integer(*width as i64));
+                dict.set("Height", Integer(*height as i64));
+                thresholds.clone()
+            }
+            HalftoneType::Type10 {
+                x_square,
+                y_square,
+                thresholds,
+            } => {
+                dict.set("Xsquare", Integer(*x_square as i64));
+                dict.set("Ysquare", Integer(*y_square as i64));
+                thresholds.clone()
+            }
+            HalftoneType::Type16 {
+                width,
+                height,
+                thresholds,
+            } => {
+                dict.set("Width", Integer(*width as i64));
+                dict.set("Height", Integer(*height as i64));
+                thresholds.iter().flat_map(|t| t.to_be_bytes()).collect()
+            }
+        };
+
+        let mut stream = lopdf::Stream::new(dict, data).with_compression(true);
+        let _ = stream.compress();
+        Reference(doc.add_object(stream))
+    }
 }
 
 /// Spot functions, Table 6.1, Page 489 in Pdf Reference v1.7
@@ -1201,6 +1484,36 @@ pub enum SpotFunction {
     Diamond,
 }
 
+impl SpotFunction {
+    /// Returns the predefined name of the spot function
+    pub fn get_id(&self) -> &'static str {
+        use self::SpotFunction::*;
+        match self {
+            SimpleDot => "SimpleDot",
+            InvertedSimpleDot => "InvertedSimpleDot",
+            DoubleDot => "DoubleDot",
+            InvertedDoubleDot => "InvertedDoubleDot",
+            CosineDot => "CosineDot",
+            Double => "Double",
+            InvertedDouble => "InvertedDouble",
+            Line => "Line",
+            LineX => "LineX",
+            LineY => "LineY",
+            Round => "Round",
+            Ellipse => "Ellipse",
+            EllipseA => "EllipseA",
+            InvertedEllipseA => "InvertedEllipseA",
+            EllipseB => "EllipseB",
+            EllipseC => "EllipseC",
+            InvertedEllipseC => "InvertedEllipseC",
+            Square => "Square",
+            Cross => "Cross",
+            Rhomboid => "Rhomboid",
+            Diamond => "Diamond",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum BlendMode {
     Seperable(SeperableBlendMode),
@@ -1561,18 +1874,96 @@ impl RenderingIntent {
     }
 }
 
-/// A soft mask is used for transparent images such as PNG with an alpha component
-/// The bytes range from 0xFF (opaque) to 0x00 (transparent). The alpha channel of a
-/// PNG image have to be sorted out.
-/// Can also be used for Vignettes, etc.
-/// Beware of color spaces!
+/// A soft mask defines the opacity of everything painted while the graphics state is active.
+/// The mask values are taken from a transparency group (a Form XObject painted with `ops`),
+/// either from its alpha channel or from its luminosity - for example, a black-to-white
+/// gradient luminosity mask fades an image into the background. Can also be used for
+/// vignettes, etc.
 /// __See PDF Reference Page 545__ - Soft masks
 #[derive(Debug, PartialEq, Clone)]
 pub struct SoftMask {
-    /// The data to be used as a soft mask
-    data: Vec<u8>,
-    /// Bits per component (1 for black / white, 8 for greyscale, up to 16)
-    bits_per_component: u8,
+    /// How the mask values are derived from the mask group
+    pub function: SoftMaskFunction,
+    /// Operations painting the transparency group that defines the mask
+    pub ops: Vec<Op>,
+    /// Bounding box of the mask group, everything outside of it is masked out
+    pub bbox: Rect,
+    /// (Luminosity masks only) Color of the backdrop the group is composited against,
+    /// default: black. Has to be an RGB, CMYK or greyscale color, which also determines
+    /// the color space of the group (default: DeviceRGB)
+    pub backdrop_color: Option<Color>,
+    /// Function mapping the computed mask values to the final opacity (default: identity).
+    /// Only `TransferFunction::Identity` and `TransferFunction::Function` are valid here.
+    pub transfer_function: Option<TransferFunction>,
+}
+
+impl SoftMask {
+    /// Creates a new luminosity mask: bright areas of the group are opaque, dark areas transparent
+    pub fn luminosity(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            function: SoftMaskFunction::GroupLuminosity,
+            ops,
+            bbox,
+            backdrop_color: None,
+            transfer_function: None,
+        }
+    }
+
+    /// Creates a new alpha mask: the opacity of the group is used as the mask
+    pub fn alpha(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            function: SoftMaskFunction::GroupAlpha,
+            ops,
+            bbox,
+            backdrop_color: None,
+            transfer_function: None,
+        }
+    }
+
+    /// Sets the backdrop color of a luminosity mask
+    #[inline]
+    pub fn with_backdrop_color(mut self, backdrop_color: Color) -> Self {
+        self.backdrop_color = Some(backdrop_color);
+        self
+    }
+
+    /// Sets the transfer function of the mask
+    #[inline]
+    pub fn with_transfer_function(mut self, transfer_function: TransferFunction) -> Self {
+        self.transfer_function = Some(transfer_function);
+        self
+    }
+
+    /// Checks that the backdrop color is an RGB, CMYK or greyscale color (it determines
+    /// the device color space of the mask group) and that the transfer function is not
+    /// a `TransferFunction::PerComponent` function (there is only a single mask value)
+    pub fn validate(&self) -> Result<(), String> {
+        match self.backdrop_color.as_ref() {
+            None
+            | Some(Color::Rgb(_) | Color::Cmyk(_) | Color::SpotColor(_) | Color::Greyscale(_)) => {}
+            Some(color) => {
+                return Err(format!(
+                    "soft mask backdrop color has to be an RGB, CMYK or greyscale color, got \
+                     {color:?}"
+                ));
+            }
+        }
+        if let Some(TransferFunction::PerComponent(_)) = self.transfer_function {
+            return Err(
+                "soft mask transfer function can't have one function per component".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the color space of the mask group
+    pub(crate) fn get_group_color_space(&self) -> ColorSpace {
+        match self.backdrop_color.as_ref() {
+            Some(Color::Cmyk(_)) | Some(Color::SpotColor(_)) => ColorSpace::Cmyk,
+            Some(Color::Greyscale(_)) => ColorSpace::Greyscale,
+            _ => ColorSpace::Rgb,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -1581,6 +1972,109 @@ pub enum SoftMaskFunction {
     /// In this function, the old (backdrop) color does not contribute to the result.
     /// This is the easies function, but may look bad at edges.
     GroupAlpha,
-    //
+    /// The mask values are computed from the luminosity of the group colors
     GroupLuminosity,
 }
+
+impl SoftMaskFunction {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            SoftMaskFunction::GroupAlpha => "Alpha",
+            SoftMaskFunction::GroupLuminosity => "Luminosity",
+        }
+    }
+}
+
+#[test]
+fn test_extgstate_functions_and_halftones() {
+    let sampled = PdfFunction::Sampled {
+        domain: vec![0.0, 1.0],
+        range: vec![0.0, 1.0],
+        size: vec![2],
+        bits_per_sample: 8,
+        samples: vec![0, 255],
+        encode: None,
+        decode: None,
+    };
+    let builder = || {
+        ExtendedGraphicsStateBuilder::new()
+            .with_transfer(Some(TransferFunction::Function(sampled.clone())))
+            .with_black_generation_extra(Some(BlackGenerationExtraFunction::Function(
+                PdfFunction::linear(vec![0.0], vec![0.5]),
+            )))
+    };
+    let gs = builder()
+        .with_halftone(Some(HalftoneType::Type6 {
+            width: 2,
+            height: 2,
+            thresholds: vec![0, 64, 128, 192],
+        }))
+        .build();
+
+    let mut doc = lopdf::Document::new();
+    let dict = extgstate_to_dict(&gs, &mut doc).unwrap();
+    assert!(matches!(dict.get(b"TR"), Ok(lopdf::Object::Reference(_))));
+    assert!(matches!(dict.get(b"BG2"), Ok(lopdf::Object::Dictionary(_))));
+    assert!(matches!(dict.get(b"HT"), Ok(lopdf::Object::Reference(_))));
+
+    // type 5 halftones without a "Default" entry are invalid
+    let gs = builder()
+        .with_halftone(Some(HalftoneType::Type5(vec![(
+            "Cyan".to_string(),
+            HalftoneType::Type1(60.0, 15.0, SpotFunction::Round),
+        )])))
+        .build();
+    let err = extgstate_to_dict(&gs, &mut doc).unwrap_err();
+    assert!(err.contains("Default"), "{err}");
+
+    let per_component = TransferFunction::PerComponent(vec![PdfFunction::identity(); 3]);
+    assert!(per_component.validate().is_err());
+    let per_component = TransferFunction::PerComponent(vec![PdfFunction::identity(); 4]);
+    assert!(per_component.validate().is_ok());
+
+    let halftone = HalftoneType::Type5(vec![
+        (
+            "Default".to_string(),
+            HalftoneType::Type1(60.0, 45.0, SpotFunction::Round),
+        ),
+        (
+            "Black".to_string(),
+            HalftoneType::Type1(60.0, 15.0, SpotFunction::Round),
+        ),
+    ]);
+    assert!(halftone.validate().is_ok());
+
+    // threshold halftones need one value per cell
+    let halftone = HalftoneType::Type6 {
+        width: 2,
+        height: 2,
+        thresholds: vec![0; 3],
+    };
+    assert!(halftone.validate().is_err());
+    let halftone = HalftoneType::Type10 {
+        x_square: 2,
+        y_square: 1,
+        thresholds: vec![0; 5],
+    };
+    assert!(halftone.validate().is_ok());
+    let halftone = HalftoneType::Type16 {
+        width: 3,
+        height: 2,
+        thresholds: vec![0; 5],
+    };
+    assert!(halftone.validate().is_err());
+    let halftone = HalftoneType::Type5(vec![("Default".to_string(), halftone)]);
+    assert!(halftone.validate().is_err());
+}
+
+#[test]
+fn test_extgstate_alpha_keys() {
+    // "CA" is the stroking alpha, "ca" the nonstroking (fill) alpha
+    let gs = ExtendedGraphicsStateBuilder::new()
+        .with_current_stroke_alpha(0.25)
+        .with_current_fill_alpha(0.75)
+        .build();
+    let dict = extgstate_to_dict(&gs, &mut lopdf::Document::new()).unwrap();
+    assert_eq!(dict.get(b"CA").unwrap(), &lopdf::Object::Real(0.25));
+    assert_eq!(dict.get(b"ca").unwrap(), &lopdf::Object::Real(0.75));
+}
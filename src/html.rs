@@ -1,4 +1,7 @@
-use crate::{BuiltinFont, Mm, Op, PdfDocument, PdfPage, PdfResources, Pt};
+use crate::{
+    BuiltinFont, Mm, Op, PaintMode, PathBuilder, PdfDocument, PdfPage, PdfResources, Pt,
+    WindingOrder,
+};
 pub use azul_core::dom::Dom;
 pub use azul_core::styled_dom::StyledDom;
 pub use azul_core::xml::{
@@ -268,7 +271,7 @@ fn fixup_xml(s: &str, doc: &mut PdfDocument, config: &XmlRenderOptions) -> Strin
         s.trim().to_string()
     };
 
-    let mut s = s.trim().to_string();
+    let mut s = mark_vertical_writing_mode(s.trim());
 
     for (k, image_bytes) in config.images.iter() {
         let opt_svg = std::str::from_utf8(&image_bytes)
@@ -300,8 +303,7 @@ fn fixup_xml(s: &str, doc: &mut PdfDocument, config: &XmlRenderOptions) -> Strin
                     }
                 };
 
-                let width = raw_image.width;
-                let height = raw_image.height;
+                let (width, height) = raw_image.get_oriented_size();
                 let image_xobject_id = doc.add_image(&raw_image);
                 ImageInfo {
                     original_id: k.clone(),
@@ -323,6 +325,462 @@ fn fixup_xml(s: &str, doc: &mut PdfDocument, config: &XmlRenderOptions) -> Strin
     s
 }
 
+/// Class that marks elements with `writing-mode: vertical-rl` (columns from right to left)
+const VERTICAL_RL_CLASS: &str = "__printpdf-vertical-rl";
+/// Class that marks elements with `writing-mode: vertical-lr` (columns from left to right)
+const VERTICAL_LR_CLASS: &str = "__printpdf-vertical-lr";
+/// Class that marks elements with `writing-mode: horizontal-tb` (i.e. inside of vertical text)
+const HORIZONTAL_TB_CLASS: &str = "__printpdf-horizontal-tb";
+
+/// The CSS engine doesn't know the `writing-mode` property: removes it from the
+/// inline styles and the `<style>` blocks and marks the elements with a class instead,
+/// which is checked when the text of the element is drawn.
+///
+/// Rules of `<style>` blocks are only applied to elements if the selector is a simple
+/// selector (`p`, `.class`, `#id`, `p.class`), the last matching rule wins. Inline
+/// styles take precedence over the rules.
+fn mark_vertical_writing_mode(s: &str) -> String {
+    let (s, rules) = remove_writing_mode_rules(s);
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s.as_str();
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let tag_len = get_tag_len(&rest[start..]);
+        out.push_str(&mark_vertical_writing_mode_tag(
+            &rest[start..start + tag_len],
+            &rules,
+        ));
+        rest = &rest[start + tag_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Returns the length of the tag at the start of the string, up to and including the
+/// closing `>` that is not inside of a quoted attribute value
+fn get_tag_len(s: &str) -> usize {
+    let mut quote = None;
+    for (pos, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return pos + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Returns the range of the value of an attribute in a tag (without quotes)
+fn get_attribute_range(tag: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let bytes = tag.as_bytes();
+    let is_space = |pos: usize| bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace());
+    // skip the tag name
+    let mut pos = 1;
+    while pos < bytes.len() && !is_space(pos) && !matches!(bytes[pos], b'>' | b'/') {
+        pos += 1;
+    }
+
+    loop {
+        while is_space(pos) || bytes.get(pos) == Some(&b'/') {
+            pos += 1;
+        }
+        let name_start = pos;
+        while pos < bytes.len() && !is_space(pos) && !matches!(bytes[pos], b'=' | b'>' | b'/') {
+            pos += 1;
+        }
+        if pos == name_start {
+            return None; // end of the tag
+        }
+        let attribute = &tag[name_start..pos];
+        while is_space(pos) {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&b'=') {
+            continue; // attribute without value
+        }
+        pos += 1;
+        while is_space(pos) {
+            pos += 1;
+        }
+
+        let range = match bytes.get(pos) {
+            Some(quote @ (b'"' | b'\'')) => {
+                let start = pos + 1;
+                let len = bytes[start..].iter().position(|b| b == quote)?;
+                pos = start + len + 1;
+                start..start + len
+            }
+            _ => {
+                let start = pos;
+                while pos < bytes.len() && !is_space(pos) && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                start..pos
+            }
+        };
+        if attribute.eq_ignore_ascii_case(name) {
+            return Some(range);
+        }
+    }
+}
+
+/// Replaces the value of an attribute, quotes values that were not quoted
+fn replace_attribute_value(tag: &mut String, range: std::ops::Range<usize>, value: &str) {
+    if tag[..range.start].ends_with(['"', '\'']) {
+        tag.replace_range(range, value);
+    } else {
+        tag.replace_range(range, &format!("\"{value}\""));
+    }
+}
+
+/// Returns the class that marks elements with the `writing-mode`, `None` for values
+/// that are not supported
+fn get_writing_mode_class(value: &str) -> Option<&'static str> {
+    match value.trim() {
+        "vertical-rl" | "tb-rl" | "tb" => Some(VERTICAL_RL_CLASS),
+        "vertical-lr" => Some(VERTICAL_LR_CLASS),
+        "horizontal-tb" | "lr-tb" | "lr" | "rl-tb" | "rl" => Some(HORIZONTAL_TB_CLASS),
+        _ => None,
+    }
+}
+
+/// Removes the `writing-mode` declarations from a declaration block, returns the
+/// remaining declarations and the class of the last `writing-mode` value
+fn remove_writing_mode(declarations: &str) -> Option<(String, Option<&'static str>)> {
+    let mut class = None;
+    let mut found = false;
+    let remaining = declarations
+        .split(';')
+        .filter(|declaration| {
+            let Some((property, value)) = declaration.split_once(':') else {
+                return true;
+            };
+            if property.trim() != "writing-mode" {
+                return true;
+            }
+            found = true;
+            class = get_writing_mode_class(value);
+            false
+        })
+        .collect::<Vec<_>>();
+
+    found.then(|| (remaining.join(";"), class))
+}
+
+/// Simple selector of a CSS rule that sets the `writing-mode`
+#[derive(Debug, Clone, PartialEq)]
+struct WritingModeRule {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    /// Class that marks the matching elements
+    class: &'static str,
+}
+
+impl WritingModeRule {
+    /// Parses a simple selector (`p`, `.class`, `#id`, `p.class`), returns `None`
+    /// for other selectors (combinators, pseudo-classes, attributes)
+    fn parse(selector: &str, class: &'static str) -> Option<Self> {
+        let selector = selector.trim();
+        let is_simple = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '#');
+        if selector.is_empty() || !selector.chars().all(is_simple) {
+            return None;
+        }
+
+        let mut rule = Self {
+            tag: None,
+            id: None,
+            classes: Vec::new(),
+            class,
+        };
+        let start = selector.find(['.', '#']).unwrap_or(selector.len());
+        if start > 0 {
+            rule.tag = Some(selector[..start].to_string());
+        }
+        let mut rest = &selector[start..];
+        while let Some(kind) = rest.chars().next() {
+            let end = rest[1..].find(['.', '#']).map_or(rest.len(), |end| end + 1);
+            let name = rest[1..end].to_string();
+            if name.is_empty() {
+                return None;
+            }
+            match kind {
+                '.' => rule.classes.push(name),
+                _ => rule.id = Some(name),
+            }
+            rest = &rest[end..];
+        }
+        Some(rule)
+    }
+
+    fn matches(&self, tag: &str, id: Option<&str>, classes: Option<&str>) -> bool {
+        let classes = classes.unwrap_or_default();
+        self.tag
+            .as_ref()
+            .is_none_or(|t| t.eq_ignore_ascii_case(tag))
+            && self.id.as_ref().is_none_or(|i| Some(i.as_str()) == id)
+            && self
+                .classes
+                .iter()
+                .all(|c| classes.split_whitespace().any(|class| class == c))
+    }
+}
+
+/// Removes the `writing-mode` declarations from the `<style>` blocks, returns the
+/// rules with a simple selector
+fn remove_writing_mode_rules(s: &str) -> (String, Vec<WritingModeRule>) {
+    let mut out = String::with_capacity(s.len());
+    let mut rules = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("<style") {
+        let css_start = start + get_tag_len(&rest[start..]);
+        let css_end = rest[css_start..]
+            .find("</style")
+            .map_or(rest.len(), |end| css_start + end);
+        out.push_str(&rest[..css_start]);
+        out.push_str(&remove_writing_mode_from_css(
+            &rest[css_start..css_end],
+            &mut rules,
+        ));
+        rest = &rest[css_end..];
+    }
+    out.push_str(rest);
+    (out, rules)
+}
+
+fn remove_writing_mode_from_css(css: &str, rules: &mut Vec<WritingModeRule>) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(end) = rest.find('}') {
+        let block = &rest[..end];
+        match block
+            .rsplit_once('{')
+            .and_then(|(prefix, declarations)| Some((prefix, remove_writing_mode(declarations)?)))
+        {
+            Some((prefix, (declarations, class))) => {
+                // the selectors of the rule (inside of an @media block)
+                let selectors = prefix.rsplit('{').next().unwrap_or(prefix);
+                if let Some(class) = class {
+                    rules.extend(
+                        selectors
+                            .split(',')
+                            .filter_map(|selector| WritingModeRule::parse(selector, class)),
+                    );
+                }
+                out.push_str(prefix);
+                out.push('{');
+                out.push_str(&declarations);
+            }
+            None => out.push_str(block),
+        }
+        out.push('}');
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn mark_vertical_writing_mode_tag(tag: &str, rules: &[WritingModeRule]) -> String {
+    if tag.starts_with("</") || tag.starts_with("<!") || tag.starts_with("<?") {
+        return tag.to_string();
+    }
+
+    let attribute = |name: &str| get_attribute_range(tag, name).map(|range| &tag[range]);
+    let (mut tag, class) = match get_attribute_range(tag, "style")
+        .and_then(|range| Some((remove_writing_mode(&tag[range.clone()])?, range)))
+    {
+        Some(((declarations, class), range)) => {
+            let mut tag = tag.to_string();
+            replace_attribute_value(&mut tag, range, &declarations);
+            (tag, class)
+        }
+        None => {
+            let name = tag[1..]
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default();
+            let class = rules
+                .iter()
+                .rev()
+                .find(|rule| rule.matches(name, attribute("id"), attribute("class")))
+                .map(|rule| rule.class);
+            (tag.to_string(), class)
+        }
+    };
+
+    if let Some(class) = class {
+        match get_attribute_range(&tag, "class") {
+            Some(range) => {
+                let value = format!("{} {class}", &tag[range.clone()]);
+                replace_attribute_value(&mut tag, range, &value);
+            }
+            None => {
+                let end = tag.len() - tag.ends_with("/>") as usize - tag.ends_with('>') as usize;
+                tag.insert_str(end, &format!(" class=\"{class}\""));
+            }
+        }
+    }
+
+    tag
+}
+
+/// Returns whether the text of the node is written vertically: `Some(true)` for
+/// columns from right to left, `Some(false)` for columns from left to right
+fn get_vertical_writing_mode(layout_result: &LayoutResult, rect_idx: NodeId) -> Option<bool> {
+    use azul_core::dom::IdOrClass;
+
+    let node_hierarchy = layout_result.styled_dom.node_hierarchy.as_container();
+    let node_data = layout_result.styled_dom.node_data.as_container();
+    let mut current = Some(rect_idx);
+    while let Some(node_id) = current {
+        for id_or_class in node_data[node_id].get_ids_and_classes().iter() {
+            if let IdOrClass::Class(class) = id_or_class {
+                match class.as_str() {
+                    VERTICAL_RL_CLASS => return Some(true),
+                    VERTICAL_LR_CLASS => return Some(false),
+                    HORIZONTAL_TB_CLASS => return None,
+                    _ => {}
+                }
+            }
+        }
+        current = node_hierarchy[node_id].parent_id();
+    }
+    None
+}
+
+/// Returns the height of the columns of vertical text: the text is laid out horizontally,
+/// so the height of its element is the height of the lines. Uses the height of the nearest
+/// element with a CSS `height` instead, or the rest of the page below the text.
+fn get_vertical_column_height(
+    layout_result: &LayoutResult,
+    rect_idx: NodeId,
+    page_height: Pt,
+) -> Pt {
+    let node_hierarchy = layout_result.styled_dom.node_hierarchy.as_container();
+    let node_data = layout_result.styled_dom.node_data.as_container();
+    let styled_nodes = layout_result.styled_dom.styled_nodes.as_container();
+    let css_property_cache = layout_result.styled_dom.get_css_property_cache();
+    let rects = layout_result.rects.as_ref();
+    let top = rects[rect_idx].position.get_static_offset().y;
+
+    let mut current = Some(rect_idx);
+    while let Some(node_id) = current {
+        let has_height = css_property_cache
+            .get_height(&node_data[node_id], &node_id, &styled_nodes[node_id].state)
+            .is_some();
+        if has_height {
+            let rect = &rects[node_id];
+            let bottom = rect.position.get_static_offset().y + rect.size.height;
+            return Pt((bottom - top).max(0.0));
+        }
+        current = node_hierarchy[node_id].parent_id();
+    }
+
+    Pt((page_height.0 - top).max(0.0))
+}
+
+/// Lays out the text of an element with a vertical writing mode: the text is shaped
+/// for vertical writing and broken into columns of the height of `rect`. The
+/// horizontal layout of the text is not used.
+fn vertical_text_to_ops(
+    font: &crate::ParsedFont,
+    font_id: &crate::FontId,
+    text: &str,
+    font_size: Pt,
+    column_width: Pt,
+    rect: &crate::Rect,
+    right_to_left: bool,
+) -> Vec<Op> {
+    let options = crate::TextShapingOptions::default().with_vertical(true);
+    let Ok(shaped) = font.shape_text(text, &options) else {
+        return Vec::new();
+    };
+    let scale = font_size.0 / shaped.units_per_em.max(1) as f32;
+    let column_height = rect.height.0.max(font_size.0);
+
+    let mut columns: Vec<Vec<crate::ShapedGlyph>> = vec![Vec::new()];
+    let mut height = 0.0;
+    for glyph in shaped.glyphs.iter() {
+        let advance = glyph.advance as f32 * scale;
+        let is_line_break = glyph.text == "\n";
+        let is_column_empty = columns.last().is_none_or(Vec::is_empty);
+        if is_line_break || (height + advance > column_height && !is_column_empty) {
+            columns.push(Vec::new());
+            height = 0.0;
+        }
+        if is_line_break {
+            continue;
+        }
+        if let Some(column) = columns.last_mut() {
+            column.push(glyph.clone());
+        }
+        height += advance;
+    }
+
+    let mut ops = vec![Op::SetWritingMode {
+        mode: crate::WritingMode::Vertical,
+    }];
+    for (i, glyphs) in columns.into_iter().enumerate() {
+        // the text cursor is the top center of the first glyph of the column
+        let offset = column_width.0 * (i as f32 + 0.5);
+        let x = if right_to_left {
+            rect.x.0 + rect.width.0 - offset
+        } else {
+            rect.x.0 + offset
+        };
+        ops.push(Op::SetTextMatrix {
+            matrix: crate::TextMatrix::Translate(Pt(x), Pt(rect.y.0 + rect.height.0)),
+        });
+        ops.push(Op::WriteShapedText {
+            font: font_id.clone(),
+            size: font_size,
+            text: crate::ShapedText {
+                glyphs,
+                units_per_em: shaped.units_per_em,
+                actual_text: None,
+                vertical: true,
+            },
+        });
+    }
+    ops
+}
+
+#[test]
+fn test_mark_vertical_writing_mode() {
+    assert_eq!(
+        mark_vertical_writing_mode("<p style='writing-mode: vertical-rl; color: red'>縦書き</p>"),
+        "<p style=' color: red' class=\"__printpdf-vertical-rl\">縦書き</p>"
+    );
+    assert_eq!(
+        mark_vertical_writing_mode("<div class=\"a\" style=\"writing-mode:vertical-lr\"/>"),
+        "<div class=\"a __printpdf-vertical-lr\" style=\"\"/>"
+    );
+    assert_eq!(
+        mark_vertical_writing_mode("<p data-style=\"writing-mode: tb\">a > b</p>"),
+        "<p data-style=\"writing-mode: tb\">a > b</p>"
+    );
+    assert_eq!(
+        mark_vertical_writing_mode("<p class=a style = \"writing-mode: vertical-rl\" hidden>"),
+        "<p class=\"a __printpdf-vertical-rl\" style = \"\" hidden>"
+    );
+
+    // rules of style blocks with simple selectors, inline styles take precedence
+    let html =
+        "<style>p.v, .c { writing-mode: vertical-rl; color: red } div p { writing-mode: tb }\
+        @media print { #x { writing-mode: horizontal-tb } }</style>\
+        <p class=\"v\">a</p><div class='c d'>b<span id=x>c</span></div><p>d</p>\
+        <p class=v style='writing-mode: vertical-lr'>e</p>";
+    assert_eq!(
+        mark_vertical_writing_mode(html),
+        "<style>p.v, .c { color: red } div p {}\
+        @media print { #x {} }</style>\
+        <p class=\"v __printpdf-vertical-rl\">a</p>\
+        <div class='c d __printpdf-vertical-rl'>b<span id=x class=\"__printpdf-horizontal-tb\">c</span></div>\
+        <p>d</p><p class=\"v __printpdf-vertical-lr\" style=''>e</p>"
+    );
+}
+
 fn fixup_xml_nodes(nodes: &[XmlNode]) -> Vec<XmlNode> {
     // TODO!
     nodes.to_vec()
@@ -415,6 +873,7 @@ fn displaylist_handle_rect(
 
     let positioned_rect = &layout_result.rects.as_ref()[rect_idx];
     let border_radius = get_border_radius(layout_result, html_node, rect_idx, styled_node);
+    let border_radii = get_border_radii_pt(&border_radius, positioned_rect.size.width);
     let background_content =
         get_background_content(layout_result, html_node, rect_idx, styled_node);
     let opt_border = get_opt_border(layout_result, html_node, rect_idx, styled_node);
@@ -446,7 +905,10 @@ fn displaylist_handle_rect(
                 }),
             });
             newops.push(Op::DrawPolygon {
-                polygon: rect.to_polygon(),
+                polygon: match rounded_rect_path(&rect, border_radii) {
+                    Some(path) => path.into_polygon(PaintMode::Fill, WindingOrder::NonZero),
+                    None => rect.to_polygon(),
+                },
             });
         }
     }
@@ -521,14 +983,21 @@ fn displaylist_handle_rect(
                 icc_profile: None,
             }),
         });
-        newops.push(Op::DrawLine {
-            line: rect.to_line(),
-        });
+        match rounded_rect_path(&rect, border_radii) {
+            Some(path) => newops.extend(
+                path.into_lines()
+                    .into_iter()
+                    .map(|line| Op::DrawLine { line }),
+            ),
+            None => newops.push(Op::DrawLine {
+                line: rect.to_line(),
+            }),
+        }
     }
 
     if let Some(image_info) = opt_image {
         let source_width = image_info.width;
-        let source_height = image_info.width;
+        let source_height = image_info.height;
         let target_width = positioned_rect.size.width;
         let target_height = positioned_rect.size.height;
         let pos = positioned_rect.position.get_static_offset();
@@ -539,16 +1008,22 @@ fn displaylist_handle_rect(
             || source_width == 0;
 
         if !is_zero {
+            // the layout is top-down, the PDF rect starts at the lower left corner
+            let rect = crate::Rect {
+                x: Pt(pos.x),
+                y: Pt(page_height.0 - pos.y - target_height),
+                width: Pt(target_width),
+                height: Pt(target_height),
+            };
             ops.push(Op::UseXObject {
                 id: crate::XObjectId(image_info.xobject_id.clone()),
-                transform: crate::XObjectTransform {
-                    translate_x: Some(Pt(pos.x)),
-                    translate_y: Some(Pt(page_height.0 - pos.y)),
-                    rotate: None, // todo
-                    scale_x: Some(target_width / source_width as f32),
-                    scale_y: Some(target_height / source_height as f32),
-                    dpi: None,
-                },
+                transform: crate::XObjectTransform::fit_into(
+                    (crate::Px(source_width), crate::Px(source_height)),
+                    None,
+                    &rect,
+                    crate::XObjectFit::Stretch,
+                    crate::XObjectAlign::Center,
+                ),
             });
         }
     }
@@ -571,11 +1046,41 @@ fn displaylist_handle_rect(
             lh: Pt(text.font_size_px),
         });
 
-        let glyphs = text.get_layouted_glyphs();
+        let vertical_text = get_vertical_writing_mode(layout_result, rect_idx).and_then(|rl| {
+            let words = layout_result.words_cache.get(&rect_idx)?;
+            let font = doc.resources.fonts.map.get(&id)?;
+            let pos = positioned_rect.position.get_static_offset();
+            let column_height = get_vertical_column_height(layout_result, rect_idx, page_height);
+            let rect = crate::Rect {
+                x: Pt(pos.x),
+                y: Pt(page_height.0 - pos.y - column_height.0),
+                width: Pt(positioned_rect.size.width),
+                height: column_height,
+            };
+            // same font scale as the horizontal text
+            let font_size = Pt(text.font_size_px * 2.0);
+            Some(vertical_text_to_ops(
+                font,
+                &id,
+                words.internal_str.as_str(),
+                font_size,
+                Pt(font_size.0 * 1.2),
+                &rect,
+                rl,
+            ))
+        });
+
+        let glyphs = match vertical_text {
+            Some(mut vertical_ops) => {
+                ops.append(&mut vertical_ops);
+                Vec::new()
+            }
+            None => text.get_layouted_glyphs().glyphs,
+        };
 
         let static_bounds = positioned_rect.get_approximate_static_bounds();
 
-        for gi in glyphs.glyphs {
+        for gi in glyphs {
             ops.push(Op::SetTextCursor {
                 pos: crate::Point {
                     x: Pt(0.0),
@@ -690,6 +1195,46 @@ fn get_border_radius(
     }
 }
 
+/// Resolves the corner radii in CSS order (top left, top right, bottom right, bottom left)
+fn get_border_radii_pt(border_radius: &StyleBorderRadius, width: f32) -> [Pt; 4] {
+    [
+        border_radius
+            .top_left
+            .and_then(|r| r.get_property_or_default())
+            .map(|r| r.inner.to_pixels(width)),
+        border_radius
+            .top_right
+            .and_then(|r| r.get_property_or_default())
+            .map(|r| r.inner.to_pixels(width)),
+        border_radius
+            .bottom_right
+            .and_then(|r| r.get_property_or_default())
+            .map(|r| r.inner.to_pixels(width)),
+        border_radius
+            .bottom_left
+            .and_then(|r| r.get_property_or_default())
+            .map(|r| r.inner.to_pixels(width)),
+    ]
+    .map(|r| Pt(r.unwrap_or(0.0)))
+}
+
+/// Returns the path of a rectangle with rounded corners, `None` if no corner is rounded.
+/// `rect.y` is the top of the rectangle (like in `Rect::to_polygon`).
+fn rounded_rect_path(rect: &crate::graphics::Rect, radii: [Pt; 4]) -> Option<PathBuilder> {
+    if radii.iter().all(|r| r.0 <= 0.0) {
+        return None;
+    }
+
+    let lower_left_rect = crate::graphics::Rect {
+        x: rect.x,
+        y: Pt(rect.y.0 - rect.height.0),
+        width: rect.width,
+        height: rect.height,
+    };
+
+    Some(PathBuilder::new().rounded_rect_with_radii(&lower_left_rect, radii))
+}
+
 #[derive(Debug)]
 struct LayoutRectContentBackground {
     content: azul_core::display_list::RectBackground,
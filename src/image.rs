@@ -1,4 +1,7 @@
-use crate::{ColorBits, ColorSpace};
+use crate::{
+    ColorBits, ColorSpace, ImageFilter, Mm, Op, PdfConformance, PdfPage, PdfSaveOptions, Pt, Px,
+    XObjectId, XObjectTransform,
+};
 use core::fmt;
 use image::GenericImageView;
 use serde_derive::{Deserialize, Serialize};
@@ -6,11 +9,554 @@ use std::io::Cursor;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
 pub struct RawImage {
+    /// Decoded pixels. Images decoded from JPEG or JPEG 2000 files also keep the original
+    /// file, which is embedded instead of the pixels: call `clear_encoded` after
+    /// modifying the pixels, so that the modified pixels are written.
     pub pixels: RawImageData,
     pub width: usize,
     pub height: usize,
     pub data_format: RawImageFormat,
     pub tag: Vec<u8>,
+    /// Original compressed image data (i.e. the JPEG file the image was decoded from),
+    /// embedded unchanged instead of re-compressing the `pixels` if the PDF conformance
+    /// allows it. Cleared by all methods that return modified pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) encoded: Option<EncodedImage>,
+    /// Masking of the image: stencil mask (painted with the fill color) or color key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<ImageMask>,
+    /// ICC profile of the image (PNG `iCCP` chunk, JPEG `APP2` marker), embedded as an
+    /// `/ICCBased` color space so that the colors are reproduced as intended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icc_profile: Option<Vec<u8>>,
+    /// EXIF orientation: the pixels are stored as-is and rotated when placing the image
+    #[serde(default)]
+    pub orientation: ImageOrientation,
+    /// Resolution of the image (PNG `pHYs` chunk, JPEG `JFIF` density), used if the
+    /// `XObjectTransform::dpi` is not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<f32>,
+    /// Color table of palette-based images (PNG color type 3), RGB triplets in the
+    /// order of the file. The pixels are written as indices into this palette with an
+    /// `/Indexed` color space, unless they contain colors that are not in the palette.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Vec<u8>>,
+}
+
+/// EXIF orientation of an image: how the stored pixels have to be transformed
+/// in order to display the image upright
+#[derive(
+    Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageOrientation {
+    /// EXIF orientation 1
+    #[default]
+    Normal,
+    /// EXIF orientation 2: mirrored horizontally
+    FlipHorizontal,
+    /// EXIF orientation 3: rotated by 180 degrees
+    Rotate180,
+    /// EXIF orientation 4: mirrored vertically
+    FlipVertical,
+    /// EXIF orientation 5: mirrored along the top-left to bottom-right diagonal
+    Transpose,
+    /// EXIF orientation 6: has to be rotated 90 degrees clockwise
+    Rotate90,
+    /// EXIF orientation 7: mirrored along the top-right to bottom-left diagonal
+    Transverse,
+    /// EXIF orientation 8: has to be rotated 90 degrees counter-clockwise
+    Rotate270,
+}
+
+impl ImageOrientation {
+    /// Converts the value of the EXIF `Orientation` tag, invalid values are `Normal`
+    pub fn from_exif(value: u16) -> Self {
+        use self::ImageOrientation::*;
+        match value {
+            2 => FlipHorizontal,
+            3 => Rotate180,
+            4 => FlipVertical,
+            5 => Transpose,
+            6 => Rotate90,
+            7 => Transverse,
+            8 => Rotate270,
+            _ => Normal,
+        }
+    }
+
+    /// Returns whether width and height of the displayed image are swapped
+    pub fn swaps_dimensions(&self) -> bool {
+        use self::ImageOrientation::*;
+        matches!(self, Transpose | Rotate90 | Transverse | Rotate270)
+    }
+
+    /// Transformation of the unit square that the PDF maps images to,
+    /// applied before scaling the image to its displayed size
+    pub(crate) fn get_unit_matrix(&self) -> [f32; 6] {
+        use self::ImageOrientation::*;
+        match self {
+            Normal => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            FlipHorizontal => [-1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+            Rotate180 => [-1.0, 0.0, 0.0, -1.0, 1.0, 1.0],
+            FlipVertical => [1.0, 0.0, 0.0, -1.0, 0.0, 1.0],
+            Transpose => [0.0, -1.0, -1.0, 0.0, 1.0, 1.0],
+            Rotate90 => [0.0, -1.0, 1.0, 0.0, 0.0, 1.0],
+            Transverse => [0.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+            Rotate270 => [0.0, 1.0, -1.0, 0.0, 1.0, 0.0],
+        }
+    }
+
+    // Position of the stored pixel that is displayed at (x, y),
+    // `width` and `height` are the dimensions of the stored image
+    fn get_source_pixel(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        use self::ImageOrientation::*;
+        match self {
+            Normal => (x, y),
+            FlipHorizontal => (width - 1 - x, y),
+            Rotate180 => (width - 1 - x, height - 1 - y),
+            FlipVertical => (x, height - 1 - y),
+            Transpose => (y, x),
+            Rotate90 => (y, height - 1 - x),
+            Transverse => (width - 1 - y, height - 1 - x),
+            Rotate270 => (width - 1 - y, x),
+        }
+    }
+}
+
+/// Page size of the pages created by `PdfDocument::from_images`
+#[derive(
+    Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFit {
+    /// The page has the size of the image at its resolution (`RawImage::dpi`,
+    /// 300 DPI if unknown)
+    #[default]
+    ImageSize,
+    /// A4 page (210 x 297 mm), the image is scaled to fit and centered. The page is
+    /// rotated to landscape if the image is wider than high.
+    A4,
+    /// US Letter page (8.5 x 11 inch), the image is scaled to fit and centered. The page
+    /// is rotated to landscape if the image is wider than high.
+    Letter,
+}
+
+impl PageFit {
+    /// Creates a page that shows the image, added to the document resources as `id`
+    pub fn get_page(&self, image: &RawImage, id: XObjectId) -> PdfPage {
+        let (w, h) = image.get_oriented_size();
+        let dpi = image.dpi.unwrap_or(300.0);
+        let (image_width, image_height) = (Px(w).into_pt(dpi), Px(h).into_pt(dpi));
+
+        let page_size = match self {
+            PageFit::ImageSize => None,
+            PageFit::A4 => Some((Mm(210.0).into_pt(), Mm(297.0).into_pt())),
+            PageFit::Letter => Some((Pt(612.0), Pt(792.0))),
+        };
+
+        let (page_width, page_height, transform) = match page_size {
+            Some((pw, ph)) if image_width.0 > 0.0 && image_height.0 > 0.0 => {
+                let (pw, ph) = if w > h { (ph, pw) } else { (pw, ph) };
+                let scale = (pw.0 / image_width.0).min(ph.0 / image_height.0);
+                let transform = XObjectTransform {
+                    translate_x: Some(Pt((pw.0 - image_width.0 * scale) / 2.0)),
+                    translate_y: Some(Pt((ph.0 - image_height.0 * scale) / 2.0)),
+                    scale_x: Some(scale),
+                    scale_y: Some(scale),
+                    ..Default::default()
+                };
+                (pw, ph, transform)
+            }
+            _ => (image_width, image_height, XObjectTransform::default()),
+        };
+
+        let ops = vec![Op::UseXObject { id, transform }];
+        PdfPage::new(page_width.into(), page_height.into(), ops)
+    }
+}
+
+/// Filter used to resample images, see `PdfSaveOptions::max_image_dpi`
+#[derive(
+    Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageResampling {
+    /// Nearest neighbor (fastest, blocky)
+    Nearest,
+    /// Linear
+    Triangle,
+    /// Cubic
+    CatmullRom,
+    /// Gaussian
+    Gaussian,
+    /// Lanczos with window 3 (slowest, sharpest)
+    #[default]
+    Lanczos3,
+}
+
+impl ImageResampling {
+    fn into_filter_type(self) -> image::imageops::FilterType {
+        use image::imageops::FilterType;
+        match self {
+            ImageResampling::Nearest => FilterType::Nearest,
+            ImageResampling::Triangle => FilterType::Triangle,
+            ImageResampling::CatmullRom => FilterType::CatmullRom,
+            ImageResampling::Gaussian => FilterType::Gaussian,
+            ImageResampling::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Transparency of an image without an alpha channel (`/SMask`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMask {
+    /// The image is written as a 1-bit stencil mask (`/ImageMask true`): black pixels
+    /// are painted with the current fill color, white pixels are transparent. Images
+    /// that are not `RawImageFormat::R1` are converted with `RawImage::to_bilevel(128)`.
+    Stencil,
+    /// Color key masking (`/Mask [min max ...]`): pixels whose color components are all
+    /// within the (inclusive) `[min, max]` ranges are not painted. Needs one range per
+    /// color component, with values in the range of the bits per component.
+    ColorKey(Vec<(u16, u16)>),
+}
+
+/// Compressed image data that can be embedded into the PDF without re-encoding,
+/// the PDF viewer decompresses it with the given `filter`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+pub struct EncodedImage {
+    /// Filter to decode the `bytes` with (`/DCTDecode` for JPEG)
+    pub filter: ImageFilter,
+    /// Number of color components: 1 (gray), 3 (RGB) or 4 (CMYK)
+    pub components: u8,
+    /// Bits per color component
+    pub bits_per_component: u8,
+    /// Whether the color components are stored inverted (Adobe CMYK JPEGs)
+    pub inverted: bool,
+    /// Compressed data
+    pub bytes: Vec<u8>,
+}
+
+impl EncodedImage {
+    /// Reads the JPEG header of the `bytes` (without decoding the image) and returns
+    /// the encoded image plus its width and height. Fails if the JPEG can't be embedded
+    /// with `/DCTDecode`, i.e. because it uses arithmetic coding or 12-bit samples.
+    pub fn from_jpeg_bytes(bytes: &[u8]) -> Result<(Self, usize, usize), String> {
+        if !bytes.starts_with(&[0xFF, 0xD8]) {
+            return Err("not a JPEG file: missing SOI marker".to_string());
+        }
+
+        let mut adobe = false;
+        for (marker, segment) in jpeg_segments(bytes) {
+            match marker {
+                // APP14 "Adobe": CMYK data is stored inverted
+                0xEE if segment.starts_with(b"Adobe") => adobe = true,
+                // SOF0 (baseline), SOF1 (extended sequential), SOF2 (progressive)
+                0xC0..=0xC2 => {
+                    if segment.len() < 6 {
+                        return Err("truncated JPEG SOF segment".to_string());
+                    }
+                    if segment[0] != 8 {
+                        return Err(format!(
+                            "unsupported JPEG sample precision: {} bits",
+                            segment[0]
+                        ));
+                    }
+                    let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                    let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                    let components = segment[5];
+                    if !matches!(components, 1 | 3 | 4) {
+                        return Err(format!("unsupported JPEG component count: {components}"));
+                    }
+                    let encoded = EncodedImage {
+                        filter: ImageFilter::DCT,
+                        components,
+                        bits_per_component: 8,
+                        inverted: adobe && components == 4,
+                        bytes: bytes.to_vec(),
+                    };
+                    return Ok((encoded, width, height));
+                }
+                // lossless, hierarchical or arithmetic coded frames
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(format!("unsupported JPEG frame type: SOF{}", marker - 0xC0));
+                }
+                _ => {}
+            }
+        }
+
+        Err("invalid JPEG file: missing SOF marker".to_string())
+    }
+
+    /// Reads the header of a JPEG 2000 file (JP2) or raw codestream (J2K) without
+    /// decoding the image and returns the encoded image plus its width and height.
+    /// For JP2 files, the dimensions and components are read from the `ihdr` box.
+    pub fn from_jpx_bytes(bytes: &[u8]) -> Result<(Self, usize, usize), String> {
+        let (width, height, components, bits) = if bytes.starts_with(JP2_SIGNATURE) {
+            let jp2h = jp2_boxes(bytes)
+                .find(|(ty, _)| ty == b"jp2h")
+                .ok_or_else(|| "invalid JP2 file: missing jp2h box".to_string())?;
+            let (_, ihdr) = jp2_boxes(jp2h.1)
+                .find(|(ty, _)| ty == b"ihdr")
+                .ok_or_else(|| "invalid JP2 file: missing ihdr box".to_string())?;
+            if ihdr.len() < 11 {
+                return Err("invalid JP2 file: truncated ihdr box".to_string());
+            }
+            let height = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]);
+            let width = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]);
+            let components = u16::from_be_bytes([ihdr[8], ihdr[9]]);
+            // 255 = components have different bit depths
+            let bits = if ihdr[10] == 255 {
+                8
+            } else {
+                (ihdr[10] & 0x7F) + 1
+            };
+            (width, height, components, bits)
+        } else if bytes.starts_with(&[0xFF, 0x4F, 0xFF, 0x51]) {
+            // SOC marker, followed by the SIZ marker segment
+            let siz = bytes
+                .get(4..)
+                .filter(|siz| siz.len() >= 41)
+                .ok_or_else(|| "invalid JPEG 2000 codestream: truncated SIZ marker".to_string())?;
+            let read_u32 =
+                |i: usize| u32::from_be_bytes([siz[i], siz[i + 1], siz[i + 2], siz[i + 3]]);
+            let width = read_u32(6).saturating_sub(read_u32(14));
+            let height = read_u32(10).saturating_sub(read_u32(18));
+            let components = u16::from_be_bytes([siz[38], siz[39]]);
+            (width, height, components, (siz[40] & 0x7F) + 1)
+        } else {
+            return Err("not a JPEG 2000 file: missing JP2 signature or SOC marker".to_string());
+        };
+
+        if !matches!(components, 1 | 3 | 4) {
+            return Err(format!(
+                "unsupported JPEG 2000 component count: {components}"
+            ));
+        }
+
+        let encoded = EncodedImage {
+            filter: ImageFilter::JPX,
+            components: components as u8,
+            bits_per_component: bits,
+            inverted: false,
+            bytes: bytes.to_vec(),
+        };
+
+        Ok((encoded, width as usize, height as usize))
+    }
+
+    /// Returns whether the PDF conformance allows embedding the image as-is
+    pub fn is_allowed(&self, conformance: &PdfConformance) -> bool {
+        match self.filter {
+            ImageFilter::DCT => conformance.is_jpeg_content_allowed(),
+            ImageFilter::JPX => conformance.is_jpx_content_allowed(),
+            _ => true,
+        }
+    }
+
+    fn get_color_space(&self) -> ColorSpace {
+        match self.components {
+            1 => ColorSpace::Greyscale,
+            4 => ColorSpace::Cmyk,
+            _ => ColorSpace::Rgb,
+        }
+    }
+}
+
+const JP2_SIGNATURE: &[u8] = &[
+    0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50, 0x20, 0x20, 0x0D, 0x0A, 0x87, 0x0A,
+];
+
+// Iterates over the (type, contents) of the boxes in a JP2 file or superbox
+fn jp2_boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    core::iter::from_fn(move || {
+        let header = data.get(..8)?;
+        let ty = [header[4], header[5], header[6], header[7]];
+        let (header_len, box_len) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                // box extends to the end of the file
+                0 => (8, data.len()),
+                // 64-bit extended length
+                1 => {
+                    let xl = data.get(8..16)?;
+                    let xl = u64::from_be_bytes([
+                        xl[0], xl[1], xl[2], xl[3], xl[4], xl[5], xl[6], xl[7],
+                    ]);
+                    (16, usize::try_from(xl).ok()?)
+                }
+                l => (8, l as usize),
+            };
+        let contents = data.get(header_len..box_len)?;
+        data = &data[box_len..];
+        Some((ty, contents))
+    })
+}
+
+// Iterates over the (marker, contents) of the JPEG segments before the first
+// start of scan, stops at invalid or truncated segments
+fn jpeg_segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut pos = 2;
+    core::iter::from_fn(move || loop {
+        let header = data.get(pos..pos + 4)?;
+        if header[0] != 0xFF {
+            return None;
+        }
+        let marker = header[1];
+        // fill bytes and standalone markers without a length
+        if marker == 0xFF || marker == 0x01 || (0xD0..=0xD8).contains(&marker) {
+            pos += if marker == 0xFF { 1 } else { 2 };
+            continue;
+        }
+        // start of scan: the entropy-coded data follows
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        pos += 2 + len;
+        return Some((marker, segment));
+    })
+}
+
+// Iterates over the (type, contents) of the chunks in a PNG file
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 8;
+    core::iter::from_fn(move || {
+        let header = data.get(pos..pos + 8)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let ty = [header[4], header[5], header[6], header[7]];
+        let contents = data.get(pos + 8..(pos + 8).checked_add(len)?)?;
+        // chunk data is followed by a 4-byte CRC
+        pos += 12 + len;
+        Some((ty, contents))
+    })
+}
+
+/// Metadata of an image file that the image crate doesn't decode
+#[derive(Debug, Default)]
+struct ImageMetadata {
+    icc_profile: Option<Vec<u8>>,
+    orientation: ImageOrientation,
+    dpi: Option<f32>,
+    palette: Option<Vec<u8>>,
+}
+
+// Reads the ICC profile, EXIF orientation and resolution of PNG and JPEG files
+fn read_image_metadata(format: image::ImageFormat, bytes: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    match format {
+        image::ImageFormat::Jpeg => {
+            // the profile can be split into multiple chunks: (sequence number, data)
+            let mut icc_chunks = Vec::new();
+            for (marker, segment) in jpeg_segments(bytes) {
+                match marker {
+                    // APP0 "JFIF": version (2 bytes), density unit, x density, y density
+                    0xE0 if segment.starts_with(b"JFIF\0") && segment.len() >= 12 => {
+                        let density = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+                        metadata.dpi = match segment[7] {
+                            1 => Some(density),
+                            2 => Some(density * 2.54),
+                            _ => None,
+                        }
+                        .filter(|dpi| *dpi > 0.0);
+                    }
+                    // APP1 "Exif": TIFF structure
+                    0xE1 if segment.starts_with(b"Exif\0\0") => {
+                        metadata.orientation = read_exif_orientation(&segment[6..]);
+                    }
+                    // APP2 "ICC_PROFILE": sequence number, number of chunks, data
+                    0xE2 if segment.starts_with(b"ICC_PROFILE\0") && segment.len() >= 14 => {
+                        icc_chunks.push((segment[12], &segment[14..]));
+                    }
+                    _ => {}
+                }
+            }
+            icc_chunks.sort_by_key(|(seq, _)| *seq);
+            if !icc_chunks.is_empty() {
+                metadata.icc_profile = Some(
+                    icc_chunks
+                        .into_iter()
+                        .flat_map(|(_, c)| c)
+                        .copied()
+                        .collect(),
+                );
+            }
+        }
+        image::ImageFormat::Png => {
+            let mut indexed = false;
+            let mut transparent = false;
+            for (ty, chunk) in png_chunks(bytes) {
+                match &ty {
+                    // profile name, null separator, compression method, zlib stream
+                    b"iCCP" => {
+                        metadata.icc_profile = chunk
+                            .iter()
+                            .position(|b| *b == 0)
+                            .and_then(|i| chunk.get(i + 2..))
+                            .and_then(crate::utils::uncompress_zlib);
+                    }
+                    // pixels per unit x and y, unit (1 = meter)
+                    b"pHYs" if chunk.len() >= 9 && chunk[8] == 1 => {
+                        let ppm = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                        metadata.dpi = Some(ppm as f32 * 0.0254).filter(|dpi| *dpi > 0.0);
+                    }
+                    b"eXIf" => metadata.orientation = read_exif_orientation(chunk),
+                    // palettes with transparent entries are decoded to RGBA
+                    b"PLTE" if !chunk.is_empty() && chunk.len() % 3 == 0 => {
+                        metadata.palette = Some(chunk.to_vec());
+                    }
+                    b"tRNS" => transparent = true,
+                    // width, height, bit depth, color type (3 = palette)
+                    b"IHDR" => indexed = chunk.get(9) == Some(&3),
+                    b"IDAT" | b"IEND" => break,
+                    _ => {}
+                }
+            }
+            if !indexed || transparent {
+                metadata.palette = None;
+            }
+        }
+        _ => {}
+    }
+    metadata
+}
+
+// Reads the `Orientation` tag (0x0112) from the first IFD of the EXIF data
+fn read_exif_orientation(tiff: &[u8]) -> ImageOrientation {
+    let big_endian = match tiff.get(..2) {
+        Some(b"MM") => true,
+        Some(b"II") => false,
+        _ => return ImageOrientation::Normal,
+    };
+    let read_u16 = |pos: usize| {
+        let b = tiff.get(pos..pos + 2)?;
+        Some(if big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    };
+    let read_u32 = |pos: usize| {
+        let b = tiff.get(pos..pos + 4)?;
+        Some(if big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    };
+
+    let orientation = (|| {
+        let ifd = read_u32(4)? as usize;
+        let entries = read_u16(ifd)? as usize;
+        // 12 bytes per entry: tag, type, count, value
+        (0..entries)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|entry| read_u16(*entry) == Some(0x0112))
+            .and_then(|entry| read_u16(entry + 8))
+    })();
+
+    orientation.map_or(ImageOrientation::Normal, ImageOrientation::from_exif)
 }
 
 struct RawImageU8 {
@@ -47,6 +593,13 @@ pub enum RawImageFormat {
     BGRA8,
     RGBF32,
     RGBAF32,
+    CMYK8,
+    CMYKA8,
+    CMYK16,
+    CMYKA16,
+    /// 1-bit black and white, 8 pixels per byte (most significant bit first),
+    /// every row starts at a byte boundary. 0 = black, 1 = white.
+    R1,
 }
 
 impl RawImageFormat {
@@ -56,10 +609,17 @@ impl RawImageFormat {
             RGBA8 => RGB8,
             RGBA16 => RGB16,
             RGBAF32 => RGBF32,
+            CMYKA8 => CMYK8,
+            CMYKA16 => CMYK16,
             other => *other,
         }
     }
 
+    pub fn is_cmyk(&self) -> bool {
+        use self::RawImageFormat::*;
+        matches!(self, CMYK8 | CMYKA8 | CMYK16 | CMYKA16)
+    }
+
     fn from_internal(f: &azul_core::app_resources::RawImageFormat) -> Self {
         use azul_core::app_resources::RawImageFormat;
         match f {
@@ -92,12 +652,19 @@ impl RawImageFormat {
             RawImageFormat::BGRA8 => azul_core::app_resources::RawImageFormat::BGRA8,
             RawImageFormat::RGBF32 => azul_core::app_resources::RawImageFormat::RGBF32,
             RawImageFormat::RGBAF32 => azul_core::app_resources::RawImageFormat::RGBAF32,
+            // CMYK images are converted to RGB for rendering, see `cmyk_to_rgb`
+            RawImageFormat::CMYK8 => azul_core::app_resources::RawImageFormat::RGB8,
+            RawImageFormat::CMYKA8 => azul_core::app_resources::RawImageFormat::RGBA8,
+            RawImageFormat::CMYK16 => azul_core::app_resources::RawImageFormat::RGB16,
+            RawImageFormat::CMYKA16 => azul_core::app_resources::RawImageFormat::RGBA16,
+            // 1-bit images are unpacked for rendering, see `bilevel_to_r8`
+            RawImageFormat::R1 => azul_core::app_resources::RawImageFormat::R8,
         }
     }
 
     pub fn has_alpha(&self) -> bool {
         use self::RawImageFormat::*;
-        matches!(self, RGBA8 | RGBA16 | RGBAF32)
+        matches!(self, RGBA8 | RGBA16 | RGBAF32 | CMYKA8 | CMYKA16)
     }
 
     pub fn get_color_bits_and_space(&self) -> (ColorBits, ColorSpace) {
@@ -115,6 +682,9 @@ impl RawImageFormat {
             BGRA8 => (ColorBits::Bit8, ColorSpace::Rgba),
             RGBF32 => (ColorBits::Bit16, ColorSpace::Rgb),
             RGBAF32 => (ColorBits::Bit16, ColorSpace::Rgba),
+            CMYK8 | CMYKA8 => (ColorBits::Bit8, ColorSpace::Cmyk),
+            CMYK16 | CMYKA16 => (ColorBits::Bit16, ColorSpace::Cmyk),
+            R1 => (ColorBits::Bit1, ColorSpace::Greyscale),
         }
     }
 }
@@ -134,9 +704,9 @@ impl RawImageData {
     pub fn empty(format: RawImageFormat) -> Self {
         use self::RawImageFormat::*;
         match format {
-            R8 | RG8 | RGB8 | RGBA8 | BGR8 | BGRA8 => Self::U8(Vec::new()),
+            R1 | R8 | RG8 | RGB8 | RGBA8 | BGR8 | BGRA8 | CMYK8 | CMYKA8 => Self::U8(Vec::new()),
 
-            R16 | RG16 | RGB16 | RGBA16 => Self::U16(Vec::new()),
+            R16 | RG16 | RGB16 | RGBA16 | CMYK16 | CMYKA16 => Self::U16(Vec::new()),
 
             RGBF32 | RGBAF32 => Self::F32(Vec::new()),
         }
@@ -160,12 +730,346 @@ impl RawImage {
             data_format: format,
             pixels: RawImageData::empty(format),
             tag: Vec::new(),
+            encoded: None,
+            mask: None,
+            icc_profile: None,
+            orientation: ImageOrientation::Normal,
+            dpi: None,
+            palette: None,
         }
     }
 
+    /// Returns whether the original compressed image data is embedded instead of the
+    /// pixels: the conformance has to allow the filter, and stencil masks need the pixels
+    pub(crate) fn uses_encoded(&self, conformance: &PdfConformance) -> bool {
+        let Some(encoded) = self.encoded.as_ref() else {
+            return false;
+        };
+        if !encoded.is_allowed(conformance) {
+            return false;
+        }
+        match self.mask {
+            None => true,
+            // color key masks can be applied to the encoded data,
+            // stencil masks are ignored if there are no pixels to convert
+            Some(ImageMask::ColorKey(_)) => {
+                encoded.filter == ImageFilter::JPX || self.pixels.is_empty()
+            }
+            Some(ImageMask::Stencil) => self.pixels.is_empty(),
+        }
+    }
+
+    /// Returns whether the image is embedded as JPEG 2000, which needs PDF 1.5
+    pub(crate) fn uses_jpx(&self, conformance: &PdfConformance) -> bool {
+        self.uses_encoded(conformance)
+            && self
+                .encoded
+                .as_ref()
+                .is_some_and(|e| e.filter == ImageFilter::JPX)
+    }
+
+    /// Returns an error if the image can't be written with the conformance: images
+    /// without decoded pixels (JPEG 2000) can only be embedded as-is
+    pub(crate) fn check_conformance(&self, conformance: &PdfConformance) -> Result<(), String> {
+        match self.encoded.as_ref() {
+            Some(encoded) if self.pixels.is_empty() && !self.uses_encoded(conformance) => {
+                Err(format!(
+                    "{:?} image data is not allowed in {} and there are no decoded pixels to \
+                     embed instead",
+                    encoded.filter,
+                    conformance.get_identifier_string()
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the original compressed image data (JPEG or JPEG 2000) that is embedded
+    /// unchanged instead of the `pixels`
+    pub fn get_encoded(&self) -> Option<&EncodedImage> {
+        self.encoded.as_ref()
+    }
+
+    /// Discards the original compressed image data, so that the `pixels` are embedded.
+    /// Has to be called after modifying the pixels of a decoded JPEG image. Images
+    /// without pixels (JPEG 2000) keep their data.
+    pub fn clear_encoded(&mut self) {
+        if !self.pixels.is_empty() {
+            self.encoded = None;
+        }
+    }
+
+    /// Converts the image to a 1-bit black and white image (`RawImageFormat::R1`):
+    /// pixels with a luminance below the `threshold` (0 - 255) become black, the alpha
+    /// channel is ignored. Useful for scanned line art and signatures.
+    pub fn to_bilevel(&self, threshold: u8) -> RawImage {
+        use self::RawImageFormat::*;
+
+        if self.data_format == R1 {
+            return self.clone();
+        }
+        if self.data_format.is_cmyk() {
+            return cmyk_to_rgb(self).to_bilevel(threshold);
+        }
+
+        let samples: Vec<f32> = match &self.pixels {
+            RawImageData::U8(vec) => vec.iter().map(|v| *v as f32 / 255.0).collect(),
+            RawImageData::U16(vec) => vec.iter().map(|v| *v as f32 / 65535.0).collect(),
+            RawImageData::F32(vec) => vec.clone(),
+        };
+
+        let channels = match self.data_format {
+            R8 | R16 => 1,
+            RG8 | RG16 => 2,
+            RGB8 | RGB16 | BGR8 | RGBF32 => 3,
+            _ => 4,
+        };
+
+        let threshold = threshold as f32 / 255.0;
+        let row_bytes = self.width.div_ceil(8);
+        let mut pixels = vec![0_u8; row_bytes * self.height];
+        for (i, px) in samples.chunks_exact(channels).enumerate() {
+            let luminance = match (channels, self.data_format) {
+                (1 | 2, _) => px[0],
+                (_, BGR8 | BGRA8) => 0.299 * px[2] + 0.587 * px[1] + 0.114 * px[0],
+                _ => 0.299 * px[0] + 0.587 * px[1] + 0.114 * px[2],
+            };
+            if luminance >= threshold && self.width > 0 {
+                let (row, col) = (i / self.width, i % self.width);
+                pixels[row * row_bytes + col / 8] |= 0x80 >> (col % 8);
+            }
+        }
+
+        RawImage {
+            pixels: RawImageData::U8(pixels),
+            width: self.width,
+            height: self.height,
+            data_format: R1,
+            tag: self.tag.clone(),
+            encoded: None,
+            mask: self.mask.clone(),
+            icc_profile: None,
+            orientation: self.orientation,
+            dpi: self.dpi,
+            palette: None,
+        }
+    }
+
+    /// Resamples the image to the given size. Returns `None` for images that can't be
+    /// resampled: images without pixels (JPEG 2000), 1-bit and CMYKA images.
+    pub fn resize(&self, width: usize, height: usize, filter: ImageResampling) -> Option<RawImage> {
+        use self::RawImageFormat::*;
+        use image::DynamicImage::*;
+        use image::ImageBuffer;
+
+        let (w, h) = (self.width as u32, self.height as u32);
+        let resize = |im: image::DynamicImage| {
+            let resized = im.resize_exact(width as u32, height as u32, filter.into_filter_type());
+            from_dynamic_image(resized)
+                .ok()
+                .map(|(_, _, _, pixels)| pixels)
+        };
+
+        // resampling works per channel, so BGR(A) can be treated as RGB(A)
+        let pixels = match (&self.pixels, self.data_format) {
+            (RawImageData::U8(p), R8) => {
+                resize(ImageLuma8(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U8(p), RG8) => {
+                resize(ImageLumaA8(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U8(p), RGB8 | BGR8) => {
+                resize(ImageRgb8(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U8(p), RGBA8 | BGRA8) => {
+                resize(ImageRgba8(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U16(p), R16) => {
+                resize(ImageLuma16(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U16(p), RG16) => {
+                resize(ImageLumaA16(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U16(p), RGB16) => {
+                resize(ImageRgb16(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::U16(p), RGBA16) => {
+                resize(ImageRgba16(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::F32(p), RGBF32) => {
+                resize(ImageRgb32F(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            (RawImageData::F32(p), RGBAF32) => {
+                resize(ImageRgba32F(ImageBuffer::from_raw(w, h, p.clone())?))?
+            }
+            // the K channel would be treated as alpha, so CMY and K are resampled separately
+            (RawImageData::U8(p), CMYK8) => {
+                let (cmy, k) = split_cmyk(p);
+                let cmy = resize(ImageRgb8(ImageBuffer::from_raw(w, h, cmy)?))?;
+                let k = resize(ImageLuma8(ImageBuffer::from_raw(w, h, k)?))?;
+                match (cmy, k) {
+                    (RawImageData::U8(cmy), RawImageData::U8(k)) => {
+                        RawImageData::U8(merge_cmyk(&cmy, &k))
+                    }
+                    _ => return None,
+                }
+            }
+            (RawImageData::U16(p), CMYK16) => {
+                let (cmy, k) = split_cmyk(p);
+                let cmy = resize(ImageRgb16(ImageBuffer::from_raw(w, h, cmy)?))?;
+                let k = resize(ImageLuma16(ImageBuffer::from_raw(w, h, k)?))?;
+                match (cmy, k) {
+                    (RawImageData::U16(cmy), RawImageData::U16(k)) => {
+                        RawImageData::U16(merge_cmyk(&cmy, &k))
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(RawImage {
+            pixels,
+            width,
+            height,
+            data_format: self.data_format,
+            tag: self.tag.clone(),
+            encoded: None,
+            mask: self.mask.clone(),
+            icc_profile: self.icc_profile.clone(),
+            orientation: self.orientation,
+            dpi: self.dpi.map(|dpi| dpi * width as f32 / self.width as f32),
+            palette: None,
+        })
+    }
+
+    /// Returns the (width, height) of the image after applying the `orientation`
+    pub fn get_oriented_size(&self) -> (usize, usize) {
+        if self.orientation.swaps_dimensions() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// Transforms the pixels according to the `orientation`, so that the returned image
+    /// is upright with `ImageOrientation::Normal`. Images without pixels (JPEG 2000)
+    /// are returned unchanged.
+    pub fn apply_orientation(&self) -> RawImage {
+        fn orient<T: Copy>(pixels: &[T], im: &RawImage) -> Vec<T> {
+            let channels = pixels.len() / (im.width * im.height);
+            let (width, height) = im.get_oriented_size();
+            let mut out = Vec::with_capacity(pixels.len());
+            for y in 0..height {
+                for x in 0..width {
+                    let (sx, sy) = im.orientation.get_source_pixel(x, y, im.width, im.height);
+                    let start = (sy * im.width + sx) * channels;
+                    out.extend_from_slice(&pixels[start..start + channels]);
+                }
+            }
+            out
+        }
+
+        if self.orientation == ImageOrientation::Normal
+            || self.pixels.is_empty()
+            || self.width == 0
+            || self.height == 0
+        {
+            return self.clone();
+        }
+
+        if self.data_format == RawImageFormat::R1 {
+            let mut oriented = bilevel_to_r8(self).apply_orientation().to_bilevel(128);
+            oriented.mask = self.mask.clone();
+            return oriented;
+        }
+
+        let (width, height) = self.get_oriented_size();
+        RawImage {
+            pixels: match &self.pixels {
+                RawImageData::U8(vec) => RawImageData::U8(orient(vec, self)),
+                RawImageData::U16(vec) => RawImageData::U16(orient(vec, self)),
+                RawImageData::F32(vec) => RawImageData::F32(orient(vec, self)),
+            },
+            width,
+            height,
+            data_format: self.data_format,
+            tag: self.tag.clone(),
+            // the encoded data still has the original orientation
+            encoded: None,
+            mask: self.mask.clone(),
+            icc_profile: self.icc_profile.clone(),
+            orientation: ImageOrientation::Normal,
+            dpi: self.dpi,
+            palette: self.palette.clone(),
+        }
+    }
+
+    /// Creates an image from a JPEG 2000 file (JP2) or codestream (J2K), which is embedded
+    /// unchanged with `/JPXDecode` (PDF 1.5). The pixels are not decoded, so the image can't
+    /// be used for rendering HTML, and saving fails if the PDF conformance doesn't allow
+    /// JPEG 2000 (see `PdfConformance::is_jpx_content_allowed`).
+    pub fn from_jpx_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let (encoded, width, height) = EncodedImage::from_jpx_bytes(bytes)?;
+        let data_format = match encoded.components {
+            1 => RawImageFormat::R8,
+            4 => RawImageFormat::CMYK8,
+            _ => RawImageFormat::RGB8,
+        };
+        Ok(Self {
+            pixels: RawImageData::empty(data_format),
+            width,
+            height,
+            data_format,
+            tag: Vec::new(),
+            encoded: Some(encoded),
+            mask: None,
+            icc_profile: None,
+            orientation: ImageOrientation::Normal,
+            dpi: None,
+            palette: None,
+        })
+    }
+
+    /// Creates an image from a JPEG file without decoding the pixels, the JPEG is embedded
+    /// unchanged with `/DCTDecode`. Faster and uses less memory than `decode_from_bytes`,
+    /// but the image can't be used for rendering HTML, resampled or written inline, and
+    /// saving fails if the PDF conformance doesn't allow JPEG content (see
+    /// `PdfConformance::is_jpeg_content_allowed`). Doesn't need the `jpeg` feature.
+    pub fn from_jpeg_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let (encoded, width, height) = EncodedImage::from_jpeg_bytes(bytes)?;
+        let data_format = match encoded.components {
+            1 => RawImageFormat::R8,
+            4 => RawImageFormat::CMYK8,
+            _ => RawImageFormat::RGB8,
+        };
+        let metadata = read_image_metadata(image::ImageFormat::Jpeg, bytes);
+        Ok(Self {
+            pixels: RawImageData::empty(data_format),
+            width,
+            height,
+            data_format,
+            tag: Vec::new(),
+            encoded: Some(encoded),
+            mask: None,
+            icc_profile: metadata.icc_profile,
+            orientation: metadata.orientation,
+            dpi: metadata.dpi,
+            palette: None,
+        })
+    }
+
     /// NOTE: depends on the enabled image formats!
+    ///
+    /// JPEG 2000 files are not decoded, see `RawImage::from_jpx_bytes`. JPEG files are
+    /// decoded in addition to keeping the original data, use `RawImage::from_jpeg_bytes`
+    /// if the pixels are not needed.
+    /// Palette images are expanded to RGB(A). The palette of PNG files without
+    /// transparency is kept (`RawImage::palette`) and used when saving.
     pub fn decode_from_bytes(bytes: &[u8]) -> Result<Self, String> {
-        use image::DynamicImage::*;
+        if bytes.starts_with(JP2_SIGNATURE) || bytes.starts_with(&[0xFF, 0x4F, 0xFF, 0x51]) {
+            return Self::from_jpx_bytes(bytes);
+        }
 
         let im = image::guess_format(bytes).map_err(|e| e.to_string())?;
         let b_len = bytes.len();
@@ -181,7 +1085,7 @@ impl RawImage {
         #[cfg(not(feature = "jpeg"))]
         {
             let err = format!("cannot decode image (len = {b_len} bytes): printpdf is missing feature 'jpeg' to decode JPEG files. Please enable it or construct the RawImage manually.");
-            if im == image::ImageFormat::Gif {
+            if im == image::ImageFormat::Jpeg {
                 return Err(err);
             }
         }
@@ -266,55 +1170,129 @@ impl RawImage {
             }
         }
 
+        // keep the original JPEG to embed it with /DCTDecode, decoding it is still
+        // necessary in case the PDF conformance doesn't allow JPEG content, for
+        // rendering HTML and for resampling (see `RawImage::from_jpeg_bytes`)
+        let encoded = match im {
+            image::ImageFormat::Jpeg => EncodedImage::from_jpeg_bytes(bytes)
+                .ok()
+                .map(|(encoded, _, _)| encoded),
+            _ => None,
+        };
+
+        // multi-page, CMYK and fax (CCITT) TIFF files, falls back to the image crate
+        #[cfg(feature = "tiff")]
+        if im == image::ImageFormat::Tiff {
+            if let Some(frame) = decode_tiff_frames(bytes, 1).ok().and_then(|mut f| f.pop()) {
+                return Ok(frame);
+            }
+        }
+
+        let metadata = read_image_metadata(im, bytes);
+
+        // the image crate converts CMYK JPEG images to RGB, decode them separately
+        if let Some((width, height, data_format, pixels)) = decode_cmyk(im, bytes) {
+            return Ok(RawImage {
+                pixels,
+                width,
+                height,
+                data_format,
+                tag: Vec::new(),
+                encoded,
+                mask: None,
+                icc_profile: metadata.icc_profile,
+                orientation: metadata.orientation,
+                dpi: metadata.dpi,
+                palette: None,
+            });
+        }
+
         let im = image::ImageReader::new(Cursor::new(bytes))
             .with_guessed_format()
             .map_err(|e| e.to_string())?
             .decode()
             .map_err(|e| e.to_string())?;
 
-        let (w, h) = im.dimensions();
-        let ct = match im.color() {
-            image::ColorType::L8 => RawImageFormat::R8,
-            image::ColorType::La8 => RawImageFormat::RG8,
-            image::ColorType::Rgb8 => RawImageFormat::RGB8,
-            image::ColorType::Rgba8 => RawImageFormat::RGBA8,
-            image::ColorType::L16 => RawImageFormat::R16,
-            image::ColorType::La16 => RawImageFormat::RG16,
-            image::ColorType::Rgb16 => RawImageFormat::RGB16,
-            image::ColorType::Rgba16 => RawImageFormat::RGBA16,
-            image::ColorType::Rgb32F => RawImageFormat::RGBF32,
-            image::ColorType::Rgba32F => RawImageFormat::RGBAF32,
-            _ => return Err("invalid raw image format".to_string()),
-        };
-
-        let pixels = match im {
-            ImageLuma8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
-            ImageLumaA8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
-            ImageRgb8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
-            ImageRgba8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
-            ImageLuma16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
-            ImageLumaA16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
-            ImageRgb16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
-            ImageRgba16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
-            ImageRgb32F(image_buffer) => RawImageData::F32(image_buffer.into_raw()),
-            ImageRgba32F(image_buffer) => RawImageData::F32(image_buffer.into_raw()),
-            _ => return Err("invalid pixel format".to_string()),
-        };
+        let (w, h, ct, pixels) = from_dynamic_image(im)?;
 
         Ok(RawImage {
             pixels,
-            width: w as usize,
-            height: h as usize,
+            width: w,
+            height: h,
             data_format: ct,
             tag: Vec::new(),
+            encoded,
+            mask: None,
+            icc_profile: metadata.icc_profile,
+            orientation: metadata.orientation,
+            dpi: metadata.dpi,
+            palette: metadata.palette,
         })
     }
 
+    /// Decodes all frames of a multi-page TIFF file (i.e. a scanned document or fax)
+    /// or of an animated GIF / PNG file, other formats are decoded to a single frame
+    /// with `RawImage::decode_from_bytes`. Frames of animations are fully composited.
+    /// See `PdfDocument::from_images` to create a document with one page per frame.
+    ///
+    /// Returns an error with the index of the first frame that can't be decoded,
+    /// single-frame TIFF files fall back to the decoder of `decode_from_bytes`.
+    pub fn decode_all_frames(bytes: &[u8]) -> Result<Vec<Self>, String> {
+        #[allow(unused_variables)]
+        let format = image::guess_format(bytes).ok();
+
+        #[cfg(feature = "tiff")]
+        if format == Some(image::ImageFormat::Tiff) {
+            return decode_tiff_frames(bytes, usize::MAX).or_else(|e| {
+                // the image crate can only decode the first frame
+                let single_frame = tiff::decoder::Decoder::new(Cursor::new(bytes))
+                    .is_ok_and(|decoder| !decoder.more_images());
+                if single_frame {
+                    Self::decode_from_bytes(bytes).map(|im| vec![im])
+                } else {
+                    Err(e)
+                }
+            });
+        }
+
+        #[cfg(feature = "gif")]
+        if format == Some(image::ImageFormat::Gif) {
+            use image::AnimationDecoder;
+            let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(bytes))
+                .map_err(|e| e.to_string())?;
+            return decode_animation_frames(decoder.into_frames());
+        }
+
+        #[cfg(feature = "png")]
+        if format == Some(image::ImageFormat::Png) {
+            use image::AnimationDecoder;
+            let decoder = image::codecs::png::PngDecoder::new(Cursor::new(bytes))
+                .map_err(|e| e.to_string())?;
+            if decoder.is_apng().map_err(|e| e.to_string())? {
+                let decoder = decoder.apng().map_err(|e| e.to_string())?;
+                let metadata = read_image_metadata(image::ImageFormat::Png, bytes);
+                return decode_animation_frames(decoder.into_frames()).map(|frames| {
+                    frames
+                        .into_iter()
+                        .map(|frame| RawImage {
+                            icc_profile: metadata.icc_profile.clone(),
+                            dpi: metadata.dpi,
+                            ..frame
+                        })
+                        .collect()
+                });
+            }
+        }
+
+        Self::decode_from_bytes(bytes).map(|im| vec![im])
+    }
+
     /// Translates to an internal `RawImage`, necessary for the `<img>` component
     pub fn to_internal(&self) -> azul_core::app_resources::ImageRef {
+        let (width, height) = self.get_oriented_size();
         let invalid = azul_core::app_resources::ImageRef::null_image(
-            self.width,
-            self.height,
+            width,
+            height,
             self.data_format.into_internal(),
             self.tag.clone(),
         );
@@ -328,13 +1306,64 @@ impl RawImage {
     }
 }
 
-pub(crate) fn image_to_stream(im: RawImage, doc: &mut lopdf::Document) -> lopdf::Stream {
+pub(crate) fn image_to_stream(
+    im: RawImage,
+    conformance: &PdfConformance,
+    opts: &PdfSaveOptions,
+    doc: &mut lopdf::Document,
+) -> lopdf::Stream {
     use lopdf::Object::*;
 
+    // images without decoded pixels (JPEG 2000) can only be embedded as-is, saving
+    // fails before if the conformance forbids that (see `RawImage::check_conformance`)
+    if im.pixels.is_empty() && im.encoded.is_some() && !im.uses_encoded(conformance) {
+        let dict = lopdf::Dictionary::from_iter(vec![
+            ("Type", Name("XObject".into())),
+            ("Subtype", Name("Form".into())),
+            ("BBox", Array(vec![0.into(), 0.into(), 1.into(), 1.into()])),
+        ]);
+        return lopdf::Stream::new(dict, Vec::new());
+    }
+
+    if let Some(encoded) = im.encoded.as_ref().filter(|_| im.uses_encoded(conformance)) {
+        let mut stream = encoded_image_to_stream(encoded, im.width, im.height);
+        if let Some(ImageMask::ColorKey(ranges)) = im.mask.as_ref() {
+            let mask = ranges
+                .iter()
+                .flat_map(|(min, max)| [Integer((*min).into()), Integer((*max).into())]);
+            stream.dict.set("Mask", Array(mask.collect()));
+        }
+        if stream.dict.has(b"ColorSpace") {
+            let icc = im
+                .icc_profile
+                .as_ref()
+                .and_then(|icc| icc_based_color_space(icc, encoded.get_color_space(), opts, doc));
+            if let Some(icc) = icc {
+                stream.dict.set("ColorSpace", icc);
+            }
+        }
+        return stream;
+    }
+
+    if im.mask == Some(ImageMask::Stencil) {
+        return stencil_mask_to_stream(im.to_bilevel(128), opts);
+    }
+
+    let color_key = match &im.mask {
+        Some(ImageMask::ColorKey(ranges)) => Some(ranges.clone()),
+        _ => None,
+    };
+
+    let icc_profile = im.icc_profile.clone();
+    let palette = im.palette.clone();
     let (rgb8, alpha) = split_rawimage_into_rgb_plus_alpha(im);
     let (bpc, cs) = rgb8.data_format.get_color_bits_and_space();
+    let (width, height) = (rgb8.width, rgb8.height);
     let bbox = crate::CurTransMat::Identity;
     let interpolate = false;
+    let color_space = icc_profile
+        .and_then(|icc| icc_based_color_space(&icc, cs, opts, doc))
+        .unwrap_or_else(|| Name(cs.as_string().into()));
 
     let mut dict = lopdf::Dictionary::from_iter(vec![
         ("Type", Name("XObject".into())),
@@ -342,7 +1371,7 @@ pub(crate) fn image_to_stream(im: RawImage, doc: &mut lopdf::Document) -> lopdf:
         ("Width", Integer(rgb8.width as i64)),
         ("Height", Integer(rgb8.height as i64)),
         ("BitsPerComponent", Integer(bpc.as_integer())),
-        ("ColorSpace", Name(cs.as_string().into())),
+        ("ColorSpace", color_space.clone()),
         ("Interpolate", interpolate.into()),
         (
             "BBox",
@@ -350,41 +1379,384 @@ pub(crate) fn image_to_stream(im: RawImage, doc: &mut lopdf::Document) -> lopdf:
         ),
     ]);
 
+    let indexed = match (bpc, cs) {
+        (ColorBits::Bit8, ColorSpace::Rgb | ColorSpace::Greyscale | ColorSpace::Cmyk)
+            // color key ranges refer to the color components, not to the indices
+            if (opts.indexed_images || palette.is_some()) && color_key.is_none() =>
+        {
+            index_image_colors(&rgb8.pixels, cs, rgb8.width, palette.as_deref())
+        }
+        _ => None,
+    };
+
+    let pixels = match indexed {
+        Some(indexed) => {
+            let hival = (indexed.lookup.len() / indexed.components - 1) as i64;
+            dict.set("BitsPerComponent", Integer(indexed.bits.into()));
+            dict.set(
+                "ColorSpace",
+                Array(vec![
+                    Name("Indexed".into()),
+                    color_space,
+                    Integer(hival),
+                    String(indexed.lookup, lopdf::StringFormat::Hexadecimal),
+                ]),
+            );
+            indexed.indices
+        }
+        None => rgb8.pixels,
+    };
+
+    if let Some(ranges) = color_key.filter(|_| alpha.is_none()) {
+        let mask = ranges
+            .into_iter()
+            .flat_map(|(min, max)| [Integer(min.into()), Integer(max.into())]);
+        dict.set("Mask", Array(mask.collect()));
+    }
+
     if let Some(alpha) = alpha {
+        let (alpha_bpc, _) = alpha.data_format.get_color_bits_and_space();
         let smask_dict = lopdf::Dictionary::from_iter(vec![
             ("Type", Name("XObject".into())),
             ("Subtype", Name("Image".into())),
             ("Width", Integer(rgb8.width as i64)),
             ("Height", Integer(rgb8.height as i64)),
             ("Interpolate", Boolean(false)),
-            ("BitsPerComponent", Integer(ColorBits::Bit8.as_integer())),
+            ("BitsPerComponent", Integer(alpha_bpc.as_integer())),
             ("ColorSpace", Name(ColorSpace::Greyscale.as_string().into())),
         ]);
 
-        let mut stream = lopdf::Stream::new(smask_dict, alpha.pixels).with_compression(true);
-
-        let _ = stream.compress();
-
+        let stream = compressed_stream(smask_dict, alpha.pixels, opts);
         dict.set("SMask", Reference(doc.add_object(stream)));
     }
 
-    let mut s = lopdf::Stream::new(dict, rgb8.pixels).with_compression(true);
+    if bpc == ColorBits::Bit1 {
+        return bilevel_image_to_stream(dict, pixels, width, height, opts);
+    }
 
-    let _ = s.compress();
+    compressed_stream(dict, pixels, opts)
+}
 
+// Adds the ICC profile to the document and returns the `[/ICCBased profile]` color space,
+// or `None` if the profile is not for the color space of the pixels
+fn icc_based_color_space(
+    icc: &[u8],
+    cs: ColorSpace,
+    opts: &PdfSaveOptions,
+    doc: &mut lopdf::Document,
+) -> Option<lopdf::Object> {
+    use lopdf::Object::*;
+
+    // data color space signature in the profile header
+    let components = match (icc.get(16..20)?, cs) {
+        (b"GRAY", ColorSpace::Greyscale) => 1,
+        (b"RGB ", ColorSpace::Rgb) => 3,
+        (b"CMYK", ColorSpace::Cmyk) => 4,
+        _ => return None,
+    };
+
+    let dict = lopdf::Dictionary::from_iter(vec![
+        ("N", Integer(components)),
+        ("Alternate", Name(cs.as_string().into())),
+    ]);
+    let id = doc.add_object(compressed_stream(dict, icc.to_vec(), opts));
+    Some(Array(vec![Name("ICCBased".into()), Reference(id)]))
+}
+
+// Compresses the stream with Flate, unless image compression is disabled
+fn compressed_stream(
+    dict: lopdf::Dictionary,
+    data: Vec<u8>,
+    opts: &PdfSaveOptions,
+) -> lopdf::Stream {
+    let mut s = lopdf::Stream::new(dict, data).with_compression(opts.compress_images);
+    if opts.compress_images {
+        let _ = s.compress();
+    }
     s
 }
 
+// Compresses the pixels of a 1-bit image with CCITT Group 4 (if enabled) or Flate
+fn bilevel_image_to_stream(
+    mut dict: lopdf::Dictionary,
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    opts: &PdfSaveOptions,
+) -> lopdf::Stream {
+    use lopdf::Object::*;
+
+    if !opts.ccitt_bilevel_images || !opts.compress_images {
+        return compressed_stream(dict, pixels, opts);
+    }
+
+    let decode_parms = lopdf::Dictionary::from_iter(vec![
+        ("K", Integer(-1)),
+        ("Columns", Integer(width as i64)),
+        ("Rows", Integer(height as i64)),
+    ]);
+    dict.set("Filter", Name("CCITTFaxDecode".into()));
+    dict.set("DecodeParms", Dictionary(decode_parms));
+
+    let data = crate::ccitt::encode_g4(&pixels, width, height);
+    lopdf::Stream::new(dict, data).with_compression(false)
+}
+
+// Writes a 1-bit image as a stencil mask, painted with the current fill color
+fn stencil_mask_to_stream(im: RawImage, opts: &PdfSaveOptions) -> lopdf::Stream {
+    use lopdf::Object::*;
+
+    let dict = lopdf::Dictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Image".into())),
+        ("Width", Integer(im.width as i64)),
+        ("Height", Integer(im.height as i64)),
+        ("ImageMask", Boolean(true)),
+        ("BitsPerComponent", Integer(1)),
+        // sample value 0 (black) is painted with the fill color
+        ("Decode", Array(vec![Integer(0), Integer(1)])),
+        ("Interpolate", false.into()),
+    ]);
+
+    let pixels = match im.pixels {
+        RawImageData::U8(vec) => vec,
+        _ => Vec::new(),
+    };
+
+    bilevel_image_to_stream(dict, pixels, im.width, im.height, opts)
+}
+
+/// Pixels of an image with an `/Indexed` color space
+struct IndexedImage {
+    /// Color table: `components` bytes per color
+    lookup: Vec<u8>,
+    components: usize,
+    /// Bits per index: 1, 2, 4 or 8
+    bits: u8,
+    /// Packed indices, each row starts at a byte boundary
+    indices: Vec<u8>,
+}
+
+// Builds a color table if the image has at most 256 distinct colors, starting with
+// the colors of the `palette` of the source file (if any). Returns `None` if the
+// indexed image wouldn't be smaller
+fn index_image_colors(
+    pixels: &[u8],
+    cs: ColorSpace,
+    width: usize,
+    source_palette: Option<&[u8]>,
+) -> Option<IndexedImage> {
+    let components = match cs {
+        ColorSpace::Greyscale => 1,
+        ColorSpace::Cmyk => 4,
+        _ => 3,
+    };
+
+    if width == 0 || pixels.is_empty() {
+        return None;
+    }
+
+    let mut palette = std::collections::BTreeMap::<&[u8], u8>::new();
+    let mut lookup = Vec::new();
+    for px in source_palette
+        .filter(|_| components == 3)
+        .unwrap_or_default()
+        .chunks_exact(3)
+        .take(256)
+    {
+        // indices refer to the position in the lookup table, duplicates keep the first
+        let next = (lookup.len() / components) as u8;
+        palette.entry(px).or_insert(next);
+        lookup.extend_from_slice(px);
+    }
+    let mut indices = Vec::with_capacity(pixels.len() / components);
+    for px in pixels.chunks_exact(components) {
+        let next = lookup.len() / components;
+        let index = match palette.get(px) {
+            Some(i) => *i,
+            None if next < 256 => {
+                palette.insert(px, next as u8);
+                lookup.extend_from_slice(px);
+                next as u8
+            }
+            None => return None,
+        };
+        indices.push(index);
+    }
+
+    let bits: u8 = match lookup.len() / components {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+
+    // 8-bit indices into a gray color table are no smaller than the gray values
+    if bits == 8 && components == 1 {
+        return None;
+    }
+
+    let per_byte = 8 / bits as usize;
+    let row_bytes = width.div_ceil(per_byte);
+    let mut packed = Vec::with_capacity(row_bytes * (indices.len() / width));
+    for row in indices.chunks(width) {
+        for group in row.chunks(per_byte) {
+            let mut byte = 0_u8;
+            for (i, index) in group.iter().enumerate() {
+                byte |= index << (8 - bits as usize * (i + 1));
+            }
+            packed.push(byte);
+        }
+    }
+
+    Some(IndexedImage {
+        lookup,
+        components,
+        bits,
+        indices: packed,
+    })
+}
+
+// Embeds the compressed image data as-is, the PDF viewer decodes it
+fn encoded_image_to_stream(im: &EncodedImage, width: usize, height: usize) -> lopdf::Stream {
+    use lopdf::Object::*;
+
+    let mut dict = lopdf::Dictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Image".into())),
+        ("Width", Integer(width as i64)),
+        ("Height", Integer(height as i64)),
+        ("Interpolate", false.into()),
+        ("Filter", Name(im.filter.get_filter_name().into())),
+    ]);
+
+    // JPXDecode ignores the BitsPerComponent and uses the color space of the JP2 file
+    // if no ColorSpace is given, raw codestreams don't carry a color space
+    if im.filter != ImageFilter::JPX {
+        dict.set("BitsPerComponent", Integer(im.bits_per_component.into()));
+    }
+    if im.filter != ImageFilter::JPX || !im.bytes.starts_with(JP2_SIGNATURE) {
+        dict.set("ColorSpace", Name(im.get_color_space().as_string().into()));
+    }
+
+    if im.inverted {
+        let decode = (0..im.components).flat_map(|_| [Integer(1), Integer(0)]);
+        dict.set("Decode", Array(decode.collect()));
+    }
+
+    lopdf::Stream::new(dict, im.bytes.clone()).with_compression(false)
+}
+
+/// Compresses the image as JPEG, see `PdfSaveOptions::jpeg_quality`. Returns `None`
+/// if the image is not an 8-bit RGB or grayscale image, has transparency or has few
+/// enough colors to be written with an indexed color space instead.
+#[allow(unused_variables)]
+pub(crate) fn image_to_jpeg(
+    im: &RawImage,
+    quality: u8,
+    opts: &PdfSaveOptions,
+) -> Option<EncodedImage> {
+    #[cfg(feature = "jpeg")]
+    {
+        use image::ExtendedColorType;
+
+        let (color_type, cs) = match im.data_format {
+            RawImageFormat::R8 => (ExtendedColorType::L8, ColorSpace::Greyscale),
+            RawImageFormat::RGB8 => (ExtendedColorType::Rgb8, ColorSpace::Rgb),
+            _ => return None,
+        };
+        let RawImageData::U8(pixels) = &im.pixels else {
+            return None;
+        };
+        if im.mask.is_some()
+            || ((opts.indexed_images || im.palette.is_some())
+                && index_image_colors(pixels, cs, im.width, im.palette.as_deref()).is_some())
+        {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100))
+            .encode(pixels, im.width as u32, im.height as u32, color_type)
+            .ok()?;
+        EncodedImage::from_jpeg_bytes(&bytes)
+            .ok()
+            .map(|(encoded, _, _)| encoded)
+    }
+
+    #[cfg(not(feature = "jpeg"))]
+    {
+        None
+    }
+}
+
+/// Maximum size of the encoded data of an inline image recommended by the PDF
+/// specification (section 8.9.7, "Inline Images")
+pub const INLINE_IMAGE_MAX_SIZE: usize = 4096;
+
+/// Encodes the image as an inline image (`BI ... ID ... EI`), compressed with
+/// `/FlateDecode` and ASCII85-encoded so that the data can't contain a stray `EI`.
+///
+/// Returns `None` if the image can't be written inline, either because it has an
+/// alpha channel or ICC profile (inline images can't have a `/SMask` or reference
+/// an `/ICCBased` color space) or because the encoded data is larger than `max_size` bytes.
+pub(crate) fn image_to_inline_image(im: &RawImage, max_size: usize) -> Option<Vec<u8>> {
+    // images without decoded pixels (JPEG, JPEG 2000) are embedded as-is
+    if im.pixels.is_empty()
+        || im.data_format.has_alpha()
+        || im.mask.is_some()
+        || im.icc_profile.is_some()
+    {
+        return None;
+    }
+
+    let (bpc, cs) = im.data_format.get_color_bits_and_space();
+    let cs = match cs {
+        ColorSpace::Rgb => "RGB",
+        ColorSpace::Greyscale => "G",
+        ColorSpace::Cmyk => "CMYK",
+        _ => return None,
+    };
+
+    let (rgb8, _) = split_rawimage_into_rgb_plus_alpha(im.clone());
+    let data = crate::utils::ascii85_encode(&crate::utils::compress_zlib(&rgb8.pixels));
+    if data.len() > max_size {
+        return None;
+    }
+
+    let mut bytes = format!(
+        "BI\n/W {} /H {} /BPC {} /CS /{cs} /F [/A85 /Fl]\nID\n",
+        rgb8.width,
+        rgb8.height,
+        bpc.as_integer()
+    )
+    .into_bytes();
+    bytes.extend(data);
+    bytes.extend_from_slice(b"\nEI\n");
+    Some(bytes)
+}
+
 // If the image has an alpha channel, splits the alpha channel as a separate image
 // to the used in the `/Smask` dictionary
 fn split_rawimage_into_rgb_plus_alpha(im: RawImage) -> (RawImageU8, Option<RawImageU8>) {
     let has_alpha = im.data_format.has_alpha();
 
+    let is_cmyk = im.data_format.is_cmyk();
+    let alpha_format = match im.pixels {
+        RawImageData::U16(_) => RawImageFormat::R16,
+        _ => RawImageFormat::R8,
+    };
+
     let (orig, alpha) = if has_alpha {
         match im.pixels {
+            RawImageData::U8(vec) if is_cmyk => crate::utils::cmyka_to_cmyk(vec),
             RawImageData::U8(vec) => crate::utils::rgba_to_rgb(vec),
             RawImageData::U16(vec) => {
-                let (d, alpha) = crate::utils::rgba_to_rgb16(vec);
+                let (d, alpha) = if is_cmyk {
+                    crate::utils::cmyka_to_cmyk(vec)
+                } else {
+                    crate::utils::rgba_to_rgb16(vec)
+                };
                 (
                     crate::utils::u16vec_to_u8(d),
                     crate::utils::u16vec_to_u8(alpha),
@@ -420,14 +1792,64 @@ fn split_rawimage_into_rgb_plus_alpha(im: RawImage) -> (RawImageU8, Option<RawIm
             pixels: alpha,
             width: im.width,
             height: im.height,
-            data_format: RawImageFormat::R8,
+            data_format: alpha_format,
         })
     };
 
     (orig, alpha_mask)
 }
 
+#[test]
+fn test_cmyka_image_stream() {
+    use lopdf::Object::{Integer, Name, Reference};
+
+    // 2 x 1 CMYKA image: the alpha channel is written as a separate /SMask
+    let mut im = RawImage::empty(2, 1, RawImageFormat::CMYKA8);
+    im.pixels = RawImageData::U8(vec![10, 20, 30, 40, 255, 50, 60, 70, 80, 128]);
+    let (cmyk, alpha) = split_rawimage_into_rgb_plus_alpha(im.clone());
+    assert_eq!(cmyk.data_format, RawImageFormat::CMYK8);
+    assert_eq!(cmyk.pixels, vec![10, 20, 30, 40, 50, 60, 70, 80]);
+    let alpha = alpha.unwrap();
+    assert_eq!(alpha.data_format, RawImageFormat::R8);
+    assert_eq!(alpha.pixels, vec![255, 128]);
+
+    let mut im16 = RawImage::empty(2, 1, RawImageFormat::CMYKA16);
+    im16.pixels = RawImageData::U16(vec![1000, 2000, 3000, 4000, 65535, 0, 0, 0, 0, 0]);
+
+    let opts = PdfSaveOptions {
+        compress_images: false,
+        ..Default::default()
+    };
+    for (im, bpc) in [(im, 8), (im16, 16)] {
+        let mut doc = lopdf::Document::new();
+        let stream = image_to_stream(im, &PdfConformance::default(), &opts, &mut doc);
+        assert_eq!(
+            stream.dict.get(b"ColorSpace").unwrap(),
+            &Name("DeviceCMYK".into())
+        );
+        assert_eq!(stream.dict.get(b"BitsPerComponent").unwrap(), &Integer(bpc));
+        assert_eq!(stream.content.len(), 2 * 4 * bpc as usize / 8);
+
+        let Ok(Reference(smask)) = stream.dict.get(b"SMask") else {
+            panic!("SMask missing");
+        };
+        let smask = doc.get_object(*smask).unwrap().as_stream().unwrap();
+        assert_eq!(smask.dict.get(b"BitsPerComponent").unwrap(), &Integer(bpc));
+        assert_eq!(smask.content.len(), 2 * bpc as usize / 8);
+    }
+}
+
 pub fn translate_to_internal_rawimage(im: &RawImage) -> azul_core::app_resources::RawImage {
+    if im.data_format.is_cmyk() {
+        return translate_to_internal_rawimage(&cmyk_to_rgb(im));
+    }
+    if im.data_format == RawImageFormat::R1 {
+        return translate_to_internal_rawimage(&bilevel_to_r8(im));
+    }
+    if im.orientation != ImageOrientation::Normal {
+        return translate_to_internal_rawimage(&im.apply_orientation());
+    }
+
     azul_core::app_resources::RawImage {
         pixels: match &im.pixels {
             RawImageData::U8(vec) => azul_core::app_resources::RawImageData::U8(vec.clone().into()),
@@ -445,3 +1867,678 @@ pub fn translate_to_internal_rawimage(im: &RawImage) -> azul_core::app_resources
         tag: im.tag.clone().into(),
     }
 }
+
+// Converts the pixels of a decoded image to (width, height, format, pixels)
+fn from_dynamic_image(
+    im: image::DynamicImage,
+) -> Result<(usize, usize, RawImageFormat, RawImageData), String> {
+    use image::DynamicImage::*;
+
+    let (w, h) = im.dimensions();
+    let ct = match im.color() {
+        image::ColorType::L8 => RawImageFormat::R8,
+        image::ColorType::La8 => RawImageFormat::RG8,
+        image::ColorType::Rgb8 => RawImageFormat::RGB8,
+        image::ColorType::Rgba8 => RawImageFormat::RGBA8,
+        image::ColorType::L16 => RawImageFormat::R16,
+        image::ColorType::La16 => RawImageFormat::RG16,
+        image::ColorType::Rgb16 => RawImageFormat::RGB16,
+        image::ColorType::Rgba16 => RawImageFormat::RGBA16,
+        image::ColorType::Rgb32F => RawImageFormat::RGBF32,
+        image::ColorType::Rgba32F => RawImageFormat::RGBAF32,
+        _ => return Err("invalid raw image format".to_string()),
+    };
+
+    let pixels = match im {
+        ImageLuma8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
+        ImageLumaA8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
+        ImageRgb8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
+        ImageRgba8(image_buffer) => RawImageData::U8(image_buffer.into_raw()),
+        ImageLuma16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
+        ImageLumaA16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
+        ImageRgb16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
+        ImageRgba16(image_buffer) => RawImageData::U16(image_buffer.into_raw()),
+        ImageRgb32F(image_buffer) => RawImageData::F32(image_buffer.into_raw()),
+        ImageRgba32F(image_buffer) => RawImageData::F32(image_buffer.into_raw()),
+        _ => return Err("invalid pixel format".to_string()),
+    };
+
+    Ok((w as usize, h as usize, ct, pixels))
+}
+
+// Decodes the (composited) RGBA frames of an animated GIF or PNG file
+#[cfg(any(feature = "gif", feature = "png"))]
+fn decode_animation_frames(frames: image::Frames<'_>) -> Result<Vec<RawImage>, String> {
+    frames
+        .enumerate()
+        .map(|(i, frame)| {
+            let frame = frame.map_err(|e| format!("cannot decode frame {i}: {e}"))?;
+            let buffer = image::DynamicImage::ImageRgba8(frame.into_buffer());
+            let (width, height, data_format, pixels) = from_dynamic_image(buffer)?;
+            Ok(RawImage {
+                pixels,
+                width,
+                height,
+                data_format,
+                tag: Vec::new(),
+                encoded: None,
+                mask: None,
+                icc_profile: None,
+                orientation: ImageOrientation::Normal,
+                dpi: None,
+                palette: None,
+            })
+        })
+        .collect()
+}
+
+// Decodes up to `max_frames` frames (images) of a TIFF file
+#[cfg(feature = "tiff")]
+fn decode_tiff_frames(bytes: &[u8], max_frames: usize) -> Result<Vec<RawImage>, String> {
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let mut frames = Vec::new();
+    loop {
+        let frame = decode_tiff_frame(&mut decoder, bytes)
+            .map_err(|e| format!("cannot decode TIFF frame {}: {e}", frames.len()))?;
+        frames.push(frame);
+        if frames.len() >= max_frames || !decoder.more_images() {
+            break;
+        }
+        decoder.next_image().map_err(|e| e.to_string())?;
+    }
+    Ok(frames)
+}
+
+// Decodes the current frame of the TIFF decoder
+#[cfg(feature = "tiff")]
+fn decode_tiff_frame(
+    decoder: &mut tiff::decoder::Decoder<Cursor<&[u8]>>,
+    bytes: &[u8],
+) -> Result<RawImage, String> {
+    use self::RawImageFormat::*;
+    use crate::ccitt::FaxCompression;
+    use tiff::decoder::{ifd::Value, DecodingResult};
+    use tiff::tags::Tag;
+    use tiff::ColorType;
+
+    let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+    let (width, height) = (width as usize, height as usize);
+
+    // CCITT (fax) compression is not supported by the tiff crate
+    let compression = decoder.get_tag_u32(Tag::Compression).ok();
+    let fax_compression = match compression {
+        Some(2) => Some(FaxCompression::Huffman),
+        Some(3) => {
+            // T4Options (292): bit 0 = 2D coding, bit 1 = uncompressed mode
+            let options = decoder.get_tag_u32(Tag::Unknown(292)).unwrap_or(0);
+            if options & 2 != 0 {
+                return Err("uncompressed mode of CCITT Group 3 is not supported".to_string());
+            }
+            Some(FaxCompression::Group3 {
+                two_dimensional: options & 1 != 0,
+            })
+        }
+        Some(4) => Some(FaxCompression::Group4),
+        _ => None,
+    };
+
+    let mut palette = None;
+    let (data_format, pixels) = if let Some(fax_compression) = fax_compression {
+        let pixels = decode_tiff_fax(decoder, bytes, width, height, fax_compression)?;
+        (R1, RawImageData::U8(pixels))
+    } else {
+        let color_type = decoder.colortype().map_err(|e| e.to_string())?;
+        match (color_type, decoder.read_image().map_err(|e| e.to_string())?) {
+            (ColorType::Gray(1), DecodingResult::U8(vec)) => (R1, RawImageData::U8(vec)),
+            (ColorType::Gray(bits @ (2 | 4)), DecodingResult::U8(vec)) => {
+                let max = (1_u32 << bits) - 1;
+                let pixels = unpack_tiff_samples(&vec, width, height, bits)
+                    .into_iter()
+                    .map(|v| (v as u32 * 255 / max) as u8)
+                    .collect();
+                (R8, RawImageData::U8(pixels))
+            }
+            (ColorType::Palette(bits @ (1 | 2 | 4 | 8)), DecodingResult::U8(vec)) => {
+                // ColorMap: all red, then all green, then all blue values (16 bit)
+                let color_map = decoder
+                    .get_tag_u16_vec(Tag::ColorMap)
+                    .map_err(|e| e.to_string())?;
+                let entries = 1_usize << bits;
+                if color_map.len() < entries * 3 {
+                    return Err("invalid TIFF color map".to_string());
+                }
+                let rgb_palette: Vec<u8> = (0..entries)
+                    .flat_map(|i| [0, 1, 2].map(|c| (color_map[c * entries + i] >> 8) as u8))
+                    .collect();
+                let mut pixels = Vec::with_capacity(width * height * 3);
+                for i in unpack_tiff_samples(&vec, width, height, bits) {
+                    let i = i as usize * 3;
+                    pixels.extend_from_slice(&rgb_palette[i..i + 3]);
+                }
+                palette = Some(rgb_palette);
+                (RGB8, RawImageData::U8(pixels))
+            }
+            // the JPEG decoder converts JPEG compressed (7) YCbCr frames to RGB
+            (ColorType::YCbCr(8), DecodingResult::U8(vec))
+                if compression == Some(7) && vec.len() == width * height * 3 =>
+            {
+                (RGB8, RawImageData::U8(vec))
+            }
+            (ColorType::Gray(8), DecodingResult::U8(vec)) => (R8, RawImageData::U8(vec)),
+            (ColorType::GrayA(8), DecodingResult::U8(vec)) => (RG8, RawImageData::U8(vec)),
+            (ColorType::RGB(8), DecodingResult::U8(vec)) => (RGB8, RawImageData::U8(vec)),
+            (ColorType::RGBA(8), DecodingResult::U8(vec)) => (RGBA8, RawImageData::U8(vec)),
+            (ColorType::CMYK(8), DecodingResult::U8(vec)) => (CMYK8, RawImageData::U8(vec)),
+            (ColorType::Gray(16), DecodingResult::U16(vec)) => (R16, RawImageData::U16(vec)),
+            (ColorType::GrayA(16), DecodingResult::U16(vec)) => (RG16, RawImageData::U16(vec)),
+            (ColorType::RGB(16), DecodingResult::U16(vec)) => (RGB16, RawImageData::U16(vec)),
+            (ColorType::RGBA(16), DecodingResult::U16(vec)) => (RGBA16, RawImageData::U16(vec)),
+            (ColorType::CMYK(16), DecodingResult::U16(vec)) => (CMYK16, RawImageData::U16(vec)),
+            (color_type, _) => return Err(format!("unsupported TIFF color type: {color_type:?}")),
+        }
+    };
+
+    let dpi = match decoder.find_tag(Tag::XResolution) {
+        Ok(Some(Value::Rational(n, d))) if n > 0 && d > 0 => {
+            let resolution = n as f32 / d as f32;
+            // 2 = inch (default), 3 = centimeter
+            match decoder.get_tag_u32(Tag::ResolutionUnit).unwrap_or(2) {
+                2 => Some(resolution),
+                3 => Some(resolution * 2.54),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    // Orientation (274) and ICC profile (34675) tags
+    let orientation = decoder
+        .get_tag_u32(Tag::Unknown(274))
+        .map_or(ImageOrientation::Normal, |o| {
+            ImageOrientation::from_exif(o as u16)
+        });
+    let icc_profile = decoder.get_tag_u8_vec(Tag::Unknown(34675)).ok();
+
+    Ok(RawImage {
+        pixels,
+        width,
+        height,
+        data_format,
+        tag: Vec::new(),
+        encoded: None,
+        mask: None,
+        icc_profile,
+        orientation,
+        dpi,
+        palette,
+    })
+}
+
+// Unpacks the 1, 2, 4 or 8 bit samples of a TIFF frame with one sample per pixel
+// (rows start at a byte boundary)
+#[cfg(feature = "tiff")]
+fn unpack_tiff_samples(data: &[u8], width: usize, height: usize, bits: u8) -> Vec<u8> {
+    let bits = bits as usize;
+    let row_bytes = (width * bits).div_ceil(8);
+    let mask = ((1_u16 << bits) - 1) as u8;
+    data.chunks(row_bytes)
+        .take(height)
+        .flat_map(|row| {
+            (0..width).map(move |x| {
+                let bit = x * bits;
+                let byte = row.get(bit / 8).copied().unwrap_or(0);
+                (byte >> (8 - bits - bit % 8)) & mask
+            })
+        })
+        .collect()
+}
+
+// Decodes the strips of a CCITT (fax) compressed TIFF frame
+#[cfg(feature = "tiff")]
+fn decode_tiff_fax(
+    decoder: &mut tiff::decoder::Decoder<Cursor<&[u8]>>,
+    bytes: &[u8],
+    width: usize,
+    height: usize,
+    compression: crate::ccitt::FaxCompression,
+) -> Result<Vec<u8>, String> {
+    use tiff::tags::Tag;
+
+    let offsets = decoder
+        .get_tag_u32_vec(Tag::StripOffsets)
+        .map_err(|e| e.to_string())?;
+    let byte_counts = decoder
+        .get_tag_u32_vec(Tag::StripByteCounts)
+        .map_err(|e| e.to_string())?;
+    let rows_per_strip = decoder
+        .get_tag_u32(Tag::RowsPerStrip)
+        .map_or(height, |rows| rows as usize)
+        .max(1);
+    // FillOrder (266) = 2: the least significant bit of each byte comes first
+    let reversed = decoder.get_tag_u32(Tag::Unknown(266)).ok() == Some(2);
+    // fax data encodes white and black runs, which are inverted for BlackIsZero (1)
+    let black_is_zero = decoder.get_tag_u32(Tag::PhotometricInterpretation).ok() == Some(1);
+
+    let mut pixels = Vec::with_capacity(width.div_ceil(8) * height);
+    for (i, (offset, len)) in offsets.iter().zip(byte_counts.iter()).enumerate() {
+        let rows = rows_per_strip.min(height.saturating_sub(i * rows_per_strip));
+        let (start, len) = (*offset as usize, *len as usize);
+        let data = bytes
+            .get(start..start.saturating_add(len))
+            .ok_or_else(|| format!("truncated TIFF strip at offset {start}"))?;
+        let strip = if reversed {
+            let data: Vec<u8> = data.iter().map(|b| b.reverse_bits()).collect();
+            crate::ccitt::decode_fax(&data, width, rows, compression)
+        } else {
+            crate::ccitt::decode_fax(data, width, rows, compression)
+        };
+        pixels.extend(strip.ok_or_else(|| format!("invalid CCITT data in TIFF strip {i}"))?);
+    }
+
+    if black_is_zero {
+        pixels.iter_mut().for_each(|b| *b = !*b);
+    }
+
+    Ok(pixels)
+}
+
+// Decodes CMYK JPEG images without converting them to RGB (CMYK TIFF images are
+// decoded by `decode_tiff_frames`), returns `None` if the image is not a CMYK image
+#[allow(unused_variables)]
+fn decode_cmyk(
+    format: image::ImageFormat,
+    bytes: &[u8],
+) -> Option<(usize, usize, RawImageFormat, RawImageData)> {
+    match format {
+        #[cfg(feature = "jpeg")]
+        image::ImageFormat::Jpeg => {
+            let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(bytes));
+            decoder.read_info().ok()?;
+            let info = decoder.info()?;
+            if !matches!(info.pixel_format, jpeg_decoder::PixelFormat::CMYK32) {
+                return None;
+            }
+            // jpeg-decoder already reverts the Adobe inversion
+            let pixels = decoder.decode().ok()?;
+            Some((
+                info.width as usize,
+                info.height as usize,
+                RawImageFormat::CMYK8,
+                RawImageData::U8(pixels),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn split_cmyk<T: Copy>(pixels: &[T]) -> (Vec<T>, Vec<T>) {
+    let cmy = pixels
+        .chunks_exact(4)
+        .flat_map(|px| [px[0], px[1], px[2]])
+        .collect();
+    let k = pixels.chunks_exact(4).map(|px| px[3]).collect();
+    (cmy, k)
+}
+
+fn merge_cmyk<T: Copy>(cmy: &[T], k: &[T]) -> Vec<T> {
+    cmy.chunks_exact(3)
+        .zip(k.iter())
+        .flat_map(|(c, k)| [c[0], c[1], c[2], *k])
+        .collect()
+}
+
+// Unpacks a 1-bit image to 8-bit grayscale, only used to render the image in the HTML layout
+fn bilevel_to_r8(im: &RawImage) -> RawImage {
+    let row_bytes = im.width.div_ceil(8);
+    let packed = match &im.pixels {
+        RawImageData::U8(vec) => vec.as_slice(),
+        _ => &[],
+    };
+
+    let mut pixels = Vec::with_capacity(im.width * im.height);
+    for row in packed.chunks(row_bytes.max(1)).take(im.height) {
+        pixels.extend((0..im.width).map(|col| {
+            let bit = row.get(col / 8).map_or(0, |b| b & (0x80 >> (col % 8)));
+            if bit == 0 {
+                0
+            } else {
+                255
+            }
+        }));
+    }
+
+    RawImage {
+        pixels: RawImageData::U8(pixels),
+        width: im.width,
+        height: im.height,
+        data_format: RawImageFormat::R8,
+        tag: im.tag.clone(),
+        encoded: None,
+        mask: None,
+        icc_profile: None,
+        orientation: im.orientation,
+        dpi: im.dpi,
+        palette: None,
+    }
+}
+
+// Naive CMYK to RGB conversion (without color management), only used to render the
+// image in the HTML layout, the PDF keeps the original CMYK data
+fn cmyk_to_rgb(im: &RawImage) -> RawImage {
+    fn convert<T: Copy + Into<f32>>(
+        pixels: &[T],
+        channels: usize,
+        max: f32,
+        from_f32: impl Fn(f32) -> T,
+    ) -> Vec<T> {
+        pixels
+            .chunks_exact(channels)
+            .flat_map(|px| {
+                let k = 1.0 - px[3].into() / max;
+                let rgb = [px[0], px[1], px[2]].map(|c| from_f32((1.0 - c.into() / max) * k * max));
+                rgb.into_iter().chain(px.get(4).copied())
+            })
+            .collect()
+    }
+
+    let channels = if im.data_format.has_alpha() { 5 } else { 4 };
+    let pixels = match &im.pixels {
+        RawImageData::U8(vec) => {
+            RawImageData::U8(convert(vec, channels, 255.0, |f| f.round() as u8))
+        }
+        RawImageData::U16(vec) => {
+            RawImageData::U16(convert(vec, channels, 65535.0, |f| f.round() as u16))
+        }
+        RawImageData::F32(vec) => RawImageData::F32(convert(vec, channels, 1.0, |f| f)),
+    };
+
+    let data_format = match im.data_format {
+        RawImageFormat::CMYK8 => RawImageFormat::RGB8,
+        RawImageFormat::CMYKA8 => RawImageFormat::RGBA8,
+        RawImageFormat::CMYK16 => RawImageFormat::RGB16,
+        RawImageFormat::CMYKA16 => RawImageFormat::RGBA16,
+        other => other,
+    };
+
+    RawImage {
+        pixels,
+        width: im.width,
+        height: im.height,
+        data_format,
+        tag: im.tag.clone(),
+        encoded: None,
+        mask: None,
+        icc_profile: None,
+        orientation: im.orientation,
+        dpi: im.dpi,
+        palette: None,
+    }
+}
+
+#[test]
+fn test_jpeg_header_adobe_cmyk() {
+    let mut jpeg = vec![0xFF, 0xD8];
+    // APP14 "Adobe" segment
+    jpeg.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+    jpeg.extend_from_slice(b"Adobe\x00\x64\x00\x00\x00\x00\x02");
+    // SOF0: 8 bit, 20 x 10 pixels, 4 components
+    jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x14, 0x08, 0x00, 0x0A, 0x00, 0x14, 0x04]);
+    jpeg.extend_from_slice(&[0; 12]);
+
+    let (encoded, width, height) = EncodedImage::from_jpeg_bytes(&jpeg).unwrap();
+    assert_eq!((width, height), (20, 10));
+    assert_eq!(encoded.components, 4);
+    assert!(encoded.inverted);
+    assert_eq!(encoded.get_color_space(), ColorSpace::Cmyk);
+
+    // modified pixels replace the original JPEG data
+    let mut im = RawImage::empty(width, height, RawImageFormat::CMYK8);
+    im.encoded = Some(encoded);
+    im.clear_encoded();
+    assert!(im.get_encoded().is_some());
+    im.pixels = RawImageData::U8(vec![0; width * height * 4]);
+    im.clear_encoded();
+    assert!(im.get_encoded().is_none());
+}
+
+#[test]
+fn test_jpeg_without_decoding() {
+    let mut jpeg = vec![0xFF, 0xD8];
+    // SOF0: 8 bit, 20 x 10 pixels, 3 components
+    jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x0A, 0x00, 0x14, 0x03]);
+    jpeg.extend_from_slice(&[0; 9]);
+
+    let im = RawImage::from_jpeg_bytes(&jpeg).unwrap();
+    assert_eq!((im.width, im.height), (20, 10));
+    assert_eq!(im.data_format, RawImageFormat::RGB8);
+    assert!(im.pixels.is_empty());
+    assert!(image_to_inline_image(&im, usize::MAX).is_none());
+
+    let conformance = PdfConformance::default();
+    assert!(im.check_conformance(&conformance).is_ok());
+    let opts = PdfSaveOptions::default();
+    let mut doc = lopdf::Document::new();
+    let stream = image_to_stream(im, &conformance, &opts, &mut doc);
+    assert_eq!(
+        stream.dict.get(b"Filter").unwrap(),
+        &lopdf::Object::Name("DCTDecode".into())
+    );
+    assert_eq!(stream.content, jpeg);
+}
+
+#[test]
+fn test_jp2_header() {
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&30_u32.to_be_bytes()); // height
+    ihdr.extend_from_slice(&40_u32.to_be_bytes()); // width
+    ihdr.extend_from_slice(&[0x00, 0x03, 0x07, 0x07, 0x00, 0x00]);
+
+    let mut jp2h = (8 + ihdr.len() as u32).to_be_bytes().to_vec();
+    jp2h.extend_from_slice(b"ihdr");
+    jp2h.extend_from_slice(&ihdr);
+
+    let mut jp2 = JP2_SIGNATURE.to_vec();
+    jp2.extend_from_slice(&(8 + jp2h.len() as u32).to_be_bytes());
+    jp2.extend_from_slice(b"jp2h");
+    jp2.extend_from_slice(&jp2h);
+    // codestream box extending to the end of the file
+    jp2.extend_from_slice(&[0, 0, 0, 0]);
+    jp2.extend_from_slice(b"jp2c");
+
+    let (encoded, width, height) = EncodedImage::from_jpx_bytes(&jp2).unwrap();
+    assert_eq!((width, height), (40, 30));
+    assert_eq!(encoded.components, 3);
+    assert_eq!(encoded.bits_per_component, 8);
+    assert_eq!(encoded.filter, ImageFilter::JPX);
+
+    let im = RawImage::from_jpx_bytes(&jp2).unwrap();
+    let opts = PdfSaveOptions::default();
+    let mut doc = lopdf::Document::new();
+
+    let stream = image_to_stream(im.clone(), &PdfConformance::default(), &opts, &mut doc);
+    assert!(im.uses_jpx(&PdfConformance::default()));
+    assert_eq!(
+        stream.dict.get(b"Filter").unwrap(),
+        &lopdf::Object::Name("JPXDecode".into())
+    );
+
+    // PDF/A-1 doesn't allow JPEG 2000 and there are no pixels to embed instead
+    let conformance = PdfConformance::A1B_2005_PDF_1_4;
+    assert!(!im.uses_jpx(&conformance));
+    assert!(im.check_conformance(&conformance).is_err());
+
+    let mut pdf = crate::PdfDocument::new("JPX");
+    pdf.metadata.info.conformance = conformance;
+    let id = pdf.add_image(&im);
+    let err = pdf.save(&opts).unwrap_err();
+    assert!(err.contains(&id.0), "{err}");
+    assert!(err.contains("PDF/A-1b"), "{err}");
+}
+
+#[test]
+fn test_index_image_colors() {
+    // 3 x 2 RGB image with 3 colors: 2-bit indices, rows padded to a full byte
+    let red = [255, 0, 0];
+    let blue = [0, 0, 255];
+    let white = [255, 255, 255];
+    let pixels = [red, blue, white, white, red, red].concat();
+
+    let indexed = index_image_colors(&pixels, ColorSpace::Rgb, 3, None).unwrap();
+    assert_eq!(indexed.bits, 2);
+    assert_eq!(indexed.lookup, [red, blue, white].concat());
+    assert_eq!(indexed.indices, vec![0b0001_1000, 0b1000_0000]);
+
+    // the palette of the source file keeps its order, even for unused colors
+    let palette = [white, red, blue].concat();
+    let indexed = index_image_colors(&pixels, ColorSpace::Rgb, 3, Some(&palette)).unwrap();
+    assert_eq!(indexed.lookup, palette);
+    assert_eq!(indexed.indices, vec![0b0110_0000, 0b0001_0100]);
+}
+
+#[test]
+fn test_bilevel_roundtrip() {
+    // 9 x 1 RGB image: black, white, black ... (row padded to 2 bytes)
+    let pixels = (0..9)
+        .flat_map(|i| {
+            if i % 2 == 0 {
+                [0, 0, 0]
+            } else {
+                [255, 255, 255]
+            }
+        })
+        .collect();
+    let mut im = RawImage::empty(9, 1, RawImageFormat::RGB8);
+    im.pixels = RawImageData::U8(pixels);
+
+    let bilevel = im.to_bilevel(128);
+    assert_eq!(bilevel.data_format, RawImageFormat::R1);
+    assert_eq!(
+        bilevel.pixels,
+        RawImageData::U8(vec![0b0101_0101, 0b0000_0000])
+    );
+
+    let unpacked = bilevel_to_r8(&bilevel);
+    assert_eq!(
+        unpacked.pixels,
+        RawImageData::U8(vec![0, 255, 0, 255, 0, 255, 0, 255, 0])
+    );
+}
+
+#[cfg(feature = "tiff")]
+#[test]
+fn test_tiff_fax_strips() {
+    // 40 x 10 image with diagonal black lines, in three CCITT Group 4 strips
+    let (width, height, rows_per_strip) = (40_usize, 10, 4);
+    let row_bytes = width.div_ceil(8);
+    let mut pixels = vec![0_u8; row_bytes * height];
+    for y in 0..height {
+        for x in (0..width).filter(|x| (x + y) % 7 != 0) {
+            pixels[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    let strips: Vec<Vec<u8>> = pixels
+        .chunks(row_bytes * rows_per_strip)
+        .map(|strip| crate::ccitt::encode_g4(strip, width, strip.len() / row_bytes))
+        .collect();
+
+    // little endian TIFF: header, strips, strip offsets and byte counts, IFD
+    let mut tiff = b"II*\0\0\0\0\0".to_vec();
+    let mut offsets = Vec::new();
+    for strip in &strips {
+        offsets.push(tiff.len() as u32);
+        tiff.extend(strip);
+    }
+    let arrays = tiff.len() as u32;
+    for offset in &offsets {
+        tiff.extend(offset.to_le_bytes());
+    }
+    for strip in &strips {
+        tiff.extend((strip.len() as u32).to_le_bytes());
+    }
+    let ifd = tiff.len() as u32;
+    tiff[4..8].copy_from_slice(&ifd.to_le_bytes());
+
+    // tag, type (3 = SHORT, 4 = LONG), count, value or offset
+    let count = strips.len() as u32;
+    let entries: [(u16, u16, u32, u32); 9] = [
+        (256, 4, 1, width as u32),
+        (257, 4, 1, height as u32),
+        (258, 3, 1, 1),
+        (259, 3, 1, 4),
+        (262, 3, 1, 0),
+        (273, 4, count, arrays),
+        (277, 3, 1, 1),
+        (278, 4, 1, rows_per_strip as u32),
+        (279, 4, count, arrays + 4 * count),
+    ];
+    tiff.extend((entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries {
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(kind.to_le_bytes());
+        tiff.extend(count.to_le_bytes());
+        tiff.extend(value.to_le_bytes());
+    }
+    tiff.extend(0_u32.to_le_bytes());
+
+    let frames = RawImage::decode_all_frames(&tiff).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].data_format, RawImageFormat::R1);
+    assert_eq!((frames[0].width, frames[0].height), (width, height));
+    assert_eq!(frames[0].pixels, RawImageData::U8(pixels));
+}
+
+#[cfg(feature = "tiff")]
+#[test]
+fn test_decode_cmyk_tiff() {
+    // the image crate would convert the pixels to RGB
+    let pixels = [10_u8, 20, 30, 40, 50, 60, 70, 80];
+    let mut tiff = Cursor::new(Vec::new());
+    tiff::encoder::TiffEncoder::new(&mut tiff)
+        .unwrap()
+        .write_image::<tiff::encoder::colortype::CMYK8>(2, 1, &pixels)
+        .unwrap();
+
+    let im = RawImage::decode_from_bytes(tiff.get_ref()).unwrap();
+    assert_eq!(im.data_format, RawImageFormat::CMYK8);
+    assert_eq!((im.width, im.height), (2, 1));
+    assert_eq!(im.pixels, RawImageData::U8(pixels.to_vec()));
+}
+
+#[test]
+fn test_exif_orientation() {
+    // JPEG with an APP1 "Exif" segment: big-endian TIFF header, one IFD entry
+    // Orientation = 6 (SHORT), then the start of scan
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x22];
+    jpeg.extend_from_slice(b"Exif\0\0MM\0\x2A\0\0\0\x08");
+    jpeg.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0]);
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+
+    let metadata = read_image_metadata(image::ImageFormat::Jpeg, &jpeg);
+    assert_eq!(metadata.orientation, ImageOrientation::Rotate90);
+
+    let mut im = RawImage::empty(3, 2, RawImageFormat::R8);
+    im.pixels = RawImageData::U8(vec![1, 2, 3, 4, 5, 6]);
+    im.orientation = metadata.orientation;
+    assert_eq!(im.get_oriented_size(), (2, 3));
+
+    // rotated 90 degrees clockwise: the bottom-left pixel ends up top-left
+    let rotated = im.apply_orientation();
+    assert_eq!(rotated.pixels, RawImageData::U8(vec![4, 1, 5, 2, 6, 3]));
+    assert_eq!(rotated.orientation, ImageOrientation::Normal);
+}
+
+#[test]
+fn test_page_fit() {
+    // 2480 x 1754 px at 300 DPI: landscape A4
+    let mut im = RawImage::empty(2480, 1754, RawImageFormat::R8);
+    im.dpi = Some(300.0);
+
+    let page = PageFit::ImageSize.get_page(&im, XObjectId::new());
+    assert!((page.media_box.width.0 - 595.2).abs() < 0.1);
+    assert!((page.media_box.height.0 - 420.96).abs() < 0.1);
+
+    // fitted into a portrait letter page, the page is rotated to landscape
+    let page = PageFit::Letter.get_page(&im, XObjectId::new());
+    assert!((page.media_box.width.0 - 792.0).abs() < 0.1);
+    assert!((page.media_box.height.0 - 612.0).abs() < 0.1);
+}
@@ -22,15 +22,38 @@ pub use date::*;
 /// Font and codepoint handling
 pub mod font;
 pub use font::*;
+/// Text shaping (ligatures, kerning, mark positioning)
+pub mod shape;
+pub use shape::*;
+/// Bidirectional text reordering (UAX #9)
+pub(crate) mod bidi;
 /// Point / line / polygon handling
 pub mod graphics;
 pub use graphics::*;
 /// Page operations
 pub mod ops;
 pub use ops::*;
+/// Path construction (bezier curves, arcs, circles, rounded rectangles)
+pub mod path;
+pub use path::*;
+/// Boolean operations on polygons (union, intersection, difference, xor)
+pub mod boolean;
+pub use boolean::*;
+/// Conversion of stroked lines into filled outlines
+pub mod stroke;
+pub use stroke::*;
 /// Color handling
 pub mod color;
 pub use color::*;
+/// PDF functions (sampled, exponential, stitching, PostScript calculator)
+pub mod function;
+pub use function::*;
+/// Axial and radial gradients
+pub mod shading;
+pub use shading::*;
+/// Tiling patterns
+pub mod pattern;
+pub use pattern::*;
 /// XObject handling
 pub mod xobject;
 pub use xobject::*;
@@ -40,6 +63,8 @@ pub use svg::*;
 /// Image decoding
 pub mod image;
 pub use image::*;
+/// CCITT Group 4 encoding and fax (Group 3 / 4) decoding of bilevel images
+pub(crate) mod ccitt;
 /// HTML handling
 pub mod html;
 pub use html::*;
@@ -113,6 +138,26 @@ impl IccProfileId {
     }
 }
 
+/// Internal ID for shadings
+#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Ord)]
+pub struct ShadingId(pub String);
+
+impl ShadingId {
+    pub fn new() -> Self {
+        Self(crate::utils::random_character_string_32())
+    }
+}
+
+/// Internal ID for tiling patterns
+#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Ord)]
+pub struct PatternId(pub String);
+
+impl PatternId {
+    pub fn new() -> Self {
+        Self(crate::utils::random_character_string_32())
+    }
+}
+
 /// Parsed PDF document
 #[derive(Debug, PartialEq, Clone)]
 pub struct PdfDocument {
@@ -170,6 +215,34 @@ impl PdfDocument {
         id
     }
 
+    /// Creates a document with one page per image, i.e. the frames of a multi-page
+    /// TIFF or an animation decoded with `RawImage::decode_all_frames`
+    pub fn from_images(images: Vec<RawImage>, fit: PageFit) -> Self {
+        let mut doc = Self::new("");
+        for image in images {
+            let id = doc.add_image(&image);
+            doc.pages.push(fit.get_page(&image, id));
+        }
+        doc
+    }
+
+    /// Returns the operations that place the XObject (image, SVG or form) into the
+    /// `rect` on the page, see `XObjectFit` and `XObjectAlign`
+    pub fn place_xobject(
+        &self,
+        id: &XObjectId,
+        rect: &Rect,
+        fit: XObjectFit,
+        align: XObjectAlign,
+    ) -> Vec<Op> {
+        self.resources
+            .xobjects
+            .map
+            .get(id)
+            .map(|xobject| xobject.get_placement_ops(id, rect, fit, align))
+            .unwrap_or_default()
+    }
+
     /// Adds an external XObject stream (usually SVG or other stream) to the PDF resources
     /// so that it can be later be invoked with `UseXObject { id }`
     pub fn add_xobject(&mut self, parsed_svg: &ExternalXObject) -> XObjectId {
@@ -181,6 +254,48 @@ impl PdfDocument {
         id
     }
 
+    /// Adds a Form XObject (i.e. built with `FormXObject::from_ops` or
+    /// `FormXObject::transparency_group`) to the PDF resources, so that
+    /// it can be painted (repeatedly) with `UseXObject { id }`
+    pub fn add_form(&mut self, form: &FormXObject) -> XObjectId {
+        let id = XObjectId::new();
+        self.resources
+            .xobjects
+            .map
+            .insert(id.clone(), XObject::Form(Box::new(form.clone())));
+        id
+    }
+
+    /// Adds a gradient to the PDF resources, so that it can be painted
+    /// with `Op::PaintShading` or used as a color with `Color::Shading`.
+    ///
+    /// Returns an error if the shading has no color stops or if the stops
+    /// are not all in the same color space.
+    pub fn add_shading(&mut self, shading: &Shading) -> Result<ShadingId, String> {
+        shading.validate()?;
+        let id = ShadingId::new();
+        self.resources
+            .shadings
+            .map
+            .insert(id.clone(), shading.clone());
+        Ok(id)
+    }
+
+    /// Adds a tiling pattern to the PDF resources, so that it can be used
+    /// as a fill or stroke color with `Color::Pattern` (colored patterns) or
+    /// `Color::UncoloredPattern` (uncolored patterns).
+    ///
+    /// Returns an error if the cell of an uncolored pattern sets colors.
+    pub fn add_pattern(&mut self, pattern: &TilingPattern) -> Result<PatternId, String> {
+        pattern.validate()?;
+        let id = PatternId::new();
+        self.resources
+            .patterns
+            .map
+            .insert(id.clone(), pattern.clone());
+        Ok(id)
+    }
+
     /// Adds a new page-level bookmark on page `$page`, returning the bookmarks internal ID
     pub fn add_bookmark(&mut self, name: &str, page: usize) -> PageAnnotId {
         let id = PageAnnotId::new();
@@ -210,8 +325,10 @@ impl PdfDocument {
         self
     }
 
-    /// Serializes the PDF document to bytes
-    pub fn save(&self, opts: &PdfSaveOptions) -> Vec<u8> {
+    /// Serializes the PDF document to bytes, fails if the document contains images
+    /// that can't be written with the conformance of the document (for example JPEG
+    /// 2000 images in a PDF/A-1 document)
+    pub fn save(&self, opts: &PdfSaveOptions) -> Result<Vec<u8>, String> {
         self::serialize::serialize_pdf_into_bytes(self, opts)
     }
 }
@@ -226,6 +343,10 @@ pub struct PdfResources {
     pub extgstates: ExtendedGraphicsStateMap,
     /// Map of optional content groups
     pub layers: PdfLayerMap,
+    /// Axial and radial gradients
+    pub shadings: ShadingMap,
+    /// Tiling patterns
+    pub patterns: PatternMap,
 }
 
 #[derive(Debug, PartialEq, Default, Clone)]
@@ -246,6 +367,16 @@ pub struct XObjectMap {
     pub map: BTreeMap<XObjectId, XObject>,
 }
 
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct ShadingMap {
+    pub map: BTreeMap<ShadingId, Shading>,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct PatternMap {
+    pub map: BTreeMap<PatternId, TilingPattern>,
+}
+
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct PageAnnotMap {
     pub map: BTreeMap<PageAnnotId, PageAnnotation>,
@@ -1,12 +1,13 @@
 use crate::{
     color::Color,
     graphics::{
-        Line, LineCapStyle, LineDashPattern, LineJoinStyle, Point, Polygon, Rect, TextRenderingMode,
+        Line, LineCapStyle, LineDashPattern, LineJoinStyle, PaintMode, Point, Polygon, Rect,
+        TextRenderingMode, WindingOrder, WritingMode,
     },
     matrix::{CurTransMat, TextMatrix},
     units::{Mm, Pt},
-    BuiltinFont, ExtendedGraphicsStateId, FontId, LayerInternalId, LinkAnnotation, XObjectId,
-    XObjectTransform,
+    BuiltinFont, ExtendedGraphicsStateId, FontId, LayerInternalId, LinkAnnotation, RawImage,
+    ShadingId, ShapedText, XObjectId, XObjectTransform,
 };
 use lopdf::Object as LoObject;
 
@@ -137,6 +138,13 @@ pub enum Op {
         size: Pt,
         cpk: Vec<(i64, u16, char)>,
     },
+    /// Writes text that was shaped with `ParsedFont::shape_text` (ligatures, kerning and
+    /// mark positioning), only valid between `StartTextSection` and `EndTextSection`
+    WriteShapedText {
+        font: FontId,
+        size: Pt,
+        text: ShapedText,
+    },
     /// Adds a line break to the text, depends on the line height
     AddLineBreak,
     /// Sets the line height for the text
@@ -161,6 +169,10 @@ pub enum Op {
     SetLineCapStyle { cap: LineCapStyle },
     /// Sets the text rendering mode (fill, stroke, fill-stroke, clip, fill-clip)
     SetTextRenderingMode { mode: TextRenderingMode },
+    /// Sets the writing mode of the current text section, only valid between `StartTextSection`
+    /// and `EndTextSection` (each text section starts horizontal). Vertical text should be
+    /// shaped with `TextShapingOptions::with_vertical` to get vertical glyph alternates.
+    SetWritingMode { mode: WritingMode },
     /// Sets the character spacing (default: 1.0)
     SetCharacterSpacing { multiplier: f32 },
     /// Sets the line offset (default: 1.0)
@@ -181,6 +193,21 @@ pub enum Op {
         id: XObjectId,
         transform: XObjectTransform,
     },
+    /// Draws a small image directly into the content stream (`BI ... ID ... EI`),
+    /// without registering an XObject. Images with an alpha channel or whose encoded
+    /// data exceeds `PdfSaveOptions::max_inline_image_size` are written as image
+    /// XObjects instead.
+    DrawInlineImage {
+        image: RawImage,
+        transform: XObjectTransform,
+    },
+    /// Paints a shading (registered with `PdfDocument::add_shading`) into the current
+    /// clipping path - usually preceded by a clipping path and `SaveGraphicsState`
+    PaintShading { shading: ShadingId },
+    /// Intersects the current clipping path with the given path, using the
+    /// `winding_order` of the polygon (its `mode` is ignored). Usually wrapped in
+    /// `SaveGraphicsState` / `RestoreGraphicsState`, see `Op::clipped`.
+    SetClippingPath { path: Polygon },
     /// Unknown, custom key / value operation
     Unknown { key: String, value: Vec<LoObject> },
 }
@@ -256,6 +283,18 @@ impl PartialEq for Op {
                     cpk: r_cpk,
                 },
             ) => l_font == r_font && l_size == r_size && l_cpk == r_cpk,
+            (
+                Self::WriteShapedText {
+                    font: l_font,
+                    size: l_size,
+                    text: l_text,
+                },
+                Self::WriteShapedText {
+                    font: r_font,
+                    size: r_size,
+                    text: r_text,
+                },
+            ) => l_font == r_font && l_size == r_size && l_text == r_text,
             (Self::SetLineHeight { lh: l_lh }, Self::SetLineHeight { lh: r_lh }) => l_lh == r_lh,
             (
                 Self::SetWordSpacing { percent: l_percent },
@@ -297,6 +336,9 @@ impl PartialEq for Op {
                 Self::SetTextRenderingMode { mode: l_mode },
                 Self::SetTextRenderingMode { mode: r_mode },
             ) => l_mode == r_mode,
+            (Self::SetWritingMode { mode: l_mode }, Self::SetWritingMode { mode: r_mode }) => {
+                l_mode == r_mode
+            }
             (
                 Self::SetCharacterSpacing {
                     multiplier: l_multiplier,
@@ -339,6 +381,23 @@ impl PartialEq for Op {
                     transform: r_transform,
                 },
             ) => l_id == r_id && l_transform == r_transform,
+            (
+                Self::DrawInlineImage {
+                    image: l_image,
+                    transform: l_transform,
+                },
+                Self::DrawInlineImage {
+                    image: r_image,
+                    transform: r_transform,
+                },
+            ) => l_image == r_image && l_transform == r_transform,
+            (
+                Self::PaintShading { shading: l_shading },
+                Self::PaintShading { shading: r_shading },
+            ) => l_shading == r_shading,
+            (Self::SetClippingPath { path: l_path }, Self::SetClippingPath { path: r_path }) => {
+                l_path == r_path
+            }
             (
                 Self::Unknown {
                     key: l_key,
@@ -353,3 +412,52 @@ impl PartialEq for Op {
         }
     }
 }
+
+impl Op {
+    /// Clips the operations to the given path (using its `winding_order`): returns the
+    /// operations wrapped in `SaveGraphicsState` / `RestoreGraphicsState`, so that the
+    /// clipping path is restored afterwards
+    pub fn clipped(path: Polygon, ops: Vec<Op>) -> Vec<Op> {
+        let mut clipped = Vec::with_capacity(ops.len() + 3);
+        clipped.push(Op::SaveGraphicsState);
+        clipped.push(Op::SetClippingPath { path });
+        clipped.extend(ops);
+        clipped.push(Op::RestoreGraphicsState);
+        clipped
+    }
+
+    /// Clips the operations to the given rectangle (i.e. to crop an image or a map frame)
+    pub fn clipped_to_rect(rect: &Rect, ops: Vec<Op>) -> Vec<Op> {
+        let ll = rect.lower_left();
+        let ur = rect.upper_right();
+        let path = Polygon {
+            rings: vec![vec![
+                (ll, false),
+                (Point { x: ur.x, y: ll.y }, false),
+                (ur, false),
+                (Point { x: ll.x, y: ur.y }, false),
+            ]],
+            mode: PaintMode::Clip,
+            winding_order: WindingOrder::NonZero,
+        };
+        Self::clipped(path, ops)
+    }
+
+    /// Clips the operations to the outlines of the glyphs written by `text_ops`
+    /// (i.e. `SetFontSize`, `SetTextCursor` and `WriteText`, without the
+    /// `StartTextSection` / `EndTextSection`). The text itself is not painted.
+    pub fn clipped_to_text(text_ops: Vec<Op>, ops: Vec<Op>) -> Vec<Op> {
+        let mut clipped = Vec::with_capacity(text_ops.len() + ops.len() + 5);
+        clipped.push(Op::SaveGraphicsState);
+        clipped.push(Op::StartTextSection);
+        clipped.push(Op::SetTextRenderingMode {
+            mode: TextRenderingMode::Clip,
+        });
+        clipped.extend(text_ops);
+        // the clipping path is only applied at the end of the text section
+        clipped.push(Op::EndTextSection);
+        clipped.extend(ops);
+        clipped.push(Op::RestoreGraphicsState);
+        clipped
+    }
+}
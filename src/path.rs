@@ -0,0 +1,375 @@
+//! Path construction with lines, bezier curves, arcs, circles, ellipses and rounded rectangles
+
+use crate::{
+    graphics::{Line, PaintMode, Point, Polygon, Rect, WindingOrder},
+    units::Pt,
+};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Builds `Polygon` rings or `Line`s from path segments, setting the bezier
+/// control point flags of the `(Point, bool)` lists automatically.
+///
+/// All coordinates are in pt from the bottom left corner, angles grow counter-clockwise.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PathBuilder {
+    subpaths: Vec<SubPath>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SubPath {
+    points: Vec<(Point, bool)>,
+    closed: bool,
+}
+
+impl PathBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new subpath at `p`
+    pub fn move_to(mut self, p: Point) -> Self {
+        self.subpaths.push(SubPath {
+            points: vec![(p, false)],
+            closed: false,
+        });
+        self
+    }
+
+    /// Straight line from the current point to `p`
+    pub fn line_to(mut self, p: Point) -> Self {
+        self.current_subpath(p).points.push((p, false));
+        self
+    }
+
+    /// Quadratic bezier curve from the current point to `p` (converted to a cubic curve)
+    pub fn quad_to(self, control: Point, p: Point) -> Self {
+        let start = self.current_point().unwrap_or(control);
+        let c1 = lerp(start, control, 2.0 / 3.0);
+        let c2 = lerp(p, control, 2.0 / 3.0);
+        self.cubic_to(c1, c2, p)
+    }
+
+    /// Cubic bezier curve from the current point to `p`
+    pub fn cubic_to(mut self, c1: Point, c2: Point, p: Point) -> Self {
+        let subpath = self.current_subpath(c1);
+        if let Some(last) = subpath.points.last_mut() {
+            // the next point is a bezier control point
+            last.1 = true;
+        }
+        subpath.points.push((c1, true));
+        subpath.points.push((c2, false));
+        subpath.points.push((p, false));
+        self
+    }
+
+    /// Elliptical arc from the current point to `p`, with the same parameters
+    /// as the SVG `A` command: the radii and x-axis rotation (in degrees) of the
+    /// ellipse, whether to take the larger of the two possible arcs and whether to
+    /// draw the arc in the direction of increasing angles (counter-clockwise).
+    ///
+    /// The radii are scaled up if no ellipse with the given radii can connect both points.
+    pub fn arc_to(
+        self,
+        rx: Pt,
+        ry: Pt,
+        x_axis_rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        p: Point,
+    ) -> Self {
+        let start = match self.current_point() {
+            Some(s) => s,
+            None => return self.move_to(p),
+        };
+
+        if start == p {
+            return self;
+        }
+
+        let (mut rx, mut ry) = (rx.0.abs(), ry.0.abs());
+        if rx == 0.0 || ry == 0.0 {
+            return self.line_to(p);
+        }
+
+        // endpoint to center parameterization, see SVG 1.1, appendix F.6.5
+        let phi = x_axis_rotation.to_radians();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let dx = (start.x.0 - p.x.0) / 2.0;
+        let dy = (start.y.0 - p.y.0) / 2.0;
+        let x1 = cos_phi * dx + sin_phi * dy;
+        let y1 = -sin_phi * dx + cos_phi * dy;
+
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let num = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let den = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+        let coef = sign * (num / den).max(0.0).sqrt();
+        let cx1 = coef * rx * y1 / ry;
+        let cy1 = -coef * ry * x1 / rx;
+
+        let center = Point {
+            x: Pt(cos_phi * cx1 - sin_phi * cy1 + (start.x.0 + p.x.0) / 2.0),
+            y: Pt(sin_phi * cx1 + cos_phi * cy1 + (start.y.0 + p.y.0) / 2.0),
+        };
+
+        let start_angle = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+        let end_angle = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+        let mut sweep_angle = end_angle - start_angle;
+        if sweep && sweep_angle < 0.0 {
+            sweep_angle += 2.0 * PI;
+        } else if !sweep && sweep_angle > 0.0 {
+            sweep_angle -= 2.0 * PI;
+        }
+
+        let mut builder = self;
+        let segments = arc_segments(center, rx, ry, phi, start_angle, sweep_angle);
+        let last = segments.len().saturating_sub(1);
+        for (i, [c1, c2, end]) in segments.into_iter().enumerate() {
+            // end exactly at the given point, without rounding errors
+            let end = if i == last { p } else { end };
+            builder = builder.cubic_to(c1, c2, end);
+        }
+        builder
+    }
+
+    /// Closes the current subpath, the next segment starts a new subpath
+    /// at the start point of the closed subpath
+    pub fn close(mut self) -> Self {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+        }
+        self
+    }
+
+    /// Adds a closed rectangle as a new subpath
+    pub fn rect(self, rect: &Rect) -> Self {
+        self.rounded_rect(rect, Pt(0.0))
+    }
+
+    /// Adds a closed rectangle with rounded corners as a new subpath
+    pub fn rounded_rect(self, rect: &Rect, radius: Pt) -> Self {
+        self.rounded_rect_with_radii(rect, [radius; 4])
+    }
+
+    /// Adds a closed rectangle with individual corner radii as a new subpath.
+    /// The radii are given in CSS order: top left, top right, bottom right,
+    /// bottom left. Like in CSS, the radii are scaled down if they overlap.
+    pub fn rounded_rect_with_radii(self, rect: &Rect, radii: [Pt; 4]) -> Self {
+        let ll = rect.lower_left();
+        let ur = rect.upper_right();
+        let (w, h) = ((ur.x.0 - ll.x.0).abs(), (ur.y.0 - ll.y.0).abs());
+        let [tl, tr, br, bl] = radii.map(|r| r.0.max(0.0));
+
+        let scale = [w / (tl + tr), w / (bl + br), h / (tl + bl), h / (tr + br)]
+            .into_iter()
+            .filter(|f| f.is_finite())
+            .fold(1.0_f32, f32::min);
+        let [tl, tr, br, bl] = [tl, tr, br, bl].map(|r| r * scale);
+
+        let pt = |x: f32, y: f32| Point { x: Pt(x), y: Pt(y) };
+        let (left, bottom, right, top) = (ll.x.0, ll.y.0, ur.x.0, ur.y.0);
+
+        self.move_to(pt(left + bl, bottom))
+            .line_to(pt(right - br, bottom))
+            .corner(pt(right - br, bottom + br), br, -FRAC_PI_2)
+            .line_to(pt(right, top - tr))
+            .corner(pt(right - tr, top - tr), tr, 0.0)
+            .line_to(pt(left + tl, top))
+            .corner(pt(left + tl, top - tl), tl, FRAC_PI_2)
+            .line_to(pt(left, bottom + bl))
+            .corner(pt(left + bl, bottom + bl), bl, PI)
+            .close()
+    }
+
+    /// Adds a closed circle as a new subpath
+    pub fn circle(self, center: Point, radius: Pt) -> Self {
+        self.ellipse(center, radius, radius)
+    }
+
+    /// Adds a closed (axis-aligned) ellipse as a new subpath
+    pub fn ellipse(self, center: Point, rx: Pt, ry: Pt) -> Self {
+        let start = Point {
+            x: Pt(center.x.0 + rx.0),
+            y: center.y,
+        };
+
+        let mut builder = self.move_to(start);
+        let segments = arc_segments(center, rx.0, ry.0, 0.0, 0.0, 2.0 * PI);
+        let last = segments.len().saturating_sub(1);
+        for (i, [c1, c2, end]) in segments.into_iter().enumerate() {
+            let end = if i == last { start } else { end };
+            builder = builder.cubic_to(c1, c2, end);
+        }
+        builder.close()
+    }
+
+    /// Returns the current point (the end of the last segment), if any
+    pub fn current_point(&self) -> Option<Point> {
+        let subpath = self.subpaths.last()?;
+        if subpath.closed {
+            subpath.points.first().map(|p| p.0)
+        } else {
+            subpath.points.last().map(|p| p.0)
+        }
+    }
+
+    /// Builds a polygon with one ring per subpath
+    pub fn into_polygon(self, mode: PaintMode, winding_order: WindingOrder) -> Polygon {
+        Polygon {
+            rings: self
+                .subpaths
+                .into_iter()
+                .filter(|s| s.points.len() > 1)
+                .map(|s| s.points)
+                .collect(),
+            mode,
+            winding_order,
+        }
+    }
+
+    /// Builds one (stroked) line per subpath
+    pub fn into_lines(self) -> Vec<Line> {
+        self.subpaths
+            .into_iter()
+            .filter(|s| s.points.len() > 1)
+            .map(|s| Line {
+                points: s.points,
+                is_closed: s.closed,
+            })
+            .collect()
+    }
+
+    /// Quarter circle arc around `center`, starting at `start_angle` (skipped if `radius` is zero)
+    fn corner(self, center: Point, radius: f32, start_angle: f32) -> Self {
+        if radius <= 0.0 {
+            return self;
+        }
+
+        let mut builder = self;
+        for [c1, c2, end] in arc_segments(center, radius, radius, 0.0, start_angle, FRAC_PI_2) {
+            builder = builder.cubic_to(c1, c2, end);
+        }
+        builder
+    }
+
+    /// Returns the subpath to append to, starting a new subpath at `start`
+    /// if there is none or if the last one has been closed
+    fn current_subpath(&mut self, start: Point) -> &mut SubPath {
+        let start = match self.subpaths.last() {
+            None => Some(start),
+            Some(s) if s.closed => Some(s.points.first().map(|p| p.0).unwrap_or(start)),
+            Some(_) => None,
+        };
+
+        if let Some(start) = start {
+            self.subpaths.push(SubPath {
+                points: vec![(start, false)],
+                closed: false,
+            });
+        }
+
+        // a subpath was pushed above if there was none
+        self.subpaths.last_mut().unwrap()
+    }
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    Point {
+        x: Pt(a.x.0 + (b.x.0 - a.x.0) * t),
+        y: Pt(a.y.0 + (b.y.0 - a.y.0) * t),
+    }
+}
+
+/// Approximates an elliptical arc with cubic bezier curves (at most 90 degrees
+/// per curve), returns the two control points and the end point of each curve
+fn arc_segments(
+    center: Point,
+    rx: f32,
+    ry: f32,
+    phi: f32,
+    start_angle: f32,
+    sweep_angle: f32,
+) -> Vec<[Point; 3]> {
+    let count = (sweep_angle.abs() / FRAC_PI_2 - 0.001).ceil().max(1.0) as usize;
+    let delta = sweep_angle / count as f32;
+    let k = 4.0 / 3.0 * (delta / 4.0).tan();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+
+    // maps a point on the unit circle to the ellipse
+    let map = |x: f32, y: f32| Point {
+        x: Pt(center.x.0 + rx * cos_phi * x - ry * sin_phi * y),
+        y: Pt(center.y.0 + rx * sin_phi * x + ry * cos_phi * y),
+    };
+
+    (0..count)
+        .map(|i| {
+            let a1 = start_angle + delta * i as f32;
+            let a2 = a1 + delta;
+            let (sin1, cos1) = a1.sin_cos();
+            let (sin2, cos2) = a2.sin_cos();
+            [
+                map(cos1 - k * sin1, sin1 + k * cos1),
+                map(cos2 + k * sin2, sin2 - k * cos2),
+                map(cos2, sin2),
+            ]
+        })
+        .collect()
+}
+
+#[test]
+fn test_circle_bezier_flags() {
+    let center = Point {
+        x: Pt(50.0),
+        y: Pt(50.0),
+    };
+    let poly = PathBuilder::new()
+        .circle(center, Pt(10.0))
+        .into_polygon(PaintMode::Fill, WindingOrder::NonZero);
+
+    assert_eq!(poly.rings.len(), 1);
+    let ring = &poly.rings[0];
+    // start point + 4 curves with 3 points each
+    assert_eq!(ring.len(), 13);
+    assert_eq!(ring[0].0, ring[12].0);
+    for curve in ring[1..].chunks(3) {
+        assert!(curve[0].1);
+        assert!(!curve[1].1);
+    }
+    // top of the circle, the control points are on the tangent
+    assert_eq!(
+        ring[3].0,
+        Point {
+            x: Pt(50.0),
+            y: Pt(60.0)
+        }
+    );
+    assert_eq!(ring[2].0.y, Pt(60.0));
+}
+
+#[test]
+fn test_arc_to_semicircle() {
+    let pt = |x: f32, y: f32| Point { x: Pt(x), y: Pt(y) };
+
+    // counter-clockwise half circle from (0, 0) to (20, 0) goes through (10, -10)
+    let lines = PathBuilder::new()
+        .move_to(pt(0.0, 0.0))
+        .arc_to(Pt(10.0), Pt(10.0), 0.0, false, true, pt(20.0, 0.0))
+        .into_lines();
+
+    let points = &lines[0].points;
+    assert_eq!(points.len(), 7);
+    assert_eq!(points[3].0, pt(10.0, -10.0));
+    assert_eq!(points[6].0, pt(20.0, 0.0));
+
+    // radii too small: scaled up to the half distance
+    let lines = PathBuilder::new()
+        .move_to(pt(0.0, 0.0))
+        .arc_to(Pt(1.0), Pt(1.0), 0.0, false, false, pt(20.0, 0.0))
+        .into_lines();
+    assert_eq!(lines[0].points[3].0, pt(10.0, 10.0));
+}
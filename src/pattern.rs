@@ -0,0 +1,125 @@
+//! Tiling patterns (hatching, dots, etc.)
+
+use crate::{graphics::Rect, matrix::CurTransMat, ops::Op, units::Pt};
+
+/// Pattern built by repeating a "pattern cell" at fixed horizontal and vertical intervals.
+/// Register it with `PdfDocument::add_pattern` and use it as a fill or stroke color
+/// with `Color::Pattern` (colored patterns) or `Color::UncoloredPattern` (uncolored patterns).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilingPattern {
+    /// Operations that draw a single pattern cell (in pattern space)
+    pub ops: Vec<Op>,
+    /// Bounding box of the pattern cell, used to clip the cell
+    pub bbox: Rect,
+    /// Horizontal spacing between pattern cells
+    pub x_step: Pt,
+    /// Vertical spacing between pattern cells
+    pub y_step: Pt,
+    /// Maps the pattern space to the default coordinate space of the page
+    pub matrix: CurTransMat,
+    /// Whether the cell specifies its own colors or is painted in the color given on use
+    pub paint_type: PatternPaintType,
+    /// How the cell spacing may be adjusted to the device pixel grid
+    pub tiling_type: TilingType,
+}
+
+impl TilingPattern {
+    /// Creates a new colored tiling pattern, where the cell size equals the step size
+    pub fn new(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            x_step: bbox.width,
+            y_step: bbox.height,
+            ops,
+            bbox,
+            matrix: CurTransMat::Identity,
+            paint_type: PatternPaintType::Colored,
+            tiling_type: TilingType::ConstantSpacing,
+        }
+    }
+
+    /// Sets the spacing between the pattern cells
+    #[inline]
+    pub fn with_step(mut self, x_step: Pt, y_step: Pt) -> Self {
+        self.x_step = x_step;
+        self.y_step = y_step;
+        self
+    }
+
+    /// Sets the pattern matrix (i.e. to rotate hatch lines)
+    #[inline]
+    pub fn with_matrix(mut self, matrix: CurTransMat) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Sets whether the pattern is colored or uncolored
+    #[inline]
+    pub fn with_paint_type(mut self, paint_type: PatternPaintType) -> Self {
+        self.paint_type = paint_type;
+        self
+    }
+
+    /// Sets the tiling type of the pattern
+    #[inline]
+    pub fn with_tiling_type(mut self, tiling_type: TilingType) -> Self {
+        self.tiling_type = tiling_type;
+        self
+    }
+    /// Checks that the cell of an uncolored pattern doesn't set any colors (or paint
+    /// shadings), the color is given when the pattern is used
+    pub fn validate(&self) -> Result<(), String> {
+        if self.paint_type == PatternPaintType::Colored {
+            return Ok(());
+        }
+        let color_op = self.ops.iter().find(|op| {
+            matches!(
+                op,
+                Op::SetFillColor { .. } | Op::SetOutlineColor { .. } | Op::PaintShading { .. }
+            )
+        });
+        match color_op {
+            Some(op) => Err(format!("uncolored pattern cell can't set colors: {op:?}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Paint type of a tiling pattern
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PatternPaintType {
+    /// The pattern cell sets its own colors
+    Colored,
+    /// The pattern cell only describes a shape (color operators are ignored),
+    /// the color is given when the pattern is used (`Color::UncoloredPattern`)
+    Uncolored,
+}
+
+impl PatternPaintType {
+    pub fn get_id(&self) -> i64 {
+        match self {
+            PatternPaintType::Colored => 1,
+            PatternPaintType::Uncolored => 2,
+        }
+    }
+}
+
+/// Controls adjustments to the spacing of tiles relative to the device pixel grid
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TilingType {
+    /// Pattern cells are spaced consistently, the cell may be distorted slightly
+    ConstantSpacing,
+    /// The pattern cell is not distorted, the spacing may vary slightly
+    NoDistortion,
+    /// Pattern cells are spaced consistently, faster but with more distortion
+    ConstantSpacingFasterTiling,
+}
+
+impl TilingType {
+    pub fn get_id(&self) -> i64 {
+        match self {
+            TilingType::ConstantSpacing => 1,
+            TilingType::NoDistortion => 2,
+            TilingType::ConstantSpacingFasterTiling => 3,
+        }
+    }
+}
@@ -9,19 +9,34 @@ use crate::Color;
 use crate::ColorArray;
 use crate::Destination;
 use crate::FontId;
+use crate::FormXObject;
+use crate::GroupXObject;
 use crate::IccProfileType;
+use crate::ImageResampling;
 use crate::Line;
 use crate::LinkAnnotation;
 use crate::Op;
 use crate::PaintMode;
 use crate::ParsedFont;
+use crate::PatternId;
+use crate::PatternPaintType;
 use crate::PdfDocument;
 use crate::PdfDocumentInfo;
-use crate::PdfPage;
 use crate::PdfResources;
+use crate::Point;
 use crate::Polygon;
+use crate::Pt;
+use crate::Px;
+use crate::RawImage;
+use crate::ShadingId;
+use crate::ShapedGlyph;
+use crate::ShapedText;
+use crate::WindingOrder;
+use crate::WritingMode;
 use crate::XObject;
 use crate::XObjectId;
+use crate::XObjectTransform;
+use crate::INLINE_IMAGE_MAX_SIZE;
 use lopdf::content::Operation as LoOp;
 use lopdf::Dictionary as LoDictionary;
 use lopdf::Object::{
@@ -33,9 +48,36 @@ use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(default)]
 pub struct PdfSaveOptions {
     pub optimize: bool,
     pub subset_fonts: bool,
+    /// Maximum size (in bytes) of the encoded data of an `Op::DrawInlineImage`,
+    /// larger images are written as image XObjects (default: 4 KB, as recommended
+    /// by the PDF specification)
+    pub max_inline_image_size: usize,
+    /// Write images with at most 256 distinct colors (charts, screenshots, logos)
+    /// with an `/Indexed` color space and 1, 2, 4 or 8-bit indices. Needs to count the
+    /// colors of every image when saving. Images with a `RawImage::palette` (decoded
+    /// from palette PNGs) are always written indexed (default: false)
+    pub indexed_images: bool,
+    /// Compress 1-bit images (`RawImageFormat::R1`, stencil masks) with CCITT Group 4
+    /// instead of Flate (default: true). Other images can be converted to 1-bit
+    /// images with `RawImage::to_bilevel`.
+    pub ccitt_bilevel_images: bool,
+    /// Compress images (Flate, CCITT). Images that are already compressed (JPEG,
+    /// JPEG 2000) are embedded unchanged either way. Doesn't affect `max_image_dpi`
+    /// and `jpeg_quality` (default: true)
+    pub compress_images: bool,
+    /// Maximum effective resolution of images on the page (worked out from the
+    /// `XObjectTransform` of every `Op::UseXObject`). Images with a higher resolution
+    /// are resampled when saving (default: None = keep the original resolution)
+    pub max_image_dpi: Option<u32>,
+    /// Filter used to resample images that exceed the `max_image_dpi`
+    pub image_resampling: ImageResampling,
+    /// If set, 8-bit RGB and grayscale images without transparency are re-compressed
+    /// as JPEG with the given quality (1 - 100). Needs the `jpeg` feature (default: None)
+    pub jpeg_quality: Option<u8>,
 }
 
 impl Default for PdfSaveOptions {
@@ -43,12 +85,36 @@ impl Default for PdfSaveOptions {
         Self {
             optimize: true,
             subset_fonts: true,
+            max_inline_image_size: INLINE_IMAGE_MAX_SIZE,
+            indexed_images: false,
+            ccitt_bilevel_images: true,
+            compress_images: true,
+            max_image_dpi: None,
+            image_resampling: ImageResampling::default(),
+            jpeg_quality: None,
         }
     }
 }
 
-pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec<u8> {
-    let mut doc = lopdf::Document::with_version("1.3");
+pub fn serialize_pdf_into_bytes(
+    pdf: &PdfDocument,
+    opts: &PdfSaveOptions,
+) -> Result<Vec<u8>, String> {
+    validate_images(pdf)?;
+    validate_patterns(pdf)?;
+
+    let (promoted, inline_images) = promote_inline_images(pdf, opts.max_inline_image_size);
+    let pdf = promoted.as_ref().unwrap_or(pdf);
+    let optimized = optimize_images(pdf, opts);
+    let pdf = optimized.as_ref().unwrap_or(pdf);
+
+    // JPEG 2000 images (/JPXDecode) need PDF 1.5
+    let conformance = &pdf.metadata.info.conformance;
+    let uses_jpx = pdf.resources.xobjects.map.values().any(|x| match x {
+        XObject::Image(im) => im.uses_jpx(conformance),
+        _ => false,
+    });
+    let mut doc = lopdf::Document::with_version(if uses_jpx { "1.5" } else { "1.3" });
     doc.reference_table.cross_reference_type = lopdf::xref::XrefType::CrossReferenceTable;
     let pages_id = doc.new_object_id();
     let mut catalog = LoDictionary::from_iter(vec![
@@ -160,35 +226,169 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
 
     // Build fonts dictionary
     let mut global_font_dict = LoDictionary::new();
-    let prepared_fonts = prepare_fonts(&pdf.resources, &pdf.pages);
+    let prepared_fonts = prepare_fonts(&pdf.resources, &get_all_ops(pdf).collect::<Vec<_>>());
     for (font_id, prepared) in prepared_fonts.iter() {
-        let font_dict = add_font_to_pdf(&mut doc, font_id, prepared);
-        let font_dict_id = doc.add_object(font_dict);
-        global_font_dict.set(font_id.0.clone(), Reference(font_dict_id));
+        let font_dicts = add_font_to_pdf(&mut doc, font_id, prepared);
+        for (vertical, font_dict) in font_dicts {
+            let font_dict_id = doc.add_object(font_dict);
+            global_font_dict.set(
+                get_font_resource_name(font_id, vertical),
+                Reference(font_dict_id),
+            );
+        }
     }
 
-    for internal_font in get_used_internal_fonts(&pdf.pages) {
+    for internal_font in get_used_internal_fonts(get_all_ops(pdf)) {
         let font_dict = builtin_font_to_dict(&internal_font);
         let font_dict_id = doc.add_object(font_dict);
         global_font_dict.set(internal_font.get_pdf_id(), Reference(font_dict_id));
     }
-    let global_font_dict_id = doc.add_object(global_font_dict);
+    let global_font_dict_id = doc.add_object(global_font_dict.clone());
 
-    // Build XObject dictionary
-    let mut global_xobject_dict = LoDictionary::new();
-    for (k, v) in pdf.resources.xobjects.map.iter() {
-        global_xobject_dict.set(
-            k.0.clone(),
-            crate::xobject::add_xobject_to_document(v, &mut doc),
-        );
+    // XObject and ExtGState dictionaries are built after the shared resources,
+    // since forms built from operations and soft masks need them
+    let global_xobject_dict_id = doc.new_object_id();
+    let global_extgstate_dict_id = doc.new_object_id();
+
+    // Build ColorSpace dictionary (CIE-based colors, uncolored patterns)
+    let global_colorspace_dict = LoDictionary::from_iter(get_used_color_spaces(get_all_ops(pdf)));
+    let global_colorspace_dict_id = if global_colorspace_dict.is_empty() {
+        None
+    } else {
+        Some(doc.add_object(global_colorspace_dict.clone()))
+    };
+
+    // Build Shading and Pattern dictionaries: every shading can be painted with "sh"
+    // and used as a fill / stroke color via a shading pattern of the same name
+    let mut global_shading_dict = LoDictionary::new();
+    let mut global_pattern_dict = LoDictionary::new();
+    let global_shading_dict_id = doc.new_object_id();
+    let global_pattern_dict_id = doc.new_object_id();
+    for (k, v) in pdf.resources.shadings.map.iter() {
+        let shading_dict = crate::shading::shading_to_dict(v, &mut doc);
+        let shading_id = doc.add_object(shading_dict);
+        global_shading_dict.set(k.0.clone(), Reference(shading_id));
+        let pattern_id = doc.add_object(LoDictionary::from_iter(vec![
+            ("Type", Name("Pattern".into())),
+            ("PatternType", Integer(2)),
+            ("Shading", Reference(shading_id)),
+        ]));
+        global_pattern_dict.set(k.0.clone(), Reference(pattern_id));
     }
-    let global_xobject_dict_id = doc.add_object(global_xobject_dict);
+
+    // Resources shared by all pages and tiling patterns
+    let mut global_resources = LoDictionary::from_iter(vec![
+        ("Font", Reference(global_font_dict_id)),
+        ("XObject", Reference(global_xobject_dict_id)),
+        ("ExtGState", Reference(global_extgstate_dict_id)),
+        ("Shading", Reference(global_shading_dict_id)),
+        ("Pattern", Reference(global_pattern_dict_id)),
+    ]);
+    if let Some(colorspace_dict_id) = global_colorspace_dict_id {
+        global_resources.set("ColorSpace", Reference(colorspace_dict_id));
+    }
+
+    for (k, v) in pdf.resources.patterns.map.iter() {
+        let pattern_stream = LoStream::new(
+            LoDictionary::from_iter(vec![
+                ("Type", Name("Pattern".into())),
+                ("PatternType", Integer(1)),
+                ("PaintType", Integer(v.paint_type.get_id())),
+                ("TilingType", Integer(v.tiling_type.get_id())),
+                ("BBox", Array(v.bbox.to_bbox_array())),
+                ("XStep", Real(v.x_step.0)),
+                ("YStep", Real(v.y_step.0)),
+                ("Resources", Dictionary(global_resources.clone())),
+                (
+                    "Matrix",
+                    Array(v.matrix.as_array().into_iter().map(Real).collect()),
+                ),
+            ]),
+            translate_operations(
+                &v.ops,
+                &prepared_fonts,
+                &pdf.resources.xobjects.map,
+                &inline_images,
+            ),
+        )
+        .with_compression(false);
+        global_pattern_dict.set(k.0.clone(), Reference(doc.add_object(pattern_stream)));
+    }
+
+    doc.set_object(global_shading_dict_id, global_shading_dict.clone());
+    doc.set_object(global_pattern_dict_id, global_pattern_dict.clone());
 
     let mut global_extgstate_dict = LoDictionary::new();
     for (k, v) in pdf.resources.extgstates.map.iter() {
-        global_extgstate_dict.set(k.0.clone(), crate::graphics::extgstate_to_dict(v));
+        let mut gs_dict = crate::graphics::extgstate_to_dict(v, &mut doc)
+            .map_err(|e| format!("graphics state {}: {e}", k.0))?;
+        if let Some(sm) = v.soft_mask.as_ref() {
+            if v.changed_fields.contains(crate::graphics::SOFT_MASK) {
+                let group = FormXObject::transparency_group(
+                    sm.ops.clone(),
+                    sm.bbox,
+                    GroupXObject::transparency().with_color_space(sm.get_group_color_space()),
+                );
+                let group_stream = ops_form_to_stream(
+                    &group,
+                    &global_resources,
+                    &prepared_fonts,
+                    &pdf.resources.xobjects.map,
+                    &inline_images,
+                    &mut doc,
+                );
+                let group_id = doc.add_object(group_stream);
+                let sm_dict = soft_mask_to_dict(sm, group_id, &mut doc);
+                gs_dict.set("SM", Dictionary(sm_dict));
+            }
+        }
+        global_extgstate_dict.set(k.0.clone(), gs_dict);
+    }
+    doc.set_object(global_extgstate_dict_id, global_extgstate_dict.clone());
+
+    // Build XObject dictionary. Forms built from operations get their own resources
+    // (which may reference any other XObject), so their object IDs are reserved first
+    let mut global_xobject_dict = LoDictionary::new();
+    let mut ops_forms = Vec::new();
+    for (k, v) in pdf.resources.xobjects.map.iter() {
+        let xobject_id = match v {
+            XObject::Form(f) if !f.ops.is_empty() => {
+                let id = doc.new_object_id();
+                ops_forms.push((id, f));
+                id
+            }
+            _ => crate::xobject::add_xobject_to_document(
+                v,
+                &pdf.metadata.info.conformance,
+                opts,
+                &mut doc,
+            ),
+        };
+        global_xobject_dict.set(k.0.clone(), Reference(xobject_id));
+    }
+    for (id, f) in ops_forms {
+        let form_resources = get_form_resources(
+            &f.ops,
+            &[
+                ("Font", &global_font_dict),
+                ("XObject", &global_xobject_dict),
+                ("ExtGState", &global_extgstate_dict),
+                ("Shading", &global_shading_dict),
+                ("Pattern", &global_pattern_dict),
+                ("ColorSpace", &global_colorspace_dict),
+            ],
+        );
+        let stream = ops_form_to_stream(
+            f,
+            &form_resources,
+            &prepared_fonts,
+            &pdf.resources.xobjects.map,
+            &inline_images,
+            &mut doc,
+        );
+        doc.set_object(id, stream);
     }
-    let global_extgstate_dict_id = doc.add_object(global_extgstate_dict);
+    doc.set_object(global_xobject_dict_id, global_xobject_dict);
 
     let page_ids_reserved = pdf
         .pages
@@ -275,13 +475,17 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
                 ),
             );
 
-            page_resources.set("Font", Reference(global_font_dict_id));
-            page_resources.set("XObject", Reference(global_xobject_dict_id));
-            page_resources.set("ExtGState", Reference(global_extgstate_dict_id));
+            for (k, v) in global_resources.iter() {
+                page_resources.set(k.clone(), v.clone());
+            }
             // page_resources.et("Properties", Dictionary(ocg_dict));
 
-            let layer_stream =
-                translate_operations(&page.ops, &prepared_fonts, &pdf.resources.xobjects.map); // Vec<u8>
+            let layer_stream = translate_operations(
+                &page.ops,
+                &prepared_fonts,
+                &pdf.resources.xobjects.map,
+                &inline_images,
+            );
             let merged_layer_stream =
                 LoStream::new(LoDictionary::new(), layer_stream).with_compression(false);
 
@@ -394,19 +598,302 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
     let _ = doc.save_to(&mut writer);
     std::mem::drop(writer);
 
-    bytes
+    Ok(bytes)
 }
 
-fn get_used_internal_fonts(pages: &[PdfPage]) -> BTreeSet<BuiltinFont> {
-    pages
+/// Returns the operations of all content streams: pages, tiling pattern cells, soft masks and forms
+fn get_all_ops(pdf: &PdfDocument) -> impl Iterator<Item = &Op> {
+    pdf.pages
         .iter()
-        .flat_map(|p| {
-            p.ops.iter().filter_map(|op| match op {
-                Op::WriteTextBuiltinFont { font, .. } => Some(*font),
-                _ => None,
-            })
-        })
-        .collect()
+        .flat_map(|p| p.ops.iter())
+        .chain(
+            pdf.resources
+                .patterns
+                .map
+                .values()
+                .flat_map(|p| p.ops.iter()),
+        )
+        .chain(
+            pdf.resources
+                .extgstates
+                .map
+                .values()
+                .filter_map(|gs| gs.soft_mask.as_ref())
+                .flat_map(|sm| sm.ops.iter()),
+        )
+        .chain(
+            pdf.resources
+                .xobjects
+                .map
+                .values()
+                .filter_map(|x| match x {
+                    XObject::Form(f) => Some(f.ops.iter()),
+                    _ => None,
+                })
+                .flatten(),
+        )
+}
+
+/// Checks that all images (XObjects and inline images) can be written with the
+/// conformance of the document
+fn validate_images(pdf: &PdfDocument) -> Result<(), String> {
+    let conformance = &pdf.metadata.info.conformance;
+    for (id, xobject) in pdf.resources.xobjects.map.iter() {
+        if let XObject::Image(im) = xobject {
+            im.check_conformance(conformance)
+                .map_err(|e| format!("image {}: {e}", id.0))?;
+        }
+    }
+    for op in get_all_ops(pdf) {
+        if let Op::DrawInlineImage { image, .. } = op {
+            image
+                .check_conformance(conformance)
+                .map_err(|e| format!("inline image: {e}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the tiling patterns (see `TilingPattern::validate`) and that colored
+/// patterns are used with `Color::Pattern`, uncolored ones with `Color::UncoloredPattern`
+fn validate_patterns(pdf: &PdfDocument) -> Result<(), String> {
+    let patterns = &pdf.resources.patterns.map;
+    for (id, pattern) in patterns.iter() {
+        pattern
+            .validate()
+            .map_err(|e| format!("pattern {}: {e}", id.0))?;
+    }
+    for op in get_all_ops(pdf) {
+        let (Op::SetFillColor { col } | Op::SetOutlineColor { col }) = op else {
+            continue;
+        };
+        let (id, used_as) = match col {
+            Color::Pattern(id) => (id, PatternPaintType::Colored),
+            Color::UncoloredPattern { pattern, .. } => (pattern, PatternPaintType::Uncolored),
+            _ => continue,
+        };
+        match patterns.get(id) {
+            Some(pattern) if pattern.paint_type != used_as => {
+                return Err(match used_as {
+                    PatternPaintType::Colored => format!(
+                        "uncolored pattern {} can't be used with Color::Pattern",
+                        id.0
+                    ),
+                    PatternPaintType::Uncolored => format!(
+                        "colored pattern {} can't be used with Color::UncoloredPattern",
+                        id.0
+                    ),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_validate_patterns() {
+    use crate::graphics::Rect;
+    use crate::{Mm, PdfPage, TilingPattern};
+
+    let bbox = Rect {
+        x: Pt(0.0),
+        y: Pt(0.0),
+        width: Pt(10.0),
+        height: Pt(10.0),
+    };
+    let red = Color::Rgb(crate::color::Rgb::new(1.0, 0.0, 0.0, None));
+    let set_red = Op::SetFillColor { col: red.clone() };
+
+    // uncolored pattern cells only describe a shape
+    let mut pdf = PdfDocument::new("patterns");
+    let uncolored = TilingPattern::new(vec![set_red.clone()], bbox.clone())
+        .with_paint_type(PatternPaintType::Uncolored);
+    assert!(pdf.add_pattern(&uncolored).is_err());
+    let uncolored =
+        TilingPattern::new(Vec::new(), bbox.clone()).with_paint_type(PatternPaintType::Uncolored);
+    let uncolored = pdf.add_pattern(&uncolored).unwrap();
+    let colored = pdf
+        .add_pattern(&TilingPattern::new(vec![set_red], bbox))
+        .unwrap();
+
+    let page = |col: Color| PdfPage::new(Mm(10.0), Mm(10.0), vec![Op::SetFillColor { col }]);
+    let save = |pdf: &PdfDocument, col: Color| {
+        let mut pdf = pdf.clone();
+        pdf.with_pages(vec![page(col)]);
+        pdf.save(&PdfSaveOptions::default())
+    };
+    assert!(save(&pdf, Color::Pattern(colored.clone())).is_ok());
+    let red_uncolored = Color::uncolored_pattern(uncolored.clone(), red.clone()).unwrap();
+    assert!(save(&pdf, red_uncolored).is_ok());
+
+    let err = save(&pdf, Color::Pattern(uncolored.clone())).unwrap_err();
+    assert!(err.contains(&uncolored.0), "{err}");
+    let red_colored = Color::uncolored_pattern(colored.clone(), red).unwrap();
+    let err = save(&pdf, red_colored).unwrap_err();
+    assert!(err.contains(&colored.0), "{err}");
+}
+
+/// Builds the stream of a Form XObject whose content is given as operations
+fn ops_form_to_stream(
+    form: &FormXObject,
+    resources: &LoDictionary,
+    fonts: &BTreeMap<FontId, PreparedFont>,
+    xobjects: &BTreeMap<XObjectId, XObject>,
+    inline_images: &[InlineImage],
+    doc: &mut lopdf::Document,
+) -> LoStream {
+    let mut stream = crate::xobject::form_xobject_to_stream(form, doc);
+    stream.dict.set("Resources", Dictionary(resources.clone()));
+    stream.set_plain_content(translate_operations(
+        &form.ops,
+        fonts,
+        xobjects,
+        inline_images,
+    ));
+    let _ = stream.compress();
+    stream
+}
+
+/// Builds the resources dictionary of a form built from operations: only the entries
+/// of the global resource dictionaries that are referenced by the operations
+fn get_form_resources(ops: &[Op], global_dicts: &[(&str, &LoDictionary)]) -> LoDictionary {
+    let mut used = BTreeMap::<&str, BTreeSet<Vec<u8>>>::new();
+    let mut add = |category: &'static str, name: &str| {
+        used.entry(category)
+            .or_default()
+            .insert(name.as_bytes().to_vec());
+    };
+
+    for op in ops {
+        match op {
+            Op::WriteText { font, .. }
+            | Op::WriteCodepoints { font, .. }
+            | Op::WriteCodepointsWithKerning { font, .. }
+            | Op::WriteShapedText { font, .. }
+            | Op::SetFontSize { font, .. } => {
+                add("Font", &font.0);
+                add("Font", &get_font_resource_name(font, true));
+            }
+            Op::WriteTextBuiltinFont { font, .. } => add("Font", font.get_pdf_id()),
+            Op::UseXObject { id, .. } => add("XObject", &id.0),
+            Op::LoadGraphicsState { gs } => add("ExtGState", &gs.0),
+            Op::PaintShading { shading } => add("Shading", &shading.0),
+            Op::SetFillColor { col } | Op::SetOutlineColor { col } => {
+                if let Some((name, _)) = col.get_color_space_resource() {
+                    add("ColorSpace", &name);
+                }
+                match col {
+                    Color::Shading(id) => add("Pattern", &id.0),
+                    Color::Pattern(id) | Color::UncoloredPattern { pattern: id, .. } => {
+                        add("Pattern", &id.0)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut resources = LoDictionary::new();
+    for (category, dict) in global_dicts {
+        let Some(names) = used.get(category) else {
+            continue;
+        };
+        let subset = LoDictionary::from_iter(
+            dict.iter()
+                .filter(|(k, _)| names.contains(*k))
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        if !subset.is_empty() {
+            resources.set(*category, Dictionary(subset));
+        }
+    }
+
+    resources
+}
+
+fn get_used_internal_fonts<'a>(ops: impl Iterator<Item = &'a Op>) -> BTreeSet<BuiltinFont> {
+    ops.filter_map(|op| match op {
+        Op::WriteTextBuiltinFont { font, .. } => Some(*font),
+        _ => None,
+    })
+    .collect()
+}
+
+fn get_used_color_spaces<'a>(ops: impl Iterator<Item = &'a Op>) -> BTreeMap<String, lopdf::Object> {
+    ops.filter_map(|op| match op {
+        Op::SetFillColor { col } | Op::SetOutlineColor { col } => col.get_color_space_resource(),
+        _ => None,
+    })
+    .collect()
+}
+
+/// Builds the soft mask dictionary of a graphics state, `group` is the
+/// transparency group that defines the mask. The soft mask has to be valid
+/// (see `SoftMask::validate`), so that the backdrop color matches the color
+/// space of the group.
+fn soft_mask_to_dict(
+    sm: &crate::graphics::SoftMask,
+    group: lopdf::ObjectId,
+    doc: &mut lopdf::Document,
+) -> LoDictionary {
+    let mut sm_dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Mask".into())),
+        ("S", Name(sm.function.get_id().into())),
+        ("G", Reference(group)),
+    ]);
+    if let Some(bc) = sm.backdrop_color.as_ref() {
+        sm_dict.set("BC", Array(bc.into_vec().into_iter().map(Real).collect()));
+    }
+    if let Some(tr) = sm.transfer_function.as_ref() {
+        sm_dict.set("TR", tr.to_object(doc));
+    }
+    sm_dict
+}
+
+#[test]
+fn test_soft_mask_transfer_function() {
+    use crate::graphics::{Rect, SoftMask, TransferFunction};
+    use crate::PdfFunction;
+
+    let mut doc = lopdf::Document::with_version("1.4");
+    let group = doc.new_object_id();
+    let bbox = Rect::from_wh(Pt(100.0), Pt(100.0));
+
+    let sm = SoftMask::luminosity(Vec::new(), bbox.clone());
+    let dict = soft_mask_to_dict(&sm, group, &mut doc);
+    assert_eq!(dict.get(b"S").unwrap(), &Name("Luminosity".into()));
+    assert_eq!(dict.get(b"G").unwrap(), &Reference(group));
+    assert!(dict.get(b"TR").is_err());
+
+    let sm = SoftMask::alpha(Vec::new(), bbox.clone())
+        .with_transfer_function(TransferFunction::Identity);
+    let dict = soft_mask_to_dict(&sm, group, &mut doc);
+    assert_eq!(dict.get(b"S").unwrap(), &Name("Alpha".into()));
+    assert_eq!(dict.get(b"TR").unwrap(), &Name("Identity".into()));
+
+    // the backdrop color is given in the (device) color space of the group
+    let grey = Color::Greyscale(crate::color::Greyscale::new(0.5, None));
+    let sm = SoftMask::luminosity(Vec::new(), bbox.clone()).with_backdrop_color(grey);
+    assert!(sm.validate().is_ok());
+    assert_eq!(sm.get_group_color_space(), crate::ColorSpace::Greyscale);
+    let dict = soft_mask_to_dict(&sm, group, &mut doc);
+    assert_eq!(dict.get(b"BC").unwrap(), &Array(vec![Real(0.5)]));
+
+    let sm = SoftMask::luminosity(Vec::new(), bbox.clone())
+        .with_backdrop_color(Color::lab(50.0, 0.0, 0.0));
+    assert!(sm.validate().is_err());
+
+    let per_component = TransferFunction::PerComponent(vec![PdfFunction::identity(); 4]);
+    let sm = SoftMask::luminosity(Vec::new(), bbox).with_transfer_function(per_component);
+    assert!(sm.validate().is_err());
+
+    // invalid soft masks are reported when the graphics state is serialized
+    let gs = crate::ExtendedGraphicsStateBuilder::new()
+        .with_soft_mask(Some(sm))
+        .build();
+    assert!(crate::graphics::extgstate_to_dict(&gs, &mut doc).is_err());
 }
 
 fn builtin_font_to_dict(font: &BuiltinFont) -> LoDictionary {
@@ -422,8 +909,14 @@ fn translate_operations(
     ops: &[Op],
     fonts: &BTreeMap<FontId, PreparedFont>,
     xobjects: &BTreeMap<XObjectId, XObject>,
+    inline_images: &[InlineImage],
 ) -> Vec<u8> {
+    let mut bytes = Vec::new();
     let mut content = Vec::new();
+    // writing mode of the current text section
+    let mut vertical = false;
+    // font of the current text section, selected again when the writing mode changes
+    let mut current_font = None;
 
     for op in ops {
         match op {
@@ -452,15 +945,32 @@ fn translate_operations(
             }
             Op::StartTextSection => {
                 content.push(LoOp::new("BT", vec![]));
+                vertical = false;
+                current_font = None;
             }
             Op::EndTextSection => {
                 content.push(LoOp::new("ET", vec![]));
+                vertical = false;
+                current_font = None;
+            }
+            Op::SetWritingMode { mode } => {
+                vertical = *mode == WritingMode::Vertical;
+                if let Some((font, size)) = current_font {
+                    content.push(LoOp::new(
+                        "Tf",
+                        vec![get_font_resource_name(font, vertical).into(), Real(size)],
+                    ));
+                }
             }
             Op::WriteText { text, font, size } => {
+                current_font = Some((font, size.0));
                 if let Some(prepared_font) = fonts.get(font) {
                     content.push(LoOp::new(
                         "Tf",
-                        vec![font.0.clone().into(), (size.0).into()],
+                        vec![
+                            get_font_resource_name(font, vertical).into(),
+                            (size.0).into(),
+                        ],
                     ));
 
                     let glyph_ids = text
@@ -488,10 +998,14 @@ fn translate_operations(
                 content.push(LoOp::new("Tj", vec![LoString(bytes, Hexadecimal)]));
             }
             Op::WriteCodepoints { font, cp, size } => {
+                current_font = Some((font, size.0));
                 if let Some(prepared_font) = fonts.get(font) {
                     content.push(LoOp::new(
                         "Tf",
-                        vec![font.0.clone().into(), (size.0).into()],
+                        vec![
+                            get_font_resource_name(font, vertical).into(),
+                            (size.0).into(),
+                        ],
                     ));
 
                     let subset_codepoints = cp
@@ -541,6 +1055,26 @@ fn translate_operations(
                     content.push(LoOp::new("TJ", vec![Array(list)]));
                 }
             }
+            Op::WriteShapedText { font, size, text } => {
+                current_font = Some((font, size.0));
+                if let Some(prepared_font) = fonts.get(font) {
+                    content.push(LoOp::new(
+                        "Tf",
+                        vec![
+                            get_font_resource_name(font, vertical).into(),
+                            (size.0).into(),
+                        ],
+                    ));
+                    // reordered (right-to-left) text is marked with its logical order
+                    if let Some(actual_text) = text.actual_text.as_ref() {
+                        content.push(actual_text_span(actual_text));
+                        content.extend(shaped_text_to_ops(text, *size, prepared_font, vertical));
+                        content.push(LoOp::new("EMC", vec![]));
+                    } else {
+                        content.extend(shaped_text_to_ops(text, *size, prepared_font, vertical));
+                    }
+                }
+            }
             Op::AddLineBreak => {
                 content.push(LoOp::new("T*", vec![]));
             }
@@ -551,31 +1085,23 @@ fn translate_operations(
                 content.push(LoOp::new("Tw", vec![Real(*percent)]));
             }
             Op::SetFontSize { size, font } => {
+                current_font = Some((font, size.0));
                 content.push(LoOp::new(
                     "Tf",
-                    vec![font.0.clone().into(), (size.0).into()],
+                    vec![
+                        get_font_resource_name(font, vertical).into(),
+                        (size.0).into(),
+                    ],
                 ));
             }
             Op::SetTextCursor { pos } => {
                 content.push(LoOp::new("Td", vec![pos.x.0.into(), pos.y.0.into()]));
             }
             Op::SetFillColor { col } => {
-                let ci = match &col {
-                    Color::Rgb(_) => "rg",
-                    Color::Cmyk(_) | Color::SpotColor(_) => "k",
-                    Color::Greyscale(_) => "g",
-                };
-                let cvec = col.into_vec().into_iter().map(Real).collect();
-                content.push(LoOp::new(ci, cvec));
+                content.append(&mut color_to_stream_ops(col, false));
             }
             Op::SetOutlineColor { col } => {
-                let ci = match &col {
-                    Color::Rgb(_) => "RG",
-                    Color::Cmyk(_) | Color::SpotColor(_) => "K",
-                    Color::Greyscale(_) => "G",
-                };
-                let cvec = col.into_vec().into_iter().map(Real).collect();
-                content.push(LoOp::new(ci, cvec));
+                content.append(&mut color_to_stream_ops(col, true));
             }
             Op::SetOutlineThickness { pt } => {
                 content.push(LoOp::new("w", vec![Real(pt.0)]));
@@ -624,40 +1150,728 @@ fn translate_operations(
                 // TODO!
             }
             Op::UseXObject { id, transform } => {
-                use crate::matrix::CurTransMat;
-                let mut t = CurTransMat::Identity;
-                for q in
-                    transform.get_ctms(xobjects.get(id).and_then(|xobj| xobj.get_width_height()))
-                {
-                    t = CurTransMat::Raw(CurTransMat::combine_matrix(t.as_array(), q.as_array()));
-                }
-
+                let ctm = match xobjects.get(id) {
+                    Some(XObject::Image(im)) => get_image_matrix(transform, im),
+                    xobj => {
+                        get_placement_matrix(transform, xobj.and_then(|x| x.get_width_height()))
+                    }
+                };
                 content.push(LoOp::new("q", vec![]));
-                content.push(LoOp::new(
-                    "cm",
-                    t.as_array().into_iter().map(Real).collect(),
-                ));
+                content.push(LoOp::new("cm", ctm.into_iter().map(Real).collect()));
                 content.push(LoOp::new("Do", vec![Name(id.0.as_bytes().to_vec())]));
                 content.push(LoOp::new("Q", vec![]));
             }
+            Op::DrawInlineImage { image, transform } => {
+                // images that don't fit were already promoted to XObjects
+                let Some(Some(inline_image)) = find_inline_image(inline_images, image) else {
+                    continue;
+                };
+                let ctm = get_image_matrix(transform, image);
+                content.push(LoOp::new("q", vec![]));
+                content.push(LoOp::new("cm", ctm.into_iter().map(Real).collect()));
+                // the inline image data can't be expressed as an operation, so the
+                // operations so far are encoded before appending the raw image bytes
+                bytes.append(&mut encode_content(std::mem::take(&mut content)));
+                bytes.extend_from_slice(inline_image);
+                content.push(LoOp::new("Q", vec![]));
+            }
+            Op::PaintShading { shading } => {
+                content.push(LoOp::new("sh", vec![Name(shading.0.clone().into())]));
+            }
+            Op::SetClippingPath { path } => {
+                content.append(&mut clipping_path_to_stream_ops(path));
+            }
             Op::Unknown { key, value } => {
                 content.push(LoOp::new(key.as_str(), value.clone()));
             }
         }
     }
 
-    lopdf::content::Content {
-        operations: content,
+    bytes.append(&mut encode_content(content));
+    bytes
+}
+
+fn encode_content(operations: Vec<LoOp>) -> Vec<u8> {
+    lopdf::content::Content { operations }
+        .encode()
+        .unwrap_or_default()
+}
+
+fn get_placement_matrix(transform: &XObjectTransform, wh: Option<(Px, Px)>) -> [f32; 6] {
+    use crate::matrix::CurTransMat;
+    let mut t = CurTransMat::Identity;
+    for q in transform.get_ctms(wh) {
+        t = CurTransMat::Raw(CurTransMat::combine_matrix(t.as_array(), q.as_array()));
+    }
+    t.as_array()
+}
+
+// Placement matrix of an image: the EXIF orientation is applied to the unit square
+// before scaling it to the oriented size, at the resolution of the image by default
+fn get_image_matrix(transform: &XObjectTransform, im: &RawImage) -> [f32; 6] {
+    use crate::matrix::CurTransMat;
+    let mut transform = transform.clone();
+    transform.dpi = transform.dpi.or(im.dpi);
+    let (w, h) = im.get_oriented_size();
+    let placement = get_placement_matrix(&transform, Some((Px(w), Px(h))));
+    CurTransMat::combine_matrix(im.orientation.get_unit_matrix(), placement)
+}
+
+// Calls `f` with the operations of all content streams: pages, tiling pattern
+// cells, soft masks and forms
+fn for_each_ops_mut(pdf: &mut PdfDocument, mut f: impl FnMut(&mut Vec<Op>)) {
+    for page in pdf.pages.iter_mut() {
+        f(&mut page.ops);
+    }
+    for pattern in pdf.resources.patterns.map.values_mut() {
+        f(&mut pattern.ops);
+    }
+    for gs in pdf.resources.extgstates.map.values_mut() {
+        if let Some(soft_mask) = gs.soft_mask.as_mut() {
+            f(&mut soft_mask.ops);
+        }
+    }
+    for xobject in pdf.resources.xobjects.map.values_mut() {
+        if let XObject::Form(form) = xobject {
+            f(&mut form.ops);
+        }
+    }
+}
+
+/// Resamples images that are placed with a higher resolution than the
+/// `PdfSaveOptions::max_image_dpi` and re-compresses images as JPEG if
+/// `PdfSaveOptions::jpeg_quality` is set. Only the `XObjectTransform` of the
+/// image is taken into account, not the transformations of the page.
+fn optimize_images(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Option<PdfDocument> {
+    if opts.max_image_dpi.is_none() && opts.jpeg_quality.is_none() {
+        return None;
+    }
+
+    // largest scale factor each image is needed at
+    let mut scales = BTreeMap::<XObjectId, f32>::new();
+    if let Some(max_dpi) = opts.max_image_dpi {
+        for op in get_all_ops(pdf) {
+            let Op::UseXObject { id, transform } = op else {
+                continue;
+            };
+            let Some(XObject::Image(im)) = pdf.resources.xobjects.map.get(id) else {
+                continue;
+            };
+            if im.width == 0 || im.height == 0 {
+                continue;
+            }
+            let [a, b, c, d, _, _] = get_image_matrix(transform, im);
+            // size of the image on the page in inches
+            let width_in = a.hypot(b) / 72.0;
+            let height_in = c.hypot(d) / 72.0;
+            let scale = (max_dpi as f32 * width_in / im.width as f32)
+                .max(max_dpi as f32 * height_in / im.height as f32);
+            let entry = scales.entry(id.clone()).or_insert(0.0);
+            *entry = entry.max(scale);
+        }
+    }
+
+    let mut pdf = pdf.clone();
+    let mut resized = BTreeMap::new();
+    for (id, xobject) in pdf.resources.xobjects.map.iter_mut() {
+        let XObject::Image(im) = xobject else {
+            continue;
+        };
+
+        let scale = scales.get(id).copied().unwrap_or(1.0);
+        if scale < 1.0 {
+            let width = ((im.width as f32 * scale).ceil() as usize).max(1);
+            let height = ((im.height as f32 * scale).ceil() as usize).max(1);
+            if let Some(new) = im.resize(width, height, opts.image_resampling) {
+                let old_size = (im.get_oriented_size(), im.dpi);
+                resized.insert(id.clone(), (old_size, new.get_oriented_size()));
+                *im = new;
+            }
+        }
+
+        if let Some(quality) = opts.jpeg_quality.filter(|_| im.encoded.is_none()) {
+            im.encoded = crate::image::image_to_jpeg(im, quality, opts);
+        }
+    }
+
+    for_each_ops_mut(&mut pdf, |ops| {
+        for op in ops.iter_mut() {
+            let Op::UseXObject { id, transform } = op else {
+                continue;
+            };
+            if let Some(((old, dpi), new)) = resized.get(id) {
+                keep_placement_size(transform, *old, *new, *dpi);
+            }
+        }
+    });
+
+    Some(pdf)
+}
+
+#[test]
+fn test_optimize_images_keeps_placement() {
+    use crate::{Mm, PdfPage, RawImageData, RawImageFormat};
+
+    let mut im = RawImage::empty(1000, 500, RawImageFormat::R8);
+    im.pixels = RawImageData::U8(vec![128; 1000 * 500]);
+
+    let mut pdf = PdfDocument::new("test");
+    let id = pdf.add_image(&im);
+    let transform = XObjectTransform {
+        translate_x: Some(Pt(10.0)),
+        translate_y: Some(Pt(20.0)),
+        dpi: Some(200.0),
+        ..Default::default()
+    };
+    let ops = vec![Op::UseXObject {
+        id: id.clone(),
+        transform,
+    }];
+    pdf.with_pages(vec![PdfPage::new(Mm(210.0), Mm(297.0), ops)]);
+
+    // 5 x 2.5 inches on the page, at most 100 DPI: resampled to 500 x 250 pixels,
+    // even if the pixels are not compressed
+    let opts = PdfSaveOptions {
+        compress_images: false,
+        max_image_dpi: Some(100),
+        ..Default::default()
+    };
+    let optimized = optimize_images(&pdf, &opts).unwrap();
+    let Some(XObject::Image(resized)) = optimized.resources.xobjects.map.get(&id) else {
+        panic!("image missing");
+    };
+    assert!(resized.width.abs_diff(500) <= 1);
+    assert!(resized.height.abs_diff(250) <= 1);
+
+    let Some(Op::UseXObject {
+        transform: new_transform,
+        ..
+    }) = optimized.pages[0].ops.first()
+    else {
+        panic!("UseXObject missing");
+    };
+    let before = get_image_matrix(&transform, &im);
+    let after = get_image_matrix(new_transform, resized);
+    for (b, a) in before.iter().zip(after.iter()) {
+        assert!((b - a).abs() < 0.01, "{before:?} != {after:?}");
+    }
+}
+
+// Adjusts the transform of a resampled image so that it keeps its size on the page,
+// `old` and `new` are the oriented sizes, `dpi` is the resolution of the original image
+fn keep_placement_size(
+    transform: &mut XObjectTransform,
+    old: (usize, usize),
+    new: (usize, usize),
+    dpi: Option<f32>,
+) {
+    let sx = new.0 as f32 / old.0 as f32;
+    let sy = new.1 as f32 / old.1 as f32;
+    // fewer pixels at a lower resolution, the aspect ratio may be off by a fraction of a pixel
+    transform.dpi = Some(transform.dpi.or(dpi).unwrap_or(300.0) * sx);
+    transform.scale_y = Some(transform.scale_y.unwrap_or(1.0) * sx / sy);
+    if let Some(rotate) = transform.rotate.as_mut() {
+        rotate.rotation_center_x = Px((rotate.rotation_center_x.0 as f32 * sx).round() as usize);
+        rotate.rotation_center_y = Px((rotate.rotation_center_y.0 as f32 * sx).round() as usize);
+    }
+}
+
+/// Image of an `Op::DrawInlineImage` with its encoded inline image data (see
+/// `image_to_inline_image`), `None` if the image is written as an XObject instead
+type InlineImage<'a> = (&'a RawImage, Option<Vec<u8>>);
+
+// Returns the encoded data of an inline image. Images are compared by value, because
+// the operations may have been cloned after they were encoded
+fn find_inline_image<'a>(
+    inline_images: &'a [InlineImage],
+    image: &RawImage,
+) -> Option<&'a Option<Vec<u8>>> {
+    inline_images
+        .iter()
+        .find(|(im, _)| std::ptr::eq(*im, image) || *im == image)
+        .map(|(_, data)| data)
+}
+
+/// Encodes the images of all `Op::DrawInlineImage` operations once and replaces the
+/// ones that can't be written inline with image XObjects. The document is `None` if
+/// there is nothing to replace, so that it doesn't have to be cloned.
+fn promote_inline_images(
+    pdf: &PdfDocument,
+    max_size: usize,
+) -> (Option<PdfDocument>, Vec<InlineImage<'_>>) {
+    let mut inline_images = Vec::<InlineImage>::new();
+    for op in get_all_ops(pdf) {
+        let Op::DrawInlineImage { image, .. } = op else {
+            continue;
+        };
+        if find_inline_image(&inline_images, image).is_none() {
+            let data = crate::image::image_to_inline_image(image, max_size);
+            inline_images.push((image, data));
+        }
+    }
+
+    if inline_images.iter().all(|(_, data)| data.is_some()) {
+        return (None, inline_images);
+    }
+
+    let mut promoted = pdf.clone();
+    let mut images = Vec::new();
+    for_each_ops_mut(&mut promoted, |ops| {
+        for op in ops.iter_mut() {
+            let Op::DrawInlineImage { image, transform } = op else {
+                continue;
+            };
+            if find_inline_image(&inline_images, image).is_some_and(Option::is_some) {
+                continue;
+            }
+            let id = XObjectId::new();
+            images.push((id.clone(), XObject::Image(image.clone())));
+            *op = Op::UseXObject {
+                id,
+                transform: transform.clone(),
+            };
+        }
+    });
+
+    promoted.resources.xobjects.map.extend(images);
+    (Some(promoted), inline_images)
+}
+
+#[test]
+fn test_inline_images() {
+    use crate::{Mm, PdfPage, RawImageData, RawImageFormat};
+
+    let image = |width, height, format, pixels| {
+        let mut im = RawImage::empty(width, height, format);
+        im.pixels = RawImageData::U8(pixels);
+        im
+    };
+    // 10000 bytes of pixels, but only a few bytes compressed: written inline
+    let uniform = image(100, 100, RawImageFormat::R8, vec![128; 100 * 100]);
+    // 3600 bytes of noise, larger than the 4 KB limit after the ASCII85 encoding
+    let mut seed = 1_u32;
+    let noise = (0..60 * 60)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    let noise = image(60, 60, RawImageFormat::R8, noise);
+    // inline images can't have an /SMask
+    let alpha = image(1, 1, RawImageFormat::RGBA8, vec![255, 0, 0, 128]);
+
+    let draw = |image: &RawImage| Op::DrawInlineImage {
+        image: image.clone(),
+        transform: XObjectTransform::default(),
+    };
+    let ops = vec![draw(&uniform), draw(&noise), draw(&alpha)];
+    let mut pdf = PdfDocument::new("inline images");
+    pdf.with_pages(vec![PdfPage::new(Mm(210.0), Mm(297.0), ops)]);
+
+    let (promoted, inline_images) = promote_inline_images(&pdf, INLINE_IMAGE_MAX_SIZE);
+    assert_eq!(inline_images.len(), 3);
+    let promoted = promoted.unwrap();
+    let ops = &promoted.pages[0].ops;
+    assert!(matches!(ops[0], Op::DrawInlineImage { .. }));
+    for op in &ops[1..] {
+        let Op::UseXObject { id, .. } = op else {
+            panic!("{op:?} was not promoted");
+        };
+        let xobject = promoted.resources.xobjects.map.get(id);
+        assert!(matches!(xobject, Some(XObject::Image(_))));
+    }
+
+    // q cm BI ... ID <data> EI Q, followed by the two promoted images
+    let bytes = translate_operations(
+        ops,
+        &BTreeMap::new(),
+        &promoted.resources.xobjects.map,
+        &inline_images,
+    );
+    let content = String::from_utf8(bytes).unwrap();
+    let (head, rest) = content.split_once("BI\n").unwrap();
+    assert!(head.starts_with("q\n"), "{head}");
+    assert!(head.ends_with(" cm\n"), "{head}");
+    let (dict, rest) = rest.split_once("\nID\n").unwrap();
+    assert_eq!(dict, "/W 100 /H 100 /BPC 8 /CS /G /F [/A85 /Fl]");
+    let (data, rest) = rest.split_once("\nEI\n").unwrap();
+    assert!(data.len() < INLINE_IMAGE_MAX_SIZE && data.ends_with("~>"));
+    assert!(rest.starts_with("Q\n"), "{rest}");
+    assert_eq!(rest.matches(" Do\n").count(), 2);
+}
+
+/// Returns the name of the font in the resources, fonts that are used in
+/// vertical text sections are added a second time with the vertical metrics
+fn get_font_resource_name(font: &FontId, vertical: bool) -> String {
+    if vertical {
+        format!("{}-V", font.0)
+    } else {
+        font.0.clone()
+    }
+}
+
+/// Starts a marked content sequence (`BDC`, ended by `EMC`) with the text that the
+/// glyphs in it represent
+fn actual_text_span(text: &str) -> LoOp {
+    let utf16 = [0xFE, 0xFF]
+        .into_iter()
+        .chain(text.encode_utf16().flat_map(|u| u.to_be_bytes()))
+        .collect::<Vec<u8>>();
+    LoOp::new(
+        "BDC",
+        vec![
+            Name("Span".into()),
+            Dictionary(LoDictionary::from_iter(vec![(
+                "ActualText",
+                LoString(utf16, Hexadecimal),
+            )])),
+        ],
+    )
+}
+
+// Returns the text of a glyph if it differs from the text in the ToUnicode CMap,
+// except if the whole text is marked with its `/ActualText` (right-to-left text)
+fn get_glyph_actual_text<'a>(
+    text: &ShapedText,
+    glyph: &'a ShapedGlyph,
+    gid: u16,
+    font: &PreparedFont,
+) -> Option<&'a str> {
+    let mapped = font.shaped_text.get(&gid).map_or("", |s| s.as_str());
+    (text.actual_text.is_none() && mapped != glyph.text).then_some(glyph.text.as_str())
+}
+
+/// Writes the glyphs of a shaped text as `TJ` arrays. The PDF viewer advances by the
+/// width from the `W` (or `W2`) array after each glyph, the difference to the shaped
+/// position is written as an adjustment before the glyph. Vertical offsets (i.e. of
+/// stacked marks) are written with the text rise (`Ts`) in horizontal text. Glyphs
+/// whose text differs from the ToUnicode CMap are written in an `/ActualText` span.
+fn shaped_text_to_ops(
+    text: &ShapedText,
+    size: Pt,
+    font: &PreparedFont,
+    vertical: bool,
+) -> Vec<LoOp> {
+    if vertical {
+        return vertical_shaped_text_to_ops(text, font);
+    }
+
+    // TJ adjustments are in thousandths of the font size
+    let scale = 1000.0 / text.units_per_em.max(1) as f32;
+    let mut ops = Vec::new();
+    let mut list = Vec::new();
+    let mut rise = 0;
+    let mut pen = 0.0;
+    let mut cursor = 0.0;
+
+    for glyph in text.glyphs.iter() {
+        let Some((gid, _)) = font.subset_font.glyph_mapping.get(&glyph.glyph_id) else {
+            pen += glyph.advance as f32 * scale;
+            continue;
+        };
+        let actual_text = get_glyph_actual_text(text, glyph, *gid, font);
+
+        if glyph.y_offset != rise {
+            if !list.is_empty() {
+                ops.push(LoOp::new("TJ", vec![Array(std::mem::take(&mut list))]));
+            }
+            rise = glyph.y_offset;
+            ops.push(LoOp::new(
+                "Ts",
+                vec![Real(rise as f32 * size.0 / text.units_per_em.max(1) as f32)],
+            ));
+        }
+
+        if let Some(actual_text) = actual_text {
+            if !list.is_empty() {
+                ops.push(LoOp::new("TJ", vec![Array(std::mem::take(&mut list))]));
+            }
+            ops.push(actual_text_span(actual_text));
+        }
+
+        let target = pen + glyph.x_offset as f32 * scale;
+        if (target - cursor).abs() > 0.01 {
+            list.push(Real(cursor - target));
+        }
+        list.push(LoString(gid.to_be_bytes().to_vec(), Hexadecimal));
+        cursor = target + font.original.get_pdf_glyph_width(*gid) as f32;
+        pen += glyph.advance as f32 * scale;
+
+        if actual_text.is_some() {
+            ops.push(LoOp::new("TJ", vec![Array(std::mem::take(&mut list))]));
+            ops.push(LoOp::new("EMC", vec![]));
+        }
+    }
+
+    // leave the text cursor at the end of the shaped text
+    if (pen - cursor).abs() > 0.01 {
+        list.push(Real(cursor - pen));
+    }
+    if !list.is_empty() {
+        ops.push(LoOp::new("TJ", vec![Array(list)]));
+    }
+    if rise != 0 {
+        ops.push(LoOp::new("Ts", vec![Real(0.0)]));
+    }
+
+    ops
+}
+
+/// Writes the glyphs of a shaped text in a vertical text section: the pen moves downwards
+/// by the vertical advances, vertical offsets are part of the `TJ` adjustments
+fn vertical_shaped_text_to_ops(text: &ShapedText, font: &PreparedFont) -> Vec<LoOp> {
+    let scale = 1000.0 / text.units_per_em.max(1) as f32;
+    let mut ops = Vec::new();
+    let mut list = Vec::new();
+    // distances from the start of the text, downwards
+    let mut pen = 0.0;
+    let mut cursor = 0.0;
+
+    for glyph in text.glyphs.iter() {
+        let Some((gid, _)) = font.subset_font.glyph_mapping.get(&glyph.glyph_id) else {
+            pen += glyph.advance as f32 * scale;
+            continue;
+        };
+
+        let actual_text = get_glyph_actual_text(text, glyph, *gid, font);
+        if let Some(actual_text) = actual_text {
+            if !list.is_empty() {
+                ops.push(LoOp::new("TJ", vec![Array(std::mem::take(&mut list))]));
+            }
+            ops.push(actual_text_span(actual_text));
+        }
+
+        // in vertical mode, positive adjustments move the next glyph down
+        let target = pen - glyph.y_offset as f32 * scale;
+        if (target - cursor).abs() > 0.01 {
+            list.push(Real(target - cursor));
+        }
+        list.push(LoString(gid.to_be_bytes().to_vec(), Hexadecimal));
+        cursor = target + font.original.get_pdf_glyph_vertical_advance(*gid) as f32;
+        pen += glyph.advance as f32 * scale;
+
+        if actual_text.is_some() {
+            ops.push(LoOp::new("TJ", vec![Array(std::mem::take(&mut list))]));
+            ops.push(LoOp::new("EMC", vec![]));
+        }
+    }
+
+    if (pen - cursor).abs() > 0.01 {
+        list.push(Real(pen - cursor));
+    }
+    if !list.is_empty() {
+        ops.push(LoOp::new("TJ", vec![Array(list)]));
+    }
+
+    ops
+}
+
+#[test]
+fn test_shaped_text_ops() {
+    let font = ParsedFont::from_bytes(
+        include_bytes!("../examples/assets/fonts/RobotoMedium.ttf"),
+        0,
+    )
+    .unwrap();
+    let mut doc = PdfDocument::new("test");
+    let font_id = doc.add_font(&font);
+    let options = crate::TextShapingOptions::default();
+    let kerned = font.shape_text("AV", &options).unwrap();
+    let ligatures = font.shape_text("fi fl", &options).unwrap();
+    // the cmap maps U+FB01 to the "fi" ligature glyph
+    let ligature_char = font.shape_text("\u{FB01}", &options).unwrap();
+    let texts = [&kerned, &ligatures, &ligature_char];
+    let ops = texts.map(|text| Op::WriteShapedText {
+        font: font_id.clone(),
+        size: Pt(12.0),
+        text: text.clone(),
+    });
+    let fonts = prepare_fonts(&doc.resources, &ops.iter().collect::<Vec<_>>());
+    let prepared = &fonts[&font_id];
+    let subset_gid = |gid: u16| prepared.subset_font.glyph_mapping[&gid].0;
+    let is_glyph = |object: &lopdf::Object, gid: u16| {
+        let gid = subset_gid(gid).to_be_bytes();
+        matches!(object, LoString(bytes, _) if *bytes == gid)
+    };
+
+    // kerning moves the "V" to the left: a positive adjustment
+    let a = font.lookup_glyph_index('A' as u32).unwrap();
+    let v = font.lookup_glyph_index('V' as u32).unwrap();
+    let ops = shaped_text_to_ops(&kerned, Pt(12.0), prepared, false);
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].operator, "TJ");
+    let list = ops[0].operands[0].as_array().unwrap();
+    assert!(is_glyph(&list[0], a));
+    assert!(matches!(list[1], Real(r) if r > 30.0));
+    assert!(is_glyph(&list[2], v));
+
+    // Roboto has no mark positioning, raise the "V" like a stacked mark
+    let mut raised = kerned.clone();
+    raised.glyphs[1].y_offset = 512;
+    let ops = shaped_text_to_ops(&raised, Pt(12.0), prepared, false);
+    let operators = ops
+        .iter()
+        .map(|op| op.operator.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(operators, ["TJ", "Ts", "TJ", "Ts"]);
+    assert_eq!(ops[1].operands, vec![Real(3.0)]);
+    assert_eq!(ops[3].operands, vec![Real(0.0)]);
+
+    // the ligature glyph of "fi" maps to U+FB01 (the cmap), the one of "fl" to its text
+    let texts = ligatures
+        .glyphs
+        .iter()
+        .map(|g| g.text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, ["fi", " ", "fl"]);
+    let fi = subset_gid(ligatures.glyphs[0].glyph_id);
+    let fl = subset_gid(ligatures.glyphs[2].glyph_id);
+    assert_eq!(
+        ligature_char.glyphs[0].glyph_id,
+        ligatures.glyphs[0].glyph_id
+    );
+    assert!(prepared
+        .cid_to_unicode_map
+        .contains(&format!("<{fi:04x}> <fb01>")));
+    assert!(prepared
+        .cid_to_unicode_map
+        .contains(&format!("<{fl:04x}> <0066006c>")));
+
+    // so "fi" is written with its text as /ActualText
+    let ops = shaped_text_to_ops(&ligatures, Pt(12.0), prepared, false);
+    let operators = ops
+        .iter()
+        .map(|op| op.operator.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(operators, ["BDC", "TJ", "EMC", "TJ"]);
+    let properties = ops[0].operands[1].as_dict().unwrap();
+    assert_eq!(
+        properties.get(b"ActualText").unwrap(),
+        &LoString(vec![0xFE, 0xFF, 0x00, 0x66, 0x00, 0x69], Hexadecimal)
+    );
+    let ops = shaped_text_to_ops(&ligature_char, Pt(12.0), prepared, false);
+    assert!(ops.iter().all(|op| op.operator != "BDC"));
+}
+
+#[test]
+fn test_vertical_shaped_text_ops() {
+    let font = ParsedFont::from_bytes(
+        include_bytes!("../examples/assets/fonts/RobotoMedium.ttf"),
+        0,
+    )
+    .unwrap();
+    let mut doc = PdfDocument::new("test");
+    let font_id = doc.add_font(&font);
+    let glyph = |c: char, y_offset: i32| ShapedGlyph {
+        glyph_id: font.lookup_glyph_index(c as u32).unwrap(),
+        text: c.to_string(),
+        advance: 2048,
+        x_offset: 0,
+        y_offset,
+    };
+    // advances of one em (1000), the W2 advance of Roboto is 1171
+    let text = ShapedText {
+        glyphs: vec![glyph('A', 0), glyph('V', -1024)],
+        units_per_em: 2048,
+        actual_text: None,
+        vertical: true,
+    };
+    let ops = [Op::WriteShapedText {
+        font: font_id.clone(),
+        size: Pt(12.0),
+        text: text.clone(),
+    }];
+    let fonts = prepare_fonts(&doc.resources, &ops.iter().collect::<Vec<_>>());
+    let prepared = &fonts[&font_id];
+
+    // positive adjustments move the next glyph down: the y offset moves the "V" 500
+    // below the pen, which is 329 below the W2 advance of the "A". The pen ends 671
+    // above the W2 advance of the "V".
+    let ops = shaped_text_to_ops(&text, Pt(12.0), prepared, true);
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].operator, "TJ");
+    let list = ops[0].operands[0].as_array().unwrap();
+    assert_eq!(list.len(), 4);
+    assert_eq!(list[1], Real(329.0));
+    assert_eq!(list[3], Real(-671.0));
+}
+
+/// Returns the fonts that are written in vertical text sections
+fn get_vertical_fonts(ops: &[&Op]) -> BTreeSet<FontId> {
+    let mut vertical = false;
+    let mut current_font = None;
+    let mut fonts = BTreeSet::new();
+    for op in ops {
+        match op {
+            Op::StartTextSection | Op::EndTextSection => {
+                vertical = false;
+                current_font = None;
+            }
+            Op::SetWritingMode { mode } => {
+                vertical = *mode == WritingMode::Vertical;
+                if let (true, Some(font)) = (vertical, current_font) {
+                    fonts.insert(font.clone());
+                }
+            }
+            Op::WriteText { font, .. }
+            | Op::WriteCodepoints { font, .. }
+            | Op::WriteCodepointsWithKerning { font, .. }
+            | Op::WriteShapedText { font, .. }
+            | Op::SetFontSize { font, .. } => {
+                current_font = Some(font);
+                if vertical {
+                    fonts.insert(font.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    fonts
+}
+
+/// Returns the text of the shaped glyphs written with the font for the ToUnicode CMap,
+/// by original glyph ID. Glyphs that are written for a character of the cmap map to
+/// that character, other glyphs (ligatures, alternates) map to the first text they are
+/// written for. Glyphs written for a different text are marked with `/ActualText`.
+fn get_shaped_text_clusters(
+    font_id: &FontId,
+    font: &ParsedFont,
+    ops: &[&Op],
+) -> BTreeMap<u16, String> {
+    let glyphs = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::WriteShapedText { font, text, .. } if font == font_id => Some(text),
+            _ => None,
+        })
+        .flat_map(|text| text.glyphs.iter())
+        .collect::<Vec<_>>();
+
+    let mut clusters = BTreeMap::new();
+    for glyph in glyphs.iter() {
+        let mut chars = glyph.text.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if font.lookup_glyph_index(c as u32) == Some(glyph.glyph_id) {
+                clusters.insert(glyph.glyph_id, glyph.text.clone());
+            }
+        }
+    }
+    for glyph in glyphs {
+        clusters
+            .entry(glyph.glyph_id)
+            .or_insert_with(|| glyph.text.clone());
     }
-    .encode()
-    .unwrap_or_default()
+    clusters
 }
 
 struct PreparedFont {
     original: ParsedFont,
     subset_font: SubsetFont,
     cid_to_unicode_map: String,
-    vertical_writing: bool, // default: false
+    // text of the shaped glyphs in the ToUnicode CMap, by glyph ID of the subset font
+    shaped_text: BTreeMap<u16, String>,
+    // W2 array of the font if it is used in vertical text sections
+    vertical_widths_list: Option<Vec<lopdf::Object>>,
     ascent: i64,
     descent: i64,
     max_height: i64,
@@ -673,6 +1887,174 @@ struct PreparedFont {
 
 const DEFAULT_CHARACTER_WIDTH: i64 = 1000;
 
+/// Sets the fill (or stroke) color, selecting the color space first if necessary
+fn color_to_stream_ops(col: &Color, stroke: bool) -> Vec<LoOp> {
+    let (cs, sc, scn) = if stroke {
+        ("CS", "SC", "SCN")
+    } else {
+        ("cs", "sc", "scn")
+    };
+    let components = |c: &Color| c.into_vec().into_iter().map(Real).collect::<Vec<_>>();
+
+    match col {
+        Color::Rgb(_) => vec![LoOp::new(if stroke { "RG" } else { "rg" }, components(col))],
+        Color::Cmyk(_) | Color::SpotColor(_) => {
+            vec![LoOp::new(if stroke { "K" } else { "k" }, components(col))]
+        }
+        Color::Greyscale(_) => vec![LoOp::new(if stroke { "G" } else { "g" }, components(col))],
+        Color::Lab { .. } | Color::CalRgb { .. } | Color::CalGray { .. } => {
+            match col.get_color_space_resource() {
+                Some((name, _)) => vec![
+                    LoOp::new(cs, vec![Name(name.into())]),
+                    LoOp::new(sc, components(col)),
+                ],
+                None => Vec::new(),
+            }
+        }
+        Color::Shading(ShadingId(id)) | Color::Pattern(PatternId(id)) => vec![
+            LoOp::new(cs, vec![Name("Pattern".into())]),
+            LoOp::new(scn, vec![Name(id.clone().into())]),
+        ],
+        Color::UncoloredPattern { pattern, .. } => match (
+            col.get_color_space_resource(),
+            col.get_uncolored_pattern_color(),
+        ) {
+            (Some((name, _)), Some(color)) => {
+                let mut args = components(&color);
+                args.push(Name(pattern.0.clone().into()));
+                vec![LoOp::new(cs, vec![Name(name.into())]), LoOp::new(scn, args)]
+            }
+            _ => Vec::new(),
+        },
+    }
+}
+
+#[test]
+fn test_uncolored_pattern_color_ops() {
+    use crate::color::Rgb;
+
+    let pattern = PatternId("P1".to_string());
+    let red = Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None));
+    let col = Color::uncolored_pattern(pattern.clone(), red).unwrap();
+    let ops = color_to_stream_ops(&col, false);
+    assert_eq!(ops.len(), 2);
+    assert_eq!(ops[0].operator, "cs");
+    assert_eq!(ops[0].operands, vec![Name("PatternDeviceRGB".into())]);
+    assert_eq!(ops[1].operator, "scn");
+    assert_eq!(
+        ops[1].operands,
+        vec![Real(1.0), Real(0.0), Real(0.0), Name("P1".into())]
+    );
+
+    // patterns can't be nested
+    let nested = Color::Shading(ShadingId("S1".to_string()));
+    assert!(Color::uncolored_pattern(pattern.clone(), nested.clone()).is_err());
+
+    // built without the check, the pattern is painted black
+    let col = Color::UncoloredPattern {
+        pattern,
+        color: Box::new(nested),
+    };
+    let ops = color_to_stream_ops(&col, true);
+    assert_eq!(ops[0].operator, "CS");
+    assert_eq!(ops[0].operands, vec![Name("PatternDeviceGray".into())]);
+    assert_eq!(ops[1].operator, "SCN");
+    assert_eq!(ops[1].operands, vec![Real(0.0), Name("P1".into())]);
+}
+
+#[test]
+fn test_cie_color_ops() {
+    use crate::color::{D50_WHITE_POINT, D65_WHITE_POINT};
+
+    let lab = Color::lab(50.0, 20.0, -30.0);
+    let cal_rgb = Color::CalRgb {
+        r: 0.1,
+        g: 0.2,
+        b: 0.3,
+        white_point: D65_WHITE_POINT,
+        black_point: [0.0; 3],
+        gamma: [2.2; 3],
+        matrix: [
+            0.4124, 0.2126, 0.0193, 0.3576, 0.7152, 0.1192, 0.1805, 0.0722, 0.9505,
+        ],
+    };
+    let cal_gray = Color::CalGray {
+        gray: 0.5,
+        white_point: D65_WHITE_POINT,
+        black_point: [0.0; 3],
+        gamma: 2.2,
+    };
+
+    for (col, components) in [(&lab, 3), (&cal_rgb, 3), (&cal_gray, 1)] {
+        let (name, _) = col.get_color_space_resource().unwrap();
+        for (stroke, cs, sc) in [(false, "cs", "sc"), (true, "CS", "SC")] {
+            let ops = color_to_stream_ops(col, stroke);
+            assert_eq!(ops.len(), 2);
+            assert_eq!(ops[0].operator, cs);
+            assert_eq!(ops[0].operands, vec![Name(name.clone().into())]);
+            assert_eq!(ops[1].operator, sc);
+            assert_eq!(ops[1].operands.len(), components);
+        }
+    }
+    assert_eq!(
+        color_to_stream_ops(&lab, false)[1].operands,
+        vec![Real(50.0), Real(20.0), Real(-30.0)]
+    );
+
+    // colors with the same parameters share one resource
+    let lighter = Color::lab(80.0, 0.0, 0.0);
+    assert_eq!(
+        lighter.get_color_space_resource().unwrap().0,
+        lab.get_color_space_resource().unwrap().0
+    );
+    let ops = [
+        Op::SetFillColor { col: lab.clone() },
+        Op::SetOutlineColor { col: lighter },
+        Op::SetFillColor {
+            col: cal_rgb.clone(),
+        },
+        Op::SetOutlineColor {
+            col: cal_gray.clone(),
+        },
+    ];
+    let color_spaces = get_used_color_spaces(ops.iter());
+    assert_eq!(color_spaces.len(), 3);
+
+    let real_array = |v: &[f32]| Array(v.iter().copied().map(Real).collect());
+    let color_space = |col: &Color| {
+        let (name, _) = col.get_color_space_resource().unwrap();
+        let cs = color_spaces[&name].as_array().unwrap();
+        (cs[0].clone(), cs[1].as_dict().unwrap().clone())
+    };
+
+    let (family, dict) = color_space(&lab);
+    assert_eq!(family, Name("Lab".into()));
+    assert_eq!(
+        dict.get(b"WhitePoint").unwrap(),
+        &real_array(&D50_WHITE_POINT)
+    );
+    assert_eq!(
+        dict.get(b"Range").unwrap(),
+        &real_array(&[-100.0, 100.0, -100.0, 100.0])
+    );
+
+    let (family, dict) = color_space(&cal_rgb);
+    assert_eq!(family, Name("CalRGB".into()));
+    assert_eq!(dict.get(b"Gamma").unwrap(), &real_array(&[2.2; 3]));
+    let Color::CalRgb { matrix, .. } = &cal_rgb else {
+        unreachable!()
+    };
+    assert_eq!(dict.get(b"Matrix").unwrap(), &real_array(matrix));
+
+    let (family, dict) = color_space(&cal_gray);
+    assert_eq!(family, Name("CalGray".into()));
+    assert_eq!(
+        dict.get(b"WhitePoint").unwrap(),
+        &real_array(&D65_WHITE_POINT)
+    );
+    assert_eq!(dict.get(b"Gamma").unwrap(), &Real(2.2));
+}
+
 fn line_to_stream_ops(line: &Line) -> Vec<LoOp> {
     /// Cubic bezier over four following points
     pub const OP_PATH_CONST_4BEZIER: &str = "c";
@@ -723,8 +2105,8 @@ fn line_to_stream_ops(line: &Line) -> Vec<LoOp> {
                             OP_PATH_CONST_3BEZIER_V1,
                             vec![p3.0.x.into(), p3.0.y.into(), p4.0.x.into(), p4.0.y.into()],
                         ));
-                    } else if p2.0 == p3.0 {
-                        // first control point coincides with final point of curve
+                    } else if p3.0 == p4.0 {
+                        // second control point coincides with final point of curve
                         operations.push(LoOp::new(
                             OP_PATH_CONST_3BEZIER_V2,
                             vec![p2.0.x.into(), p2.0.y.into(), p4.0.x.into(), p4.0.y.into()],
@@ -768,6 +2150,117 @@ fn line_to_stream_ops(line: &Line) -> Vec<LoOp> {
 }
 
 fn polygon_to_stream_ops(poly: &Polygon) -> Vec<LoOp> {
+    /// Close and stroke path
+    pub const OP_PATH_PAINT_STROKE_CLOSE: &str = "s";
+    /// End path without filling or stroking
+    pub const OP_PATH_PAINT_END: &str = "n";
+
+    let mut operations = rings_to_path_ops(&poly.rings);
+
+    if poly.rings.is_empty() {
+        return operations;
+    };
+
+    match poly.mode {
+        PaintMode::Clip => {
+            // set the path as a clipping path
+            operations.push(LoOp::new(poly.winding_order.get_clip_op(), vec![]));
+        }
+        PaintMode::Fill => {
+            // is not stroked, only filled
+            // closed-ness doesn't matter in this case, an area is always closed
+            operations.push(LoOp::new(poly.winding_order.get_fill_op(), vec![]));
+        }
+        PaintMode::Stroke => {
+            // same as line with is_closed = true
+            operations.push(LoOp::new(OP_PATH_PAINT_STROKE_CLOSE, vec![]));
+        }
+        PaintMode::FillStroke => {
+            operations.push(LoOp::new(
+                poly.winding_order.get_fill_stroke_close_op(),
+                vec![],
+            ));
+        }
+    }
+
+    if !operations.is_empty() {
+        operations.push(LoOp::new(OP_PATH_PAINT_END, vec![]));
+    }
+
+    operations
+}
+
+/// Intersects the current clipping path with the path (`W n` / `W* n`)
+fn clipping_path_to_stream_ops(path: &Polygon) -> Vec<LoOp> {
+    /// End path without filling or stroking
+    pub const OP_PATH_PAINT_END: &str = "n";
+
+    let mut operations = rings_to_path_ops(&path.rings);
+
+    if !operations.is_empty() {
+        operations.push(LoOp::new(path.winding_order.get_clip_op(), vec![]));
+        operations.push(LoOp::new(OP_PATH_PAINT_END, vec![]));
+    }
+
+    operations
+}
+
+#[test]
+fn test_clipping_ops() {
+    use crate::graphics::Rect;
+
+    let operators = |ops: Vec<Op>| {
+        let bytes = translate_operations(&ops, &BTreeMap::new(), &BTreeMap::new(), &[]);
+        lopdf::content::Content::decode(&bytes)
+            .unwrap()
+            .operations
+            .into_iter()
+            .map(|op| op.operator)
+            .collect::<Vec<_>>()
+    };
+    let marker = || {
+        vec![Op::Marker {
+            id: "clipped".to_string(),
+        }]
+    };
+    let rect = Rect {
+        x: Pt(10.0),
+        y: Pt(10.0),
+        width: Pt(100.0),
+        height: Pt(50.0),
+    };
+
+    assert_eq!(
+        operators(Op::clipped_to_rect(&rect, marker())),
+        vec!["q", "m", "l", "l", "l", "W", "n", "MP", "Q"]
+    );
+
+    let path = Polygon {
+        rings: vec![vec![
+            (Point::new(crate::Mm(0.0), crate::Mm(0.0)), false),
+            (Point::new(crate::Mm(10.0), crate::Mm(0.0)), false),
+            (Point::new(crate::Mm(10.0), crate::Mm(10.0)), false),
+        ]],
+        mode: PaintMode::Clip,
+        winding_order: WindingOrder::EvenOdd,
+    };
+    assert_eq!(
+        operators(Op::clipped(path, marker())),
+        vec!["q", "m", "l", "l", "W*", "n", "MP", "Q"]
+    );
+
+    // glyph outlines are clipped with text rendering mode 7, applied at ET
+    let text = vec![Op::SetTextCursor {
+        pos: Point::new(crate::Mm(10.0), crate::Mm(10.0)),
+    }];
+    assert_eq!(
+        operators(Op::clipped_to_text(text, marker())),
+        vec!["q", "BT", "Tr", "Td", "ET", "MP", "Q"]
+    );
+}
+
+/// Builds the path construction operators (`m`, `l`, `c`, `v`, `y`) of the polygon rings
+fn rings_to_path_ops(rings: &[Vec<(Point, bool)>]) -> Vec<LoOp> {
     /// Cubic bezier over four following points
     pub const OP_PATH_CONST_4BEZIER: &str = "c";
     /// Cubic bezier with two points in v1
@@ -778,18 +2271,10 @@ fn polygon_to_stream_ops(poly: &Polygon) -> Vec<LoOp> {
     pub const OP_PATH_CONST_MOVE_TO: &str = "m";
     /// Straight line to the two following points
     pub const OP_PATH_CONST_LINE_TO: &str = "l";
-    /// Close and stroke path
-    pub const OP_PATH_PAINT_STROKE_CLOSE: &str = "s";
-    /// End path without filling or stroking
-    pub const OP_PATH_PAINT_END: &str = "n";
 
     let mut operations = Vec::new();
 
-    if poly.rings.is_empty() {
-        return operations;
-    };
-
-    for ring in poly.rings.iter() {
+    for ring in rings.iter().filter(|r| !r.is_empty()) {
         operations.push(LoOp::new(
             OP_PATH_CONST_MOVE_TO,
             vec![ring[0].0.x.into(), ring[0].0.y.into()],
@@ -818,8 +2303,8 @@ fn polygon_to_stream_ops(poly: &Polygon) -> Vec<LoOp> {
                                 OP_PATH_CONST_3BEZIER_V1,
                                 vec![p3.0.x.into(), p3.0.y.into(), p4.0.x.into(), p4.0.y.into()],
                             ));
-                        } else if p2.0 == p3.0 {
-                            // first control point coincides with final point of curve
+                        } else if p3.0 == p4.0 {
+                            // second control point coincides with final point of curve
                             operations.push(LoOp::new(
                                 OP_PATH_CONST_3BEZIER_V2,
                                 vec![p2.0.x.into(), p2.0.y.into(), p4.0.x.into(), p4.0.y.into()],
@@ -853,40 +2338,15 @@ fn polygon_to_stream_ops(poly: &Polygon) -> Vec<LoOp> {
         }
     }
 
-    match poly.mode {
-        PaintMode::Clip => {
-            // set the path as a clipping path
-            operations.push(LoOp::new(poly.winding_order.get_clip_op(), vec![]));
-        }
-        PaintMode::Fill => {
-            // is not stroked, only filled
-            // closed-ness doesn't matter in this case, an area is always closed
-            operations.push(LoOp::new(poly.winding_order.get_fill_op(), vec![]));
-        }
-        PaintMode::Stroke => {
-            // same as line with is_closed = true
-            operations.push(LoOp::new(OP_PATH_PAINT_STROKE_CLOSE, vec![]));
-        }
-        PaintMode::FillStroke => {
-            operations.push(LoOp::new(
-                poly.winding_order.get_fill_stroke_close_op(),
-                vec![],
-            ));
-        }
-    }
-
-    if !operations.is_empty() {
-        operations.push(LoOp::new(OP_PATH_PAINT_END, vec![]));
-    }
-
     operations
 }
 
-fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId, PreparedFont> {
+fn prepare_fonts(resources: &PdfResources, ops: &[&Op]) -> BTreeMap<FontId, PreparedFont> {
     let mut fonts_in_pdf = BTreeMap::new();
+    let vertical_fonts = get_vertical_fonts(ops);
 
     for (font_id, font) in resources.fonts.map.iter() {
-        let glyph_ids = font.get_used_glyph_ids(font_id, pages);
+        let glyph_ids = font.get_used_glyph_ids(font_id, ops);
         if glyph_ids.is_empty() {
             continue; // unused font
         }
@@ -898,12 +2358,23 @@ fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId
                     continue;
                 }
             };
+        let clusters = get_shaped_text_clusters(font_id, font, ops);
         let font = match ParsedFont::from_bytes(&subset_font.bytes, 0) {
             Some(s) => s,
             None => continue,
         };
-        let glyph_ids = font.get_used_glyph_ids(font_id, pages);
-        let cid_to_unicode = font.generate_cid_to_unicode_map(font_id, &glyph_ids);
+        // glyph IDs of the subset font: shaped text can contain glyphs that are not
+        // reachable from the cmap (ligatures, alternates)
+        let glyph_ids = subset_font
+            .glyph_mapping
+            .values()
+            .copied()
+            .collect::<BTreeMap<_, _>>();
+        let clusters = clusters
+            .into_iter()
+            .filter_map(|(gid, text)| Some((subset_font.glyph_mapping.get(&gid)?.0, text)))
+            .collect::<BTreeMap<_, _>>();
+        let cid_to_unicode = font.generate_cid_to_unicode_map(font_id, &glyph_ids, &clusters);
         let widths = font.get_normalized_widths(&glyph_ids);
         fonts_in_pdf.insert(
             font_id.clone(),
@@ -911,7 +2382,10 @@ fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId
                 original: font.clone(),
                 subset_font,
                 cid_to_unicode_map: cid_to_unicode,
-                vertical_writing: false, // !font.vmtx_data.is_empty(),
+                shaped_text: clusters,
+                vertical_widths_list: vertical_fonts
+                    .contains(font_id)
+                    .then(|| font.get_normalized_vertical_widths(&glyph_ids)),
                 ascent: font.font_metrics.ascender as i64,
                 descent: font.font_metrics.descender as i64,
                 widths_list: widths,
@@ -924,15 +2398,16 @@ fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId
     fonts_in_pdf
 }
 
+/// Adds the font file, descriptor and ToUnicode map to the document and returns the
+/// font dictionaries: the horizontal (`Identity-H`) one and, if the font is used in
+/// vertical text sections, the vertical (`Identity-V`) one with the `W2` metrics
 fn add_font_to_pdf(
     doc: &mut lopdf::Document,
     font_id: &FontId,
     prepared: &PreparedFont,
-) -> LoDictionary {
+) -> Vec<(bool, LoDictionary)> {
     let face_name = font_id.0.clone();
 
-    let vertical = prepared.vertical_writing;
-
     // WARNING: Font stream MAY NOT be compressed
     let font_stream = LoStream::new(
         LoDictionary::from_iter(vec![(
@@ -945,69 +2420,85 @@ fn add_font_to_pdf(
 
     let font_stream_ref = doc.add_object(font_stream);
 
-    LoDictionary::from_iter(vec![
-        ("Type", Name("Font".into())),
-        ("Subtype", Name("Type0".into())),
-        ("BaseFont", Name(face_name.clone().into_bytes())),
+    let font_descriptor_ref = doc.add_object(LoDictionary::from_iter(vec![
+        ("Type", Name("FontDescriptor".into())),
+        ("FontName", Name(face_name.clone().into_bytes())),
+        ("Ascent", Integer(prepared.ascent)),
+        ("Descent", Integer(prepared.descent)),
+        ("CapHeight", Integer(prepared.ascent)),
+        ("ItalicAngle", Integer(0)),
+        ("Flags", Integer(32)),
+        ("StemV", Integer(80)),
+        ("FontFile2", Reference(font_stream_ref)),
         (
-            "Encoding",
-            Name(if vertical { "Identity-V" } else { "Identity-H" }.into()),
-        ),
-        (
-            "ToUnicode",
-            Reference(doc.add_object(LoStream::new(
-                LoDictionary::new(),
-                prepared.cid_to_unicode_map.as_bytes().to_vec(),
-            ))),
+            "FontBBox",
+            Array(vec![
+                Integer(0),
+                Integer(0),
+                Integer(prepared.total_width),
+                Integer(prepared.max_height),
+            ]),
         ),
-        (
-            "DescendantFonts",
-            Array(vec![Dictionary(LoDictionary::from_iter(vec![
-                ("Type", Name("Font".into())),
-                ("Subtype", Name("CIDFontType2".into())),
-                ("BaseFont", Name(face_name.clone().into_bytes())),
-                (
-                    "CIDSystemInfo",
-                    Dictionary(LoDictionary::from_iter(vec![
-                        ("Registry", LoString("Adobe".into(), Literal)),
-                        ("Ordering", LoString("Identity".into(), Literal)),
-                        ("Supplement", Integer(0)),
-                    ])),
-                ),
-                (
-                    if vertical { "W2" } else { "W" },
-                    Array(prepared.widths_list.clone()),
-                ),
-                (
-                    if vertical { "DW2" } else { "DW" },
-                    Integer(DEFAULT_CHARACTER_WIDTH),
-                ),
-                (
-                    "FontDescriptor",
-                    Reference(doc.add_object(LoDictionary::from_iter(vec![
-                        ("Type", Name("FontDescriptor".into())),
-                        ("FontName", Name(face_name.clone().into_bytes())),
-                        ("Ascent", Integer(prepared.ascent)),
-                        ("Descent", Integer(prepared.descent)),
-                        ("CapHeight", Integer(prepared.ascent)),
-                        ("ItalicAngle", Integer(0)),
-                        ("Flags", Integer(32)),
-                        ("StemV", Integer(80)),
-                        ("FontFile2", Reference(font_stream_ref)),
+    ]));
+
+    let to_unicode_ref = doc.add_object(LoStream::new(
+        LoDictionary::new(),
+        prepared.cid_to_unicode_map.as_bytes().to_vec(),
+    ));
+
+    let font_dict = |vertical: bool| {
+        let metrics = match prepared.vertical_widths_list.as_ref() {
+            Some(widths) if vertical => vec![
+                ("W2", Array(widths.clone())),
+                // default vertical origin and advance (PDF 1.7 reference, page 446)
+                ("DW2", Array(vec![Integer(880), Integer(-1000)])),
+                // the horizontal widths are still required for the vertical origins
+                ("W", Array(prepared.widths_list.clone())),
+            ],
+            _ => vec![
+                ("W", Array(prepared.widths_list.clone())),
+                ("DW", Integer(DEFAULT_CHARACTER_WIDTH)),
+            ],
+        };
+
+        LoDictionary::from_iter(vec![
+            ("Type", Name("Font".into())),
+            ("Subtype", Name("Type0".into())),
+            ("BaseFont", Name(face_name.clone().into_bytes())),
+            (
+                "Encoding",
+                Name(if vertical { "Identity-V" } else { "Identity-H" }.into()),
+            ),
+            ("ToUnicode", Reference(to_unicode_ref)),
+            (
+                "DescendantFonts",
+                Array(vec![Dictionary(LoDictionary::from_iter(
+                    vec![
+                        ("Type", Name("Font".into())),
+                        ("Subtype", Name("CIDFontType2".into())),
+                        ("BaseFont", Name(face_name.clone().into_bytes())),
                         (
-                            "FontBBox",
-                            Array(vec![
-                                Integer(0),
-                                Integer(0),
-                                Integer(prepared.total_width),
-                                Integer(prepared.max_height),
-                            ]),
+                            "CIDSystemInfo",
+                            Dictionary(LoDictionary::from_iter(vec![
+                                ("Registry", LoString("Adobe".into(), Literal)),
+                                ("Ordering", LoString("Identity".into(), Literal)),
+                                ("Supplement", Integer(0)),
+                            ])),
                         ),
-                    ]))),
-                ),
-            ]))]),
-        ),
-    ])
+                        ("FontDescriptor", Reference(font_descriptor_ref)),
+                    ]
+                    .into_iter()
+                    .chain(metrics),
+                ))]),
+            ),
+        ])
+    };
+
+    let mut font_dicts = vec![(false, font_dict(false))];
+    if prepared.vertical_widths_list.is_some() {
+        font_dicts.push((true, font_dict(true)));
+    }
+    font_dicts
 }
 
 fn docinfo_to_dict(m: &PdfDocumentInfo) -> LoDictionary {
@@ -0,0 +1,315 @@
+//! Smooth shadings (axial and radial gradients)
+
+use crate::{color::Color, function::PdfFunction, graphics::Point, units::Pt};
+use lopdf::Object::{Array, Boolean, Integer, Name, Real};
+
+/// Axial or radial gradient, register it with `PdfDocument::add_shading`.
+///
+/// A shading can either be painted directly into the current clipping path
+/// (`Op::PaintShading`) or be used as a fill / stroke color (`Color::Shading`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shading {
+    /// Geometry of the gradient
+    pub shading_type: ShadingType,
+    /// Color stops of the gradient. All colors must be in the same color space
+    /// (i.e. all `Color::Rgb` or all `Color::Cmyk`).
+    pub stops: Vec<ColorStop>,
+    /// Whether to extend the shading beyond the start and end point
+    pub extend: (bool, bool),
+    /// Whether to apply anti-aliasing (may be ignored by the viewer)
+    pub anti_alias: bool,
+}
+
+/// Geometry of a `Shading`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadingType {
+    /// Type 2 shading: gradient along the axis from `start` to `end`
+    Axial { start: Point, end: Point },
+    /// Type 3 shading: gradient between two circles
+    Radial {
+        start: Point,
+        start_radius: Pt,
+        end: Point,
+        end_radius: Pt,
+    },
+}
+
+impl ShadingType {
+    /// Returns the PDF shading type
+    pub fn get_id(&self) -> i64 {
+        match self {
+            ShadingType::Axial { .. } => 2,
+            ShadingType::Radial { .. } => 3,
+        }
+    }
+}
+
+/// Color at a certain offset of a gradient
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStop {
+    /// Position of the stop, from 0.0 (start) to 1.0 (end)
+    pub offset: f32,
+    pub color: Color,
+}
+
+impl ColorStop {
+    pub fn new(offset: f32, color: Color) -> Self {
+        Self { offset, color }
+    }
+}
+
+impl Shading {
+    /// Creates a new linear gradient from `start` to `end`
+    pub fn axial(start: Point, end: Point, stops: Vec<ColorStop>) -> Self {
+        Self {
+            shading_type: ShadingType::Axial { start, end },
+            stops,
+            extend: (true, true),
+            anti_alias: false,
+        }
+    }
+
+    /// Creates a new radial gradient from the circle (`start`, `start_radius`)
+    /// to the circle (`end`, `end_radius`)
+    pub fn radial(
+        start: Point,
+        start_radius: Pt,
+        end: Point,
+        end_radius: Pt,
+        stops: Vec<ColorStop>,
+    ) -> Self {
+        Self {
+            shading_type: ShadingType::Radial {
+                start,
+                start_radius,
+                end,
+                end_radius,
+            },
+            stops,
+            extend: (true, true),
+            anti_alias: false,
+        }
+    }
+
+    /// Sets whether the shading is extended beyond the start and end point
+    #[inline]
+    pub fn with_extend(mut self, extend_start: bool, extend_end: bool) -> Self {
+        self.extend = (extend_start, extend_end);
+        self
+    }
+
+    /// Sets whether the shading should be anti-aliased
+    #[inline]
+    pub fn with_anti_alias(mut self, anti_alias: bool) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+
+    /// Checks that the shading has at least one color stop and that all stops
+    /// are plain colors in the same color space
+    pub fn validate(&self) -> Result<(), String> {
+        let first = self
+            .stops
+            .first()
+            .ok_or_else(|| "shading has no color stops".to_string())?;
+
+        for stop in self.stops.iter() {
+            if stop.color.get_cie_color_space().is_none()
+                && stop.color.get_device_color_space().is_none()
+            {
+                return Err(format!(
+                    "shading color stop at offset {} is not a plain color",
+                    stop.offset
+                ));
+            }
+
+            if color_space_of(&stop.color) != color_space_of(&first.color) {
+                return Err(format!(
+                    "shading color stop at offset {} is in a different color space than the first stop",
+                    stop.offset
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stops sorted by offset, with additional stops at 0.0 and 1.0
+    /// if the first or last stop doesn't start / end the gradient
+    fn get_normalized_stops(&self) -> Vec<ColorStop> {
+        let mut stops = self
+            .stops
+            .iter()
+            .map(|s| ColorStop::new(s.offset.clamp(0.0, 1.0), s.color.clone()))
+            .collect::<Vec<_>>();
+
+        stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+
+        if let Some(first) = stops.first().cloned() {
+            if first.offset > 0.0 {
+                stops.insert(0, ColorStop::new(0.0, first.color));
+            }
+        }
+
+        if let Some(last) = stops.last().cloned() {
+            if last.offset < 1.0 {
+                stops.push(ColorStop::new(1.0, last.color));
+            }
+        }
+
+        stops
+    }
+}
+
+/// Returns the color space of the gradient colors
+fn color_space_of(color: &Color) -> lopdf::Object {
+    if let Some(cs) = color.get_cie_color_space() {
+        return cs.to_object();
+    }
+
+    Name(color.get_device_color_space().unwrap_or("DeviceRGB").into())
+}
+
+/// Builds the color function of the shading: a single interpolation function for
+/// two color stops, a stitching function for multiple stops. Shadings without
+/// stops (rejected by `PdfDocument::add_shading`) are painted black.
+fn stops_to_function(stops: &[ColorStop]) -> PdfFunction {
+    let interpolate = |c0: &Color, c1: &Color| PdfFunction::linear(c0.into_vec(), c1.into_vec());
+
+    match stops {
+        [] => PdfFunction::linear(vec![0.0; 3], vec![0.0; 3]),
+        [single] => interpolate(&single.color, &single.color),
+        [start, end] => interpolate(&start.color, &end.color),
+        _ => {
+            let functions = stops
+                .windows(2)
+                .map(|w| interpolate(&w[0].color, &w[1].color))
+                .collect::<Vec<_>>();
+
+            let bounds = stops[1..stops.len() - 1]
+                .iter()
+                .map(|s| s.offset)
+                .collect::<Vec<_>>();
+
+            let encode = functions.iter().flat_map(|_| [0.0, 1.0]).collect();
+
+            PdfFunction::Stitching {
+                domain: [0.0, 1.0],
+                functions,
+                bounds,
+                encode,
+            }
+        }
+    }
+}
+
+/// Serializes the shading into a shading dictionary
+pub(crate) fn shading_to_dict(shading: &Shading, doc: &mut lopdf::Document) -> lopdf::Dictionary {
+    let stops = shading.get_normalized_stops();
+
+    let coords = match &shading.shading_type {
+        ShadingType::Axial { start, end } => vec![start.x.0, start.y.0, end.x.0, end.y.0],
+        ShadingType::Radial {
+            start,
+            start_radius,
+            end,
+            end_radius,
+        } => vec![
+            start.x.0,
+            start.y.0,
+            start_radius.0,
+            end.x.0,
+            end.y.0,
+            end_radius.0,
+        ],
+    };
+
+    lopdf::Dictionary::from_iter(vec![
+        ("ShadingType", Integer(shading.shading_type.get_id())),
+        (
+            "ColorSpace",
+            stops
+                .first()
+                .map(|s| color_space_of(&s.color))
+                .unwrap_or(Name("DeviceRGB".into())),
+        ),
+        ("Coords", Array(coords.into_iter().map(Real).collect())),
+        (
+            "Extend",
+            Array(vec![Boolean(shading.extend.0), Boolean(shading.extend.1)]),
+        ),
+        ("AntiAlias", Boolean(shading.anti_alias)),
+        ("Function", stops_to_function(&stops).to_object(doc)),
+    ])
+}
+
+#[test]
+fn test_shading_validate() {
+    use crate::{
+        color::{Cmyk, Rgb},
+        units::Mm,
+    };
+
+    let axial = |stops| {
+        Shading::axial(
+            Point::new(Mm(0.0), Mm(0.0)),
+            Point::new(Mm(10.0), Mm(0.0)),
+            stops,
+        )
+    };
+    let red = Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None));
+    let blue = Color::Rgb(Rgb::new(0.0, 0.0, 1.0, None));
+    let cyan = Color::Cmyk(Cmyk::new(1.0, 0.0, 0.0, 0.0, None));
+
+    let stops = vec![ColorStop::new(0.0, red.clone()), ColorStop::new(1.0, blue)];
+    assert!(axial(stops).validate().is_ok());
+
+    assert!(axial(Vec::new()).validate().is_err());
+
+    let mixed = vec![ColorStop::new(0.0, red), ColorStop::new(1.0, cyan)];
+    assert!(axial(mixed).validate().is_err());
+}
+
+#[test]
+fn test_stops_to_function() {
+    use crate::{color::Rgb, units::Mm};
+
+    let gray = |v: f32| Color::Rgb(Rgb::new(v, v, v, None));
+    let stops = Shading::axial(
+        Point::new(Mm(0.0), Mm(0.0)),
+        Point::new(Mm(10.0), Mm(0.0)),
+        vec![
+            ColorStop::new(0.5, gray(0.5)),
+            ColorStop::new(0.25, gray(0.0)),
+            ColorStop::new(0.75, gray(1.0)),
+        ],
+    )
+    .get_normalized_stops();
+
+    // padded to the full domain and sorted by offset
+    let offsets = stops.iter().map(|s| s.offset).collect::<Vec<_>>();
+    assert_eq!(offsets, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+
+    match stops_to_function(&stops) {
+        PdfFunction::Stitching {
+            functions,
+            bounds,
+            encode,
+            ..
+        } => {
+            assert_eq!(functions.len(), 4);
+            assert_eq!(bounds, vec![0.25, 0.5, 0.75]);
+            assert_eq!(encode.len(), 8);
+        }
+        f => panic!("expected stitching function, got {f:?}"),
+    }
+
+    assert_eq!(
+        stops_to_function(&stops[1..3]),
+        PdfFunction::linear(vec![0.0; 3], vec![0.5; 3])
+    );
+    assert_eq!(
+        stops_to_function(&[]),
+        PdfFunction::linear(vec![0.0; 3], vec![0.0; 3])
+    );
+}
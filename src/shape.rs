@@ -0,0 +1,369 @@
+//! Text shaping with the OpenType layout tables (GSUB / GPOS) of a font
+
+use crate::{
+    bidi::{get_mirrored_char, get_visual_runs},
+    ParsedFont, Pt,
+};
+use allsorts::{
+    binary::read::ReadScope,
+    font::MatchingPresentation,
+    font_data::FontData,
+    glyph_position::{GlyphLayout, TextDirection as LayoutDirection},
+    gsub::{FeatureInfo, FeatureMask, Features},
+    tables::FontTableProvider,
+};
+
+/// Features that are applied when the default features are combined with
+/// features that have to be enabled by tag (i.e. stylistic sets)
+const DEFAULT_FEATURES: &[&str] = &[
+    "ccmp", "locl", "rlig", "liga", "clig", "calt", "rclt", "curs", "dist", "mark", "mkmk",
+];
+
+/// Options for shaping text: script, language and the OpenType features to apply
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextShapingOptions {
+    /// OpenType script tag (`"latn"`, `"cyrl"`, ...), detected from the text if `None`
+    pub script: Option<String>,
+    /// OpenType language system tag (`"ENG "`, `"TRK "`, ...), the default language
+    /// system of the script is used if `None`
+    pub language: Option<String>,
+    /// Features to turn on or off in addition to the default features,
+    /// i.e. `("liga", false)` or `("smcp", true)`
+    pub features: Vec<(String, bool)>,
+    /// Whether to apply kerning from the GPOS or kern table (default: true)
+    pub kerning: bool,
+    /// Base direction of the text, detected from the first letter if `None`
+    pub direction: Option<TextDirection>,
+    /// Shape the text for vertical writing (`WritingMode::Vertical`): applies the
+    /// vertical alternates (`vert`) and uses the vertical advances of the glyphs
+    pub vertical: bool,
+}
+
+/// Base direction of a text: determines the order of runs with different directions
+/// and the direction of neutral characters (spaces, punctuation) between them
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+impl Default for TextShapingOptions {
+    fn default() -> Self {
+        Self {
+            script: None,
+            language: None,
+            features: Vec::new(),
+            kerning: true,
+            direction: None,
+            vertical: false,
+        }
+    }
+}
+
+impl TextShapingOptions {
+    #[inline]
+    pub fn with_script(mut self, script: &str) -> Self {
+        self.script = Some(script.to_string());
+        self
+    }
+
+    #[inline]
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    #[inline]
+    pub fn with_feature(mut self, feature: &str, enabled: bool) -> Self {
+        self.features.push((feature.to_string(), enabled));
+        self
+    }
+
+    #[inline]
+    pub fn with_kerning(mut self, kerning: bool) -> Self {
+        self.kerning = kerning;
+        self
+    }
+
+    #[inline]
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    #[inline]
+    pub fn with_vertical(mut self, vertical: bool) -> Self {
+        self.vertical = vertical;
+        self
+    }
+
+    fn get_features(&self) -> Features {
+        if self.features.is_empty() && !self.vertical {
+            return Features::Mask(FeatureMask::default());
+        }
+
+        let is_enabled = |feature: &str| {
+            self.features
+                .iter()
+                .rev()
+                .find(|(f, _)| f == feature)
+                .map(|(_, enabled)| *enabled)
+        };
+
+        let mut tags = DEFAULT_FEATURES
+            .iter()
+            .copied()
+            .filter(|f| is_enabled(f) != Some(false))
+            .collect::<Vec<_>>();
+        if self.kerning && is_enabled("kern") != Some(false) {
+            tags.push("kern");
+        }
+        if self.vertical {
+            tags.extend(
+                ["vert", "vrt2", "vkrn"]
+                    .into_iter()
+                    .filter(|f| is_enabled(f) != Some(false))
+                    .filter(|f| *f != "vkrn" || self.kerning),
+            );
+        }
+        for (feature, _) in self.features.iter() {
+            if is_enabled(feature) == Some(true) && !tags.contains(&feature.as_str()) {
+                tags.push(feature);
+            }
+        }
+
+        Features::Custom(
+            tags.into_iter()
+                .filter_map(|f| allsorts::tag::from_string(f).ok())
+                .map(|feature_tag| FeatureInfo {
+                    feature_tag,
+                    alternate: None,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Text that has been shaped with a specific font, ready to be written with `Op::WriteShapedText`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedText {
+    /// Glyphs in visual order (left to right)
+    pub glyphs: Vec<ShapedGlyph>,
+    pub units_per_em: u16,
+    /// Original text if the glyphs are not in the logical order of the text (right-to-left
+    /// runs), written as `/ActualText` so that copying the text yields the logical order
+    pub actual_text: Option<String>,
+    /// Whether the text was shaped for vertical writing, the advances are then
+    /// vertical advances (top to bottom)
+    pub vertical: bool,
+}
+
+/// Glyph of a shaped text, all distances are in font units
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedGlyph {
+    /// Glyph ID in the font that the text was shaped with
+    pub glyph_id: u16,
+    /// Text that the glyph represents, i.e. `"ffi"` for a ligature. Empty if the glyph is
+    /// not the first glyph of a character that was decomposed into multiple glyphs
+    pub text: String,
+    /// Distance to advance the pen after drawing the glyph (downwards for vertical text)
+    pub advance: i32,
+    /// Horizontal offset of the glyph from the pen position
+    pub x_offset: i32,
+    /// Vertical offset of the glyph from the baseline (i.e. for stacked marks)
+    pub y_offset: i32,
+}
+
+impl ShapedText {
+    /// Returns the total advance of the text when written at the given font size
+    /// (the height for vertical text)
+    pub fn get_width(&self, size: Pt) -> Pt {
+        let advance = self.glyphs.iter().map(|g| g.advance as f32).sum::<f32>();
+        Pt(advance * size.0 / self.units_per_em.max(1) as f32)
+    }
+
+    /// Returns the text that the glyphs represent (in logical order)
+    pub fn get_text(&self) -> String {
+        match self.actual_text.as_ref() {
+            Some(text) => text.clone(),
+            None => self.glyphs.iter().map(|g| g.text.as_str()).collect(),
+        }
+    }
+}
+
+impl ParsedFont {
+    /// Shapes the text with the GSUB and GPOS tables of the font (ligatures, contextual
+    /// forms, reordering, kerning, mark positioning). The text is split into runs of the
+    /// same direction and script, which are shaped separately and placed in visual order.
+    /// Returns an error if the font can't be parsed.
+    pub fn shape_text(
+        &self,
+        text: &str,
+        options: &TextShapingOptions,
+    ) -> Result<ShapedText, String> {
+        let scope = ReadScope::new(&self.original_bytes);
+        let font_file = scope.read::<FontData<'_>>().map_err(|e| e.to_string())?;
+        let provider = font_file
+            .table_provider(self.original_index)
+            .map_err(|e| e.to_string())?;
+        let mut font = allsorts::font::Font::new(provider).map_err(|e| e.to_string())?;
+
+        let language = match options.language.as_deref() {
+            Some(l) => Some(allsorts::tag::from_string(l).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let features = options.get_features();
+        let base_level = options.direction.map(|d| match d {
+            TextDirection::LeftToRight => 0,
+            TextDirection::RightToLeft => 1,
+        });
+
+        let runs = get_visual_runs(text, base_level);
+        let mut glyphs = Vec::new();
+        for run in runs.iter() {
+            let mut segments = split_by_script(&text[run.range.clone()]);
+            if run.is_rtl() {
+                segments.reverse();
+            }
+            for segment in segments {
+                let script = options
+                    .script
+                    .as_deref()
+                    .unwrap_or_else(|| detect_script(segment));
+                let script = allsorts::tag::from_string(script).map_err(|e| e.to_string())?;
+                glyphs.extend(shape_run(
+                    &mut font,
+                    segment,
+                    script,
+                    language,
+                    &features,
+                    options,
+                    run.is_rtl(),
+                )?);
+            }
+        }
+
+        Ok(ShapedText {
+            glyphs,
+            units_per_em: self.font_metrics.units_per_em,
+            actual_text: runs.iter().any(|r| r.is_rtl()).then(|| text.to_string()),
+            vertical: options.vertical,
+        })
+    }
+}
+
+/// Shapes a run of text with a single script and direction, returns the glyphs in visual order
+fn shape_run<T: FontTableProvider>(
+    font: &mut allsorts::font::Font<T>,
+    text: &str,
+    script: u32,
+    language: Option<u32>,
+    features: &Features,
+    options: &TextShapingOptions,
+    rtl: bool,
+) -> Result<Vec<ShapedGlyph>, String> {
+    // characters like brackets are displayed mirrored in right-to-left runs
+    let text = if rtl {
+        text.chars().map(get_mirrored_char).collect()
+    } else {
+        text.to_string()
+    };
+
+    let glyphs = font.map_glyphs(&text, script, MatchingPresentation::NotRequired);
+    let infos = font
+        .shape(glyphs, script, language, features, None, options.kerning)
+        // shaping errors still return the glyphs that could be processed
+        .unwrap_or_else(|(_, infos)| infos);
+
+    let direction = if rtl {
+        LayoutDirection::RightToLeft
+    } else {
+        LayoutDirection::LeftToRight
+    };
+    let mut layout = GlyphLayout::new(font, &infos, direction, options.vertical);
+    let positions = layout.glyph_positions().map_err(|e| e.to_string())?;
+
+    let mut last_text = String::new();
+    let mut glyphs = infos
+        .iter()
+        .zip(positions.iter())
+        .map(|(info, pos)| {
+            let text = info.glyph.unicodes.iter().collect::<String>();
+            // a decomposed character yields multiple glyphs with the same characters,
+            // only the first one maps back to the text
+            let is_continuation = info.glyph.multi_subst_dup() && text == last_text;
+            last_text = text.clone();
+            ShapedGlyph {
+                glyph_id: info.glyph.glyph_index,
+                text: if is_continuation { String::new() } else { text },
+                advance: if options.vertical {
+                    pos.vert_advance
+                } else {
+                    pos.hori_advance
+                },
+                x_offset: pos.x_offset,
+                y_offset: pos.y_offset,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if rtl {
+        glyphs.reverse();
+    }
+
+    Ok(glyphs)
+}
+
+/// Splits the text into segments of the same script, characters without
+/// a specific script (spaces, digits, punctuation) belong to the preceding segment
+fn split_by_script(text: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut current = None;
+    for (pos, c) in text.char_indices() {
+        let Some(script) = get_char_script(c) else {
+            continue;
+        };
+        if current.is_some_and(|s| s != script) {
+            segments.push(&text[start..pos]);
+            start = pos;
+        }
+        current = Some(script);
+    }
+    segments.push(&text[start..]);
+    segments
+}
+
+/// Returns the OpenType script tag of the first character in the text that
+/// belongs to a specific script, `"DFLT"` for text without such characters
+pub fn detect_script(text: &str) -> &'static str {
+    text.chars().find_map(get_char_script).unwrap_or("DFLT")
+}
+
+fn get_char_script(c: char) -> Option<&'static str> {
+    Some(match c as u32 {
+        0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => "latn",
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => "grek",
+        0x0400..=0x052F => "cyrl",
+        0x0590..=0x05FF | 0xFB1D..=0xFB4F => "hebr",
+        0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => "arab",
+        0x0900..=0x097F => "deva",
+        0x0E00..=0x0E7F => "thai",
+        0x1100..=0x11FF | 0xAC00..=0xD7AF => "hang",
+        0x3040..=0x30FF => "kana",
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF => "hani",
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_detect_script() {
+    assert_eq!(detect_script("Hello"), "latn");
+    assert_eq!(detect_script("123 Привет"), "cyrl");
+    assert_eq!(detect_script("«שלום»"), "hebr");
+    assert_eq!(detect_script("123"), "DFLT");
+    assert_eq!(
+        split_by_script("Total: ₹ 100 कुल राशि"),
+        vec!["Total: ₹ 100 ", "कुल राशि"]
+    );
+}
@@ -0,0 +1,410 @@
+//! Conversion of stroked lines into filled outlines
+
+use crate::{
+    boolean::{flatten_rings, DEFAULT_FLATTEN_TOLERANCE},
+    graphics::{
+        Line, LineCapStyle, LineDashPattern, LineJoinStyle, PaintMode, Point, Polygon, WindingOrder,
+    },
+    units::Pt,
+};
+use std::f64::consts::PI;
+
+/// Stroke parameters, mirroring the stroke state of a PDF page
+/// (`SetOutlineThickness`, `SetLineJoinStyle`, `SetLineCapStyle`,
+/// `SetLineDashPattern` and the miter limit of the graphics state)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StrokeStyle {
+    /// Line width. Note that a width of 0 (a "hairline", the thinnest line the
+    /// output device can render) has no outline, use the minimum width instead.
+    pub width: Pt,
+    pub join: LineJoinStyle,
+    pub cap: LineCapStyle,
+    /// Maximum ratio of the miter length to the line width, longer miters are beveled
+    pub miter_limit: f32,
+    /// Dash pattern, `None` for a solid line
+    pub dash: Option<LineDashPattern>,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            width: Pt(1.0),
+            join: LineJoinStyle::Miter,
+            cap: LineCapStyle::Butt,
+            miter_limit: 10.0,
+            dash: None,
+        }
+    }
+}
+
+impl StrokeStyle {
+    pub fn new(width: Pt) -> Self {
+        Self {
+            width,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn with_join(mut self, join: LineJoinStyle) -> Self {
+        self.join = join;
+        self
+    }
+
+    #[inline]
+    pub fn with_cap(mut self, cap: LineCapStyle) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    #[inline]
+    pub fn with_miter_limit(mut self, miter_limit: f32) -> Self {
+        self.miter_limit = miter_limit;
+        self
+    }
+
+    #[inline]
+    pub fn with_dash(mut self, dash: LineDashPattern) -> Self {
+        self.dash = Some(dash);
+        self
+    }
+}
+
+impl Line {
+    /// Returns the area covered by stroking the line with the given style as a filled
+    /// polygon (i.e. for cutting plotters or to replace hairlines by fills).
+    ///
+    /// Bezier curves are flattened, the outline only consists of straight line segments.
+    pub fn to_outline(&self, style: &StrokeStyle) -> Polygon {
+        let half_width = style.width.0 as f64 / 2.0;
+        let tolerance = DEFAULT_FLATTEN_TOLERANCE.0 as f64;
+
+        let mut points = flatten_rings(&[self.points.clone()], tolerance)
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        points.dedup_by(|a, b| distance(*a, *b) < 1e-9);
+
+        if self.is_closed
+            && points.len() > 1
+            && distance(points[0], points[points.len() - 1]) > 1e-9
+        {
+            points.push(points[0]);
+        }
+
+        let pieces = match style.dash.as_ref() {
+            Some(dash) => dash_polyline(&points, dash),
+            None => vec![points.clone()],
+        };
+        let closed = self.is_closed && style.dash.is_none();
+
+        let mut shapes = Vec::new();
+        if half_width > 0.0 {
+            for piece in pieces.iter() {
+                stroke_polyline(piece, closed, half_width, style, &mut shapes);
+            }
+        }
+
+        // every shape is counter-clockwise: resolving the overlaps with
+        // the nonzero winding rule merges them into one outline
+        Polygon {
+            rings: shapes
+                .into_iter()
+                .map(|shape| {
+                    shape
+                        .into_iter()
+                        .map(|(x, y)| {
+                            let point = Point {
+                                x: Pt(x as f32),
+                                y: Pt(y as f32),
+                            };
+                            (point, false)
+                        })
+                        .collect()
+                })
+                .collect(),
+            mode: PaintMode::Fill,
+            winding_order: WindingOrder::NonZero,
+        }
+        .simplify()
+    }
+}
+
+/// Adds the shapes (segment rectangles, joins and caps) covered by the stroke of a polyline
+fn stroke_polyline(
+    points: &[(f64, f64)],
+    closed: bool,
+    half_width: f64,
+    style: &StrokeStyle,
+    shapes: &mut Vec<Vec<(f64, f64)>>,
+) {
+    let tolerance = DEFAULT_FLATTEN_TOLERANCE.0 as f64;
+
+    match points {
+        [] => return,
+        [dot] => {
+            // zero-length dashes are only visible with round caps
+            if style.cap == LineCapStyle::Round {
+                shapes.push(circle(*dot, half_width, tolerance));
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let last = points.len() - 1;
+    for (i, w) in points.windows(2).enumerate() {
+        let (p, q) = (w[0], w[1]);
+        let d = direction(p, q);
+        let n = (-d.1 * half_width, d.0 * half_width);
+
+        // projecting square caps extend the first and last segment
+        let square = style.cap == LineCapStyle::ProjectingSquare && !closed;
+        let p = if square && i == 0 {
+            (p.0 - d.0 * half_width, p.1 - d.1 * half_width)
+        } else {
+            p
+        };
+        let q = if square && i + 1 == last {
+            (q.0 + d.0 * half_width, q.1 + d.1 * half_width)
+        } else {
+            q
+        };
+
+        shapes.push(vec![
+            (p.0 - n.0, p.1 - n.1),
+            (q.0 - n.0, q.1 - n.1),
+            (q.0 + n.0, q.1 + n.1),
+            (p.0 + n.0, p.1 + n.1),
+        ]);
+    }
+
+    // joins between segments (at every vertex for closed lines)
+    let joins = if closed {
+        1..=last
+    } else {
+        1..=last.saturating_sub(1)
+    };
+    for i in joins {
+        let prev = points[i - 1];
+        let vertex = points[i];
+        let next = if i == last { points[1] } else { points[i + 1] };
+        add_join(prev, vertex, next, half_width, style, tolerance, shapes);
+    }
+
+    if !closed && style.cap == LineCapStyle::Round {
+        shapes.push(circle(points[0], half_width, tolerance));
+        shapes.push(circle(points[last], half_width, tolerance));
+    }
+}
+
+/// Adds the join between the segments `prev -> vertex` and `vertex -> next`
+fn add_join(
+    prev: (f64, f64),
+    vertex: (f64, f64),
+    next: (f64, f64),
+    half_width: f64,
+    style: &StrokeStyle,
+    tolerance: f64,
+    shapes: &mut Vec<Vec<(f64, f64)>>,
+) {
+    let d1 = direction(prev, vertex);
+    let d2 = direction(vertex, next);
+    let turn = d1.0 * d2.1 - d1.1 * d2.0;
+    if turn.abs() < 1e-9 && d1.0 * d2.0 + d1.1 * d2.1 > 0.0 {
+        return; // straight continuation
+    }
+
+    if style.join == LineJoinStyle::Round {
+        shapes.push(circle(vertex, half_width, tolerance));
+        return;
+    }
+
+    // the gap is on the outer side of the turn
+    let sign = if turn > 0.0 { -1.0 } else { 1.0 };
+    let n1 = (-d1.1 * half_width * sign, d1.0 * half_width * sign);
+    let n2 = (-d2.1 * half_width * sign, d2.0 * half_width * sign);
+    let a = (vertex.0 + n1.0, vertex.1 + n1.1);
+    let b = (vertex.0 + n2.0, vertex.1 + n2.1);
+
+    // miter length / line width = 1 / sin(angle between the segments / 2)
+    let cos_angle = -(d1.0 * d2.0 + d1.1 * d2.1);
+    let sin_half = ((1.0 - cos_angle) / 2.0).max(0.0).sqrt();
+    let use_miter = style.join == LineJoinStyle::Miter
+        && sin_half > 1e-9
+        && 1.0 / sin_half <= style.miter_limit as f64;
+
+    let mut shape = if use_miter {
+        // the miter tip is on the bisector of the outer normals
+        let m = (n1.0 + n2.0, n1.1 + n2.1);
+        let m_len = (m.0 * m.0 + m.1 * m.1).sqrt();
+        let tip_dist = half_width / sin_half;
+        let tip = (
+            vertex.0 + m.0 / m_len * tip_dist,
+            vertex.1 + m.1 / m_len * tip_dist,
+        );
+        vec![vertex, a, tip, b]
+    } else {
+        vec![vertex, a, b]
+    };
+
+    make_counter_clockwise(&mut shape);
+    shapes.push(shape);
+}
+
+/// Splits the polyline into the "on" pieces of the dash pattern
+fn dash_polyline(points: &[(f64, f64)], dash: &LineDashPattern) -> Vec<Vec<(f64, f64)>> {
+    let mut pattern = dash
+        .as_array()
+        .into_iter()
+        .map(|d| d.max(0) as f64)
+        .collect::<Vec<_>>();
+    if pattern.iter().sum::<f64>() <= 0.0 {
+        return vec![points.to_vec()];
+    }
+    if pattern.len() % 2 == 1 {
+        // odd number of elements: the pattern is repeated with dashes and gaps swapped
+        pattern.extend(pattern.clone());
+    }
+
+    // apply the phase
+    let total = pattern.iter().sum::<f64>();
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut phase = (dash.offset.max(0) as f64) % total;
+    while phase > 0.0 {
+        if phase < remaining {
+            remaining -= phase;
+            break;
+        }
+        phase -= remaining;
+        index = (index + 1) % pattern.len();
+        remaining = pattern[index];
+    }
+
+    let mut pieces = Vec::new();
+    let mut current = Vec::new();
+    let is_on = |index: usize| index % 2 == 0;
+    if is_on(index) {
+        if let Some(first) = points.first() {
+            current.push(*first);
+        }
+    }
+
+    for w in points.windows(2) {
+        let (mut p, q) = (w[0], w[1]);
+        let mut segment_len = distance(p, q);
+
+        while segment_len >= remaining {
+            let d = direction(p, q);
+            p = (p.0 + d.0 * remaining, p.1 + d.1 * remaining);
+            segment_len -= remaining;
+
+            if is_on(index) {
+                current.push(p);
+                pieces.push(std::mem::take(&mut current));
+            } else {
+                current.push(p);
+            }
+
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+
+        remaining -= segment_len;
+        if is_on(index) {
+            current.push(q);
+        }
+    }
+
+    if is_on(index) && !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+        .into_iter()
+        .map(|mut piece| {
+            piece.dedup_by(|a, b| distance(*a, *b) < 1e-9);
+            piece
+        })
+        .collect()
+}
+
+/// Polygon approximating a circle, counter-clockwise
+fn circle(center: (f64, f64), radius: f64, tolerance: f64) -> Vec<(f64, f64)> {
+    let step = 2.0 * (1.0 - (tolerance / radius).min(1.0)).acos();
+    let count = ((2.0 * PI / step).ceil() as usize).clamp(8, 256);
+    (0..count)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / count as f64;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn make_counter_clockwise(shape: &mut [(f64, f64)]) {
+    let area = (0..shape.len())
+        .map(|i| {
+            let (p, q) = (shape[i], shape[(i + 1) % shape.len()]);
+            p.0 * q.1 - q.0 * p.1
+        })
+        .sum::<f64>();
+    if area < 0.0 {
+        shape.reverse();
+    }
+}
+
+fn distance(p: (f64, f64), q: (f64, f64)) -> f64 {
+    ((q.0 - p.0).powi(2) + (q.1 - p.1).powi(2)).sqrt()
+}
+
+/// Unit vector from `p` to `q`
+fn direction(p: (f64, f64), q: (f64, f64)) -> (f64, f64) {
+    let len = distance(p, q);
+    ((q.0 - p.0) / len, (q.1 - p.1) / len)
+}
+
+#[test]
+fn test_stroke_to_outline() {
+    let pt = |x: f32, y: f32| (Point { x: Pt(x), y: Pt(y) }, false);
+    let line = Line {
+        points: vec![pt(0.0, 0.0), pt(10.0, 0.0)],
+        is_closed: false,
+    };
+
+    let butt = line.to_outline(&StrokeStyle::new(Pt(2.0)));
+    assert!((butt.signed_area() - 20.0).abs() < 0.01);
+
+    let square = StrokeStyle::new(Pt(2.0)).with_cap(LineCapStyle::ProjectingSquare);
+    assert!((line.to_outline(&square).signed_area() - 24.0).abs() < 0.01);
+
+    // the round caps are flattened with the default tolerance
+    let round = StrokeStyle::new(Pt(2.0)).with_cap(LineCapStyle::Round);
+    assert!((line.to_outline(&round).signed_area() - (20.0 + std::f32::consts::PI)).abs() < 0.25);
+
+    // closed square with miter joins: 12 x 12 outside, 8 x 8 hole
+    let frame = Line {
+        points: vec![pt(0.0, 0.0), pt(10.0, 0.0), pt(10.0, 10.0), pt(0.0, 10.0)],
+        is_closed: true,
+    };
+    let outline = frame.to_outline(&StrokeStyle::new(Pt(2.0)));
+    assert_eq!(outline.rings.len(), 2);
+    assert!((outline.signed_area() - 80.0).abs() < 0.01);
+
+    // bevel joins cut off the corners
+    let bevel = StrokeStyle::new(Pt(2.0)).with_join(LineJoinStyle::Limit);
+    assert!((frame.to_outline(&bevel).signed_area() - 78.0).abs() < 0.01);
+
+    // 2 on, 3 off: dashes at 0..2, 5..7
+    let dashed = StrokeStyle::new(Pt(2.0)).with_dash(LineDashPattern {
+        dash_1: Some(2),
+        gap_1: Some(3),
+        ..Default::default()
+    });
+    let outline = line.to_outline(&dashed);
+    assert_eq!(outline.rings.len(), 2);
+    assert!((outline.signed_area() - 8.0).abs() < 0.01);
+}
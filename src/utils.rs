@@ -137,6 +137,18 @@ pub(crate) fn rgba_to_rgb16(data: Vec<u16>) -> (Vec<u16>, Vec<u16>) {
     (rgb, alpha)
 }
 
+/// Takes a Vec of CMYKA samples (8 or 16 bit) and returns two Vecs of CMYK and alpha samples
+pub(crate) fn cmyka_to_cmyk<T: Copy>(data: Vec<T>) -> (Vec<T>, Vec<T>) {
+    let mut cmyk = Vec::with_capacity(data.len() / 5 * 4);
+    let mut alpha = Vec::with_capacity(data.len() / 5);
+    for px in data.chunks_exact(5) {
+        cmyk.extend_from_slice(&px[..4]);
+        alpha.push(px[4]);
+    }
+
+    (cmyk, alpha)
+}
+
 pub(crate) fn rgba_to_rgbf32(data: Vec<f32>) -> (Vec<f32>, Vec<f32>) {
     let mut rgb = Vec::with_capacity(data.len() / 4 * 3);
     let mut alpha = Vec::with_capacity(data.len() / 4);
@@ -157,3 +169,52 @@ pub(crate) fn u16vec_to_u8(data: Vec<u16>) -> Vec<u8> {
 pub(crate) fn f32vec_to_u8(data: Vec<f32>) -> Vec<u8> {
     data.iter().flat_map(|us| us.to_be_bytes()).collect()
 }
+
+/// Compresses the bytes as a zlib stream (`/FlateDecode`)
+pub(crate) fn compress_zlib(bytes: &[u8]) -> Vec<u8> {
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::prelude::*;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    let _ = encoder.write_all(bytes);
+    encoder.finish().unwrap_or_default()
+}
+
+/// Decompresses a zlib stream, returns `None` if the stream is invalid
+pub(crate) fn uncompress_zlib(bytes: &[u8]) -> Option<Vec<u8>> {
+    use flate2::read::ZlibDecoder;
+    let mut decoder = ZlibDecoder::new(bytes);
+    let mut s = Vec::<u8>::new();
+    decoder.read_to_end(&mut s).ok()?;
+    Some(s)
+}
+
+/// Encodes the bytes as ASCII base-85 (`/ASCII85Decode`), including the `~>` EOD marker
+pub(crate) fn ascii85_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() / 4 * 5 + 7);
+    for chunk in bytes.chunks(4) {
+        let mut group = [0_u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        if value == 0 && chunk.len() == 4 {
+            out.push(b'z');
+            continue;
+        }
+        let mut encoded = [0_u8; 5];
+        for c in encoded.iter_mut().rev() {
+            *c = (value % 85) as u8 + b'!';
+            value /= 85;
+        }
+        // a partial group of n bytes is written as n + 1 characters
+        out.extend_from_slice(&encoded[..chunk.len() + 1]);
+    }
+    out.extend_from_slice(b"~>");
+    out
+}
+
+#[test]
+fn test_ascii85_encode() {
+    assert_eq!(ascii85_encode(b"Man "), b"9jqo^~>".to_vec());
+    assert_eq!(ascii85_encode(&[0, 0, 0, 0, 1]), b"z!<~>".to_vec());
+    assert_eq!(ascii85_encode(b""), b"~>".to_vec());
+}
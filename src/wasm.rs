@@ -103,7 +103,19 @@ fn printpdf_from_xml_internal(
             error: e,
         })?;
 
-    let pdf = pdf.with_pages(pages).save(&PdfSaveOptions::default());
+    let save_opts = PdfSaveOptions {
+        compress_images: !input.options.dont_compress_images.unwrap_or(false),
+        ..Default::default()
+    };
+
+    let pdf = pdf
+        .with_pages(pages)
+        .save(&save_opts)
+        .map_err(|e| PrintPdfApiReturn {
+            pdf: String::new(),
+            status: 3,
+            error: e,
+        })?;
 
     Ok(PrintPdfApiReturn {
         pdf: BASE64_STANDARD.encode(pdf),
@@ -1,9 +1,13 @@
 use crate::{
+    color::{Color, ColorSpace},
+    graphics::Rect,
     image::RawImage,
     matrix::CurTransMat,
+    ops::Op,
     units::{Pt, Px},
-    OffsetDateTime,
+    OffsetDateTime, PdfConformance, PdfDocument, PdfSaveOptions, XObjectId,
 };
+use serde_derive::{Deserialize, Serialize};
 
 /* Parent: Resources dictionary of the page */
 /// External object that gets reference outside the PDF content stream
@@ -30,9 +34,58 @@ pub enum XObject {
 }
 
 impl XObject {
+    /// Returns the operations that place the XObject into the `rect`, clipped to the
+    /// `rect` if necessary. Form XObjects without a `size` are placed according to
+    /// their `bbox`. Returns no operations if the size of the XObject is unknown.
+    pub fn get_placement_ops(
+        &self,
+        id: &XObjectId,
+        rect: &Rect,
+        fit: XObjectFit,
+        align: XObjectAlign,
+    ) -> Vec<Op> {
+        let transform = match (self, self.get_width_height()) {
+            (XObject::Image(im), Some(wh)) => {
+                XObjectTransform::fit_into(wh, im.dpi, rect, fit, align)
+            }
+            (_, Some(wh)) => XObjectTransform::fit_into(wh, None, rect, fit, align),
+            // forms are drawn in form space, scale the bounding box instead of the unit square
+            (XObject::Form(form), None) => {
+                let Some(bbox) = form.bbox else {
+                    return Vec::new();
+                };
+                let (width, height) = fit.get_size((bbox.width, bbox.height), rect);
+                let (x, y) = align.get_position((width, height), rect);
+                let scale_x = width.0 / bbox.width.0.max(f32::EPSILON);
+                let scale_y = height.0 / bbox.height.0.max(f32::EPSILON);
+                XObjectTransform {
+                    translate_x: Some(x - bbox.x * scale_x),
+                    translate_y: Some(y - bbox.y * scale_y),
+                    scale_x: Some(scale_x),
+                    scale_y: Some(scale_y),
+                    ..Default::default()
+                }
+            }
+            (_, None) => return Vec::new(),
+        };
+
+        let ops = vec![Op::UseXObject {
+            id: id.clone(),
+            transform,
+        }];
+        if fit.needs_clipping() {
+            Op::clipped_to_rect(rect, ops)
+        } else {
+            ops
+        }
+    }
+
     pub fn get_width_height(&self) -> Option<(Px, Px)> {
         match self {
-            XObject::Image(raw_image) => Some((Px(raw_image.width), Px(raw_image.height))),
+            XObject::Image(raw_image) => {
+                let (w, h) = raw_image.get_oriented_size();
+                Some((Px(w), Px(h)))
+            }
             XObject::Form(form_xobject) => form_xobject.size,
             XObject::External(external_xobject) => {
                 Some((external_xobject.width?, external_xobject.height?))
@@ -44,12 +97,14 @@ impl XObject {
 // translates the xobject to a document object ID
 pub(crate) fn add_xobject_to_document(
     xobj: &XObject,
+    conformance: &PdfConformance,
+    opts: &PdfSaveOptions,
     doc: &mut lopdf::Document,
 ) -> lopdf::ObjectId {
     // in the PDF content stream, reference an XObject like this
     match xobj {
         XObject::Image(i) => {
-            let stream = crate::image::image_to_stream(i.clone(), doc);
+            let stream = crate::image::image_to_stream(i.clone(), conformance, opts, doc);
             doc.add_object(stream)
         }
         XObject::Form(f) => {
@@ -86,7 +141,7 @@ pub struct ExternalXObject {
 }
 
 /// Describes the format the image bytes are compressed with.
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, PartialOrd, Copy, Clone, Serialize, Deserialize)]
 pub enum ImageFilter {
     /// ???
     Ascii85,
@@ -98,6 +153,18 @@ pub enum ImageFilter {
     JPX,
 }
 
+impl ImageFilter {
+    /// Returns the name of the `/Filter` for the image stream dictionary
+    pub fn get_filter_name(&self) -> &'static str {
+        match self {
+            ImageFilter::Ascii85 => "ASCII85Decode",
+            ImageFilter::Lzw => "LZWDecode",
+            ImageFilter::DCT => "DCTDecode",
+            ImageFilter::JPX => "JPXDecode",
+        }
+    }
+}
+
 /// __THIS IS NOT A PDF FORM!__ A form `XObject` can be nearly everything.
 /// PDF allows you to reuse content for the graphics stream in a `FormXObject`.
 /// A `FormXObject` is basically a layer-like content stream and can contain anything
@@ -114,6 +181,13 @@ pub struct FormXObject {
     pub size: Option<(Px, Px)>,
     /// The actual content of this FormXObject
     pub bytes: Vec<u8>,
+    /// Operations of this FormXObject, translated into the content stream when
+    /// the PDF is saved (see `FormXObject::from_ops`). If not empty, `bytes`
+    /// and `resources` are ignored.
+    pub ops: Vec<Op>,
+    /* /BBox [Integer, 4] */
+    /// Bounding box of the form in form space, used to clip the form
+    pub bbox: Option<Rect>,
     /* /Matrix [Integer , 6] */
     /// Optional matrix, maps the form into user space
     pub matrix: Option<CurTransMat>,
@@ -197,16 +271,109 @@ pub struct FormXObject {
     pub name: Option<String>,
 }
 
-fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::Stream {
+impl FormXObject {
+    /// Creates a form from the given operations. The operations are translated into the
+    /// content stream when the PDF is saved, the resources dictionary of the form is
+    /// built automatically from the fonts, XObjects, graphics states, shadings and
+    /// patterns referenced by the operations.
+    ///
+    /// Returns an error if an operation references a resource that has not been
+    /// added to `doc`. Register the form with `PdfDocument::add_form`, then place
+    /// it as often as needed with `UseXObject { id }`.
+    pub fn from_ops(ops: Vec<Op>, bbox: Rect, doc: &PdfDocument) -> Result<Self, String> {
+        for op in ops.iter() {
+            let missing = match op {
+                Op::WriteText { font, .. }
+                | Op::WriteCodepoints { font, .. }
+                | Op::WriteCodepointsWithKerning { font, .. }
+                | Op::WriteShapedText { font, .. }
+                | Op::SetFontSize { font, .. }
+                    if !doc.resources.fonts.map.contains_key(font) =>
+                {
+                    Some(format!("font {}", font.0))
+                }
+                Op::UseXObject { id, .. } if !doc.resources.xobjects.map.contains_key(id) => {
+                    Some(format!("XObject {}", id.0))
+                }
+                Op::LoadGraphicsState { gs } if !doc.resources.extgstates.map.contains_key(gs) => {
+                    Some(format!("graphics state {}", gs.0))
+                }
+                Op::PaintShading { shading }
+                    if !doc.resources.shadings.map.contains_key(shading) =>
+                {
+                    Some(format!("shading {}", shading.0))
+                }
+                Op::SetFillColor { col } | Op::SetOutlineColor { col } => match col {
+                    Color::Shading(id) if !doc.resources.shadings.map.contains_key(id) => {
+                        Some(format!("shading {}", id.0))
+                    }
+                    Color::Pattern(id) | Color::UncoloredPattern { pattern: id, .. }
+                        if !doc.resources.patterns.map.contains_key(id) =>
+                    {
+                        Some(format!("pattern {}", id.0))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            if let Some(missing) = missing {
+                return Err(format!("FormXObject references unknown {missing}"));
+            }
+        }
+
+        Ok(Self::with_ops(ops, bbox))
+    }
+
+    /// Creates a transparency group from the given operations: the operations are
+    /// composited into a single result first, to which the opacity, blend mode and
+    /// soft mask of the graphics state at the `UseXObject` operation are applied.
+    ///
+    /// Register it with `PdfDocument::add_form`.
+    pub fn transparency_group(ops: Vec<Op>, bbox: Rect, group: GroupXObject) -> Self {
+        Self {
+            group: Some(group),
+            ..Self::with_ops(ops, bbox)
+        }
+    }
+
+    fn with_ops(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            form_type: FormType::Type1,
+            size: None,
+            bytes: Vec::new(),
+            ops,
+            bbox: Some(bbox),
+            matrix: None,
+            resources: None,
+            group: None,
+            ref_dict: None,
+            metadata: None,
+            piece_info: None,
+            last_modified: None,
+            struct_parent: None,
+            struct_parents: None,
+            opi: None,
+            oc: None,
+            name: None,
+        }
+    }
+}
+
+pub(crate) fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::Stream {
     use lopdf::Object::String as LoString;
     use lopdf::Object::*;
 
     let mut dict = lopdf::Dictionary::from_iter(vec![
         ("Type", Name("XObject".into())),
         ("Subtype", Name("Form".into())),
-        ("FormType", Name(f.form_type.get_id().into())),
+        ("FormType", Integer(f.form_type.get_id())),
     ]);
 
+    if let Some(bbox) = f.bbox.as_ref() {
+        dict.set("BBox", Array(bbox.to_bbox_array()));
+    }
+
     if let Some(matrix) = f.matrix.as_ref() {
         dict.set(
             "Matrix",
@@ -219,12 +386,7 @@ fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::
     }
 
     if let Some(g) = f.group.as_ref() {
-        let group_dict = lopdf::Dictionary::from_iter(vec![
-            ("Type", Name("Group".into())),
-            ("S", Name(g.grouptype.get_id().into())),
-        ]);
-
-        dict.set("Group", Dictionary(group_dict));
+        dict.set("Group", Dictionary(g.to_dict()));
     }
 
     if let Some(r) = f.ref_dict.as_ref() {
@@ -283,9 +445,9 @@ pub enum FormType {
 }
 
 impl FormType {
-    fn get_id(&self) -> &'static str {
+    fn get_id(&self) -> i64 {
         match self {
-            FormType::Type1 => "Type1",
+            FormType::Type1 => 1,
         }
     }
 }
@@ -294,6 +456,78 @@ impl FormType {
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct GroupXObject {
     pub grouptype: GroupXObjectType,
+    /// Color space in which the group is composited (`/CS`), defaults
+    /// to the color space of the parent group or page
+    pub color_space: Option<ColorSpace>,
+    /// Isolated groups are composited onto a fully transparent backdrop
+    /// instead of the content below the group (`/I`)
+    pub isolated: bool,
+    /// In knockout groups, objects overwrite earlier objects of the same group
+    /// instead of being composited with them (`/K`)
+    pub knockout: bool,
+}
+
+impl GroupXObject {
+    /// Creates a non-isolated, non-knockout transparency group
+    pub fn transparency() -> Self {
+        Self {
+            grouptype: GroupXObjectType::TransparencyGroup,
+            color_space: None,
+            isolated: false,
+            knockout: false,
+        }
+    }
+
+    /// Sets the blending color space of the group
+    #[inline]
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = Some(color_space);
+        self
+    }
+
+    /// Sets whether the group is isolated
+    #[inline]
+    pub fn with_isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// Sets whether the group is a knockout group
+    #[inline]
+    pub fn with_knockout(mut self, knockout: bool) -> Self {
+        self.knockout = knockout;
+        self
+    }
+
+    /// Builds the group attributes dictionary
+    pub(crate) fn to_dict(&self) -> lopdf::Dictionary {
+        use lopdf::Object::{Boolean, Name};
+
+        let mut dict = lopdf::Dictionary::from_iter(vec![
+            ("Type", Name("Group".into())),
+            ("S", Name(self.grouptype.get_id().into())),
+        ]);
+
+        if let Some(cs) = self.color_space {
+            // groups can only be blended in a device (or CIE-based) color space
+            let cs = match cs {
+                ColorSpace::Cmyk => "DeviceCMYK",
+                ColorSpace::Greyscale | ColorSpace::GreyscaleAlpha => "DeviceGray",
+                ColorSpace::Rgb | ColorSpace::Rgba | ColorSpace::Palette => "DeviceRGB",
+            };
+            dict.set("CS", Name(cs.into()));
+        }
+
+        if self.isolated {
+            dict.set("I", Boolean(true));
+        }
+
+        if self.knockout {
+            dict.set("K", Boolean(true));
+        }
+
+        dict
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -343,11 +577,38 @@ pub struct XObjectTransform {
     pub rotate: Option<XObjectRotation>,
     pub scale_x: Option<f32>,
     pub scale_y: Option<f32>,
-    /// If set to None, will be set to 300.0 for images
+    /// If set to None, will be set to the resolution of the image (`RawImage::dpi`)
+    /// or 300.0
     pub dpi: Option<f32>,
 }
 
 impl XObjectTransform {
+    /// Returns the transform that places an XObject of the size `wh` (the pixel size
+    /// of an image, see `XObject::get_width_height`) into the `rect`. The natural size
+    /// of the XObject (used for `XObjectFit::None`) is the size at `dpi`, 300 DPI if
+    /// not set. Doesn't clip the XObject, see `XObject::get_placement_ops`.
+    pub fn fit_into(
+        wh: (Px, Px),
+        dpi: Option<f32>,
+        rect: &Rect,
+        fit: XObjectFit,
+        align: XObjectAlign,
+    ) -> Self {
+        let dpi = dpi.unwrap_or(300.0);
+        let natural = (wh.0.into_pt(dpi), wh.1.into_pt(dpi));
+        let (width, height) = fit.get_size(natural, rect);
+        let (x, y) = align.get_position((width, height), rect);
+        // at 72 DPI, 1px = 1pt
+        Self {
+            translate_x: Some(x),
+            translate_y: Some(y),
+            rotate: None,
+            scale_x: Some(width.0 / (wh.0 .0 as f32).max(1.0)),
+            scale_y: Some(height.0 / (wh.1 .0 as f32).max(1.0)),
+            dpi: Some(72.0),
+        }
+    }
+
     pub fn get_ctms(&self, wh: Option<(Px, Px)>) -> Vec<CurTransMat> {
         let mut transforms = Vec::new();
         let dpi = self.dpi.unwrap_or(300.0);
@@ -395,3 +656,158 @@ pub struct XObjectRotation {
     pub rotation_center_x: Px,
     pub rotation_center_y: Px,
 }
+
+/// How an XObject is sized when placing it into a rectangle,
+/// see `PdfDocument::place_xobject`
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum XObjectFit {
+    /// Scales the XObject to the largest size that fits into the rectangle,
+    /// preserving its aspect ratio
+    #[default]
+    Contain,
+    /// Scales the XObject to the smallest size that covers the rectangle, preserving
+    /// its aspect ratio. The parts outside of the rectangle are clipped.
+    Cover,
+    /// Scales the XObject to the size of the rectangle, ignoring its aspect ratio
+    Stretch,
+    /// Keeps the natural size of the XObject, the parts outside of the rectangle
+    /// are clipped
+    None,
+}
+
+impl XObjectFit {
+    /// Returns the size of an XObject with the `natural` size inside the `rect`
+    pub fn get_size(&self, natural: (Pt, Pt), rect: &Rect) -> (Pt, Pt) {
+        let (w, h) = natural;
+        if w.0 <= 0.0 || h.0 <= 0.0 {
+            return (rect.width, rect.height);
+        }
+        let (sx, sy) = (rect.width.0 / w.0, rect.height.0 / h.0);
+        match self {
+            XObjectFit::Contain => (w * sx.min(sy), h * sx.min(sy)),
+            XObjectFit::Cover => (w * sx.max(sy), h * sx.max(sy)),
+            XObjectFit::Stretch => (rect.width, rect.height),
+            XObjectFit::None => natural,
+        }
+    }
+
+    /// Returns whether the XObject can extend beyond the rectangle
+    pub fn needs_clipping(&self) -> bool {
+        matches!(self, XObjectFit::Cover | XObjectFit::None)
+    }
+}
+
+/// Position of an XObject inside a rectangle of a different size,
+/// see `PdfDocument::place_xobject`
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum XObjectAlign {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl XObjectAlign {
+    /// Returns the lower left corner of an XObject of the given `size` inside the `rect`
+    pub fn get_position(&self, size: (Pt, Pt), rect: &Rect) -> (Pt, Pt) {
+        use self::XObjectAlign::*;
+        // fraction of the free space left of / below the XObject
+        let fx = match self {
+            TopLeft | Left | BottomLeft => 0.0,
+            Top | Center | Bottom => 0.5,
+            TopRight | Right | BottomRight => 1.0,
+        };
+        let fy = match self {
+            BottomLeft | Bottom | BottomRight => 0.0,
+            Left | Center | Right => 0.5,
+            TopLeft | Top | TopRight => 1.0,
+        };
+        (
+            rect.x + (rect.width - size.0) * fx,
+            rect.y + (rect.height - size.1) * fy,
+        )
+    }
+}
+
+#[test]
+fn test_form_type() {
+    let bbox = Rect::from_wh(Pt(10.0), Pt(10.0));
+    let form = FormXObject::transparency_group(Vec::new(), bbox, GroupXObject::transparency());
+    let mut doc = lopdf::Document::new();
+    let stream = form_xobject_to_stream(&form, &mut doc);
+    // /FormType is an integer, not a name
+    assert_eq!(
+        stream.dict.get(b"FormType").unwrap(),
+        &lopdf::Object::Integer(1)
+    );
+    assert!(stream.dict.get(b"Group").is_ok());
+}
+
+#[test]
+fn test_from_ops_resources() {
+    use crate::{PatternId, ShadingId};
+
+    let doc = PdfDocument::new("test");
+    let bbox = Rect::from_wh(Pt(10.0), Pt(10.0));
+
+    let ops = vec![Op::SetFillColor {
+        col: Color::Shading(ShadingId("S1".to_string())),
+    }];
+    let err = FormXObject::from_ops(ops, bbox.clone(), &doc).unwrap_err();
+    assert!(err.contains("shading S1"));
+
+    let ops = vec![Op::SetOutlineColor {
+        col: Color::UncoloredPattern {
+            pattern: PatternId("P1".to_string()),
+            color: Box::new(Color::Greyscale(crate::Greyscale::new(0.5, None))),
+        },
+    }];
+    let err = FormXObject::from_ops(ops, bbox.clone(), &doc).unwrap_err();
+    assert!(err.contains("pattern P1"));
+
+    let ops = vec![Op::SetFillColor {
+        col: Color::Greyscale(crate::Greyscale::new(0.5, None)),
+    }];
+    assert!(FormXObject::from_ops(ops, bbox, &doc).is_ok());
+}
+
+#[test]
+fn test_fit_into() {
+    let rect = Rect {
+        x: Pt(10.0),
+        y: Pt(20.0),
+        width: Pt(100.0),
+        height: Pt(100.0),
+    };
+    let wh = (Px(200), Px(100));
+    let placed = |fit, align| {
+        let transform = XObjectTransform::fit_into(wh, None, &rect, fit, align);
+        let mut m = CurTransMat::Identity.as_array();
+        for ctm in transform.get_ctms(Some(wh)) {
+            m = CurTransMat::combine_matrix(m, ctm.as_array());
+        }
+        // width, height, x, y of the unit square on the page
+        [m[0], m[3], m[4], m[5]].map(|v| (v * 100.0).round() / 100.0)
+    };
+
+    let contain = placed(XObjectFit::Contain, XObjectAlign::Center);
+    assert_eq!(contain, [100.0, 50.0, 10.0, 45.0]);
+    let contain = placed(XObjectFit::Contain, XObjectAlign::TopLeft);
+    assert_eq!(contain, [100.0, 50.0, 10.0, 70.0]);
+    let cover = placed(XObjectFit::Cover, XObjectAlign::Center);
+    assert_eq!(cover, [200.0, 100.0, -40.0, 20.0]);
+    let stretch = placed(XObjectFit::Stretch, XObjectAlign::Center);
+    assert_eq!(stretch, [100.0, 100.0, 10.0, 20.0]);
+}